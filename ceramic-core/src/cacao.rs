@@ -0,0 +1,592 @@
+//! Validates CACAO capabilities that delegate authority from a blockchain account to a session key.
+//!
+//! Sign-In With Ethereum (EIP-4361) and Sign-In With Solana messages are supported.
+//! See https://chainagnostic.org/CAIPs/caip-74
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use libipld::Ipld;
+use multibase::Base;
+use sha3::{Digest, Keccak256};
+
+use crate::StreamId;
+
+// Signature types of CACAOs.
+const EIP191: &str = "eip191";
+const SOLANA_ED25519: &str = "solana:ed25519";
+
+// Allowed difference between the clocks of the signer and the verifier, same as js-ceramic.
+const CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// A chain agnostic capability object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cacao {
+    /// Type of the capability, i.e. eip4361 or caip122
+    pub header_type: String,
+    /// Claims of the capability
+    pub payload: CacaoPayload,
+    /// Signature of the issuer over the message formatted from the payload
+    pub signature: CacaoSignature,
+}
+
+/// The claims of a CACAO, mirroring the fields of a SIWE message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacaoPayload {
+    /// Domain requesting the signing
+    pub domain: String,
+    /// did:pkh DID of the account that signed the capability
+    pub iss: String,
+    /// DID the capability is delegated to, i.e. the session key
+    pub aud: String,
+    /// Version of the message format
+    pub version: String,
+    /// Random value preventing replay attacks
+    pub nonce: String,
+    /// Time the capability was issued at, RFC 3339
+    pub iat: String,
+    /// Time the capability becomes valid, RFC 3339
+    pub nbf: Option<String>,
+    /// Time the capability expires, RFC 3339
+    pub exp: Option<String>,
+    /// Human readable statement the account agreed to
+    pub statement: Option<String>,
+    /// Identifier of the request
+    pub request_id: Option<String>,
+    /// Resources the capability grants access to, i.e. ceramic://*
+    pub resources: Vec<String>,
+}
+
+/// The signature of a CACAO.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacaoSignature {
+    /// Type of the signature, i.e. eip191 or solana:ed25519
+    pub signature_type: String,
+    /// Raw signature bytes
+    pub signature: Vec<u8>,
+}
+
+impl TryFrom<&Ipld> for Cacao {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let cacao = map(ipld, "CACAO")?;
+        let header = map(field(cacao, "h")?, "CACAO header")?;
+        let payload = map(field(cacao, "p")?, "CACAO payload")?;
+        let signature = map(field(cacao, "s")?, "CACAO signature")?;
+        Ok(Self {
+            header_type: string(header, "t")?,
+            payload: CacaoPayload {
+                domain: string(payload, "domain")?,
+                iss: string(payload, "iss")?,
+                aud: string(payload, "aud")?,
+                version: string(payload, "version")?,
+                nonce: string(payload, "nonce")?,
+                iat: string(payload, "iat")?,
+                nbf: opt_string(payload, "nbf")?,
+                exp: opt_string(payload, "exp")?,
+                statement: opt_string(payload, "statement")?,
+                request_id: opt_string(payload, "requestId")?,
+                resources: match payload.get("resources") {
+                    Some(Ipld::List(resources)) => resources
+                        .iter()
+                        .map(|resource| match resource {
+                            Ipld::String(resource) => Ok(resource.clone()),
+                            _ => Err(anyhow!("resources must be a list of strings")),
+                        })
+                        .collect::<Result<Vec<String>>>()?,
+                    Some(Ipld::Null) | None => Vec::new(),
+                    Some(_) => return Err(anyhow!("resources must be a list of strings")),
+                },
+            },
+            signature: CacaoSignature {
+                signature_type: string(signature, "t")?,
+                signature: match field(signature, "s")? {
+                    Ipld::Bytes(bytes) => bytes.clone(),
+                    _ => return Err(anyhow!("s must be bytes")),
+                },
+            },
+        })
+    }
+}
+
+impl Cacao {
+    /// Format the message signed by the issuer, i.e. the SIWE or SIWS message.
+    pub fn message(&self) -> Result<String> {
+        let (namespace, chain_id, address) = self.issuer()?;
+        let chain_name = match namespace {
+            "eip155" => "Ethereum",
+            "solana" => "Solana",
+            _ => return Err(anyhow!("unsupported issuer chain {}", namespace)),
+        };
+        let payload = &self.payload;
+        let mut suffix = vec![
+            format!("URI: {}", payload.aud),
+            format!("Version: {}", payload.version),
+            format!("Chain ID: {}", chain_id),
+            format!("Nonce: {}", payload.nonce),
+            format!("Issued At: {}", payload.iat),
+        ];
+        if let Some(exp) = &payload.exp {
+            suffix.push(format!("Expiration Time: {}", exp));
+        }
+        if let Some(nbf) = &payload.nbf {
+            suffix.push(format!("Not Before: {}", nbf));
+        }
+        if let Some(request_id) = &payload.request_id {
+            suffix.push(format!("Request ID: {}", request_id));
+        }
+        if !payload.resources.is_empty() {
+            suffix.push("Resources:".to_string());
+            suffix.extend(payload.resources.iter().map(|r| format!("- {}", r)));
+        }
+        // Match the formatting of the siwe libraries exactly,
+        // including the empty line left behind when there is no statement.
+        let prefix = format!(
+            "{} wants you to sign in with your {} account:\n{}\n\n{}",
+            payload.domain,
+            chain_name,
+            address,
+            payload
+                .statement
+                .as_ref()
+                .map(|statement| format!("{}\n", statement))
+                .unwrap_or_default(),
+        );
+        Ok(format!("{}\n{}", prefix, suffix.join("\n")))
+    }
+
+    /// Verify the issuer signed the message of the CACAO.
+    pub fn verify_signature(&self) -> Result<()> {
+        let (_, _, address) = self.issuer()?;
+        let message = self.message()?;
+        let signature = &self.signature.signature;
+        match self.signature.signature_type.as_str() {
+            EIP191 => {
+                let recovered = recover_eip191(&message, signature)?;
+                if !recovered.eq_ignore_ascii_case(address) {
+                    return Err(anyhow!(
+                        "CACAO signature was made by {} not the issuer {}",
+                        recovered,
+                        address
+                    ));
+                }
+            }
+            SOLANA_ED25519 => {
+                use ed25519_dalek::Verifier;
+                let key = ed25519_dalek::VerifyingKey::from_bytes(
+                    Base::Base58Btc
+                        .decode(address)?
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid Solana address {}", address))?,
+                )?;
+                let signature = ed25519_dalek::Signature::from_slice(signature)?;
+                key.verify(message.as_bytes(), &signature)
+                    .map_err(|_| anyhow!("CACAO signature is not valid"))?;
+            }
+            t => return Err(anyhow!("unsupported CACAO signature type {}", t)),
+        }
+        Ok(())
+    }
+
+    /// Verify the CACAO delegates the capability to write to the stream to the signer at the given time.
+    ///
+    /// The expiry and not before times are not checked when the time is not known.
+    pub fn verify(
+        &self,
+        signer: &str,
+        stream_id: &StreamId,
+        model: Option<&StreamId>,
+        at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        if self.payload.aud != signer {
+            return Err(anyhow!(
+                "CACAO audience {} does not match the signer {}",
+                self.payload.aud,
+                signer
+            ));
+        }
+        if let Some(at) = at {
+            let skew = Duration::seconds(CLOCK_SKEW_SECS);
+            if let Some(exp) = &self.payload.exp {
+                if parse_time(exp)? + skew < at {
+                    return Err(anyhow!("CACAO expired at {}", exp));
+                }
+            }
+            if let Some(nbf) = &self.payload.nbf {
+                if parse_time(nbf)? > at + skew {
+                    return Err(anyhow!("CACAO is not valid before {}", nbf));
+                }
+            }
+        }
+        let mut allowed = vec![
+            "ceramic://*".to_string(),
+            format!("ceramic://{}", stream_id),
+        ];
+        if let Some(model) = model {
+            allowed.push(format!("ceramic://*?model={}", model));
+        }
+        if !self
+            .payload
+            .resources
+            .iter()
+            .any(|resource| allowed.contains(resource))
+        {
+            return Err(anyhow!(
+                "CACAO does not grant access to stream {}",
+                stream_id
+            ));
+        }
+        self.verify_signature()
+    }
+
+    // Split the did:pkh issuer into its chain namespace, chain id and account address.
+    fn issuer(&self) -> Result<(&str, &str, &str)> {
+        let iss = &self.payload.iss;
+        match iss.splitn(5, ':').collect::<Vec<&str>>().as_slice() {
+            ["did", "pkh", namespace, chain_id, address] => Ok((namespace, chain_id, address)),
+            _ => Err(anyhow!("CACAO issuer must be a did:pkh: {}", iss)),
+        }
+    }
+}
+
+// Recover the Ethereum address that signed the message according to EIP-191.
+fn recover_eip191(message: &str, signature: &[u8]) -> Result<String> {
+    if signature.len() != 65 {
+        return Err(anyhow!(
+            "invalid eip191 signature length {}",
+            signature.len()
+        ));
+    }
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+    hasher.update(message);
+    let digest = hasher.finalize();
+
+    // The recovery id is offset by 27 for legacy reasons
+    let v = signature[64];
+    let recovery_id = k256::ecdsa::RecoveryId::try_from(if v >= 27 { v - 27 } else { v })?;
+    let key = k256::ecdsa::VerifyingKey::recover_from_prehash(
+        &digest,
+        &k256::ecdsa::Signature::from_slice(&signature[..64])?,
+        recovery_id,
+    )?;
+    // The address is the last 20 bytes of the hash of the uncompressed public key
+    let key = key.to_encoded_point(false);
+    let hash = Keccak256::digest(&key.as_bytes()[1..]);
+    Ok(format!("0x{}", Base::Base16Lower.encode(&hash[12..])))
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc))
+}
+
+fn map<'a>(ipld: &'a Ipld, name: &str) -> Result<&'a BTreeMap<String, Ipld>> {
+    match ipld {
+        Ipld::Map(map) => Ok(map),
+        _ => Err(anyhow!("{} must be a map", name)),
+    }
+}
+
+fn field<'a>(map: &'a BTreeMap<String, Ipld>, key: &str) -> Result<&'a Ipld> {
+    map.get(key).ok_or_else(|| anyhow!("missing field {}", key))
+}
+
+fn string(map: &BTreeMap<String, Ipld>, key: &str) -> Result<String> {
+    opt_string(map, key)?.ok_or_else(|| anyhow!("missing field {}", key))
+}
+
+fn opt_string(map: &BTreeMap<String, Ipld>, key: &str) -> Result<Option<String>> {
+    match map.get(key) {
+        Some(Ipld::String(s)) => Ok(Some(s.clone())),
+        Some(Ipld::Null) | None => Ok(None),
+        Some(_) => Err(anyhow!("{} must be a string", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use libipld::ipld;
+
+    use super::*;
+
+    // Capabilities signed by accounts generated for these tests,
+    // delegating to the session key did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK.
+    const SESSION: &str = "did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK";
+    const MODEL: &str = "kjzl6hvfrbw6c74hccl7t8237c7txblexlxhns30kyrp687rk7n4nw0vl0wx130";
+    const STREAM: &str = "kjzl6kcym7w8y6zs5iabwwquzmodg17b8ob7sphtva2x0arl1n2srgth4twiym4";
+    const SIWE_SIGNATURE: &str = "ec1e817e785202e6f76fd1fb01ea8e5747bfa992ab215be07126a9143e504f310db230cdb94d8bc7348772db2454ae539ec59eb326ea2920e24bc4ab9e09a92a1c";
+    const SIWS_SIGNATURE: &str = "ecaa7736ebefb6b39c73a9d34bd26e7231dc74f02a2cadd06a4b6291d6ff6f72c1f3ad66912c600d7ec790685acf31a752323ee9b796a086a94f80b989c0e802";
+
+    fn siwe() -> Cacao {
+        Cacao {
+            header_type: "eip4361".to_string(),
+            payload: CacaoPayload {
+                domain: "service.org".to_string(),
+                iss: "did:pkh:eip155:1:0x2Fd866b7082220c0bB7C104147F732E83062BF97".to_string(),
+                aud: SESSION.to_string(),
+                version: "1".to_string(),
+                nonce: "328917".to_string(),
+                iat: "2022-03-17T12:45:13.610Z".to_string(),
+                exp: Some("2022-03-24T12:45:13.610Z".to_string()),
+                statement: Some(
+                    "I accept the ServiceOrg Terms of Service: https://service.org/tos".to_string(),
+                ),
+                resources: vec![format!("ceramic://*?model={}", MODEL)],
+                ..Default::default()
+            },
+            signature: CacaoSignature {
+                signature_type: EIP191.to_string(),
+                signature: hex::decode(SIWE_SIGNATURE).unwrap(),
+            },
+        }
+    }
+
+    fn siws() -> Cacao {
+        Cacao {
+            header_type: "caip122".to_string(),
+            payload: CacaoPayload {
+                domain: "service.org".to_string(),
+                iss: "did:pkh:solana:4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ:FieM7so8cEQ9dbzjGABvZ1fb2Ua1nw1VCV5gYQ5LBDUM".to_string(),
+                aud: SESSION.to_string(),
+                version: "1".to_string(),
+                nonce: "740325".to_string(),
+                iat: "2022-03-17T12:45:13.610Z".to_string(),
+                resources: vec!["ceramic://*".to_string()],
+                ..Default::default()
+            },
+            signature: CacaoSignature {
+                signature_type: SOLANA_ED25519.to_string(),
+                signature: hex::decode(SIWS_SIGNATURE).unwrap(),
+            },
+        }
+    }
+
+    fn time(s: &str) -> Option<DateTime<Utc>> {
+        Some(parse_time(s).unwrap())
+    }
+
+    fn stream() -> StreamId {
+        StreamId::from_str(STREAM).unwrap()
+    }
+
+    fn model() -> StreamId {
+        StreamId::from_str(MODEL).unwrap()
+    }
+
+    #[test]
+    fn test_siwe_message() {
+        assert_eq!(
+            "service.org wants you to sign in with your Ethereum account:
+0x2Fd866b7082220c0bB7C104147F732E83062BF97
+
+I accept the ServiceOrg Terms of Service: https://service.org/tos
+
+URI: did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK
+Version: 1
+Chain ID: 1
+Nonce: 328917
+Issued At: 2022-03-17T12:45:13.610Z
+Expiration Time: 2022-03-24T12:45:13.610Z
+Resources:
+- ceramic://*?model=kjzl6hvfrbw6c74hccl7t8237c7txblexlxhns30kyrp687rk7n4nw0vl0wx130",
+            siwe().message().unwrap()
+        );
+    }
+
+    // The example message of EIP-4361, https://eips.ethereum.org/EIPS/eip-4361#example-message
+    #[test]
+    fn test_eip4361_example_message() {
+        let cacao = Cacao {
+            header_type: "eip4361".to_string(),
+            payload: CacaoPayload {
+                domain: "service.invalid".to_string(),
+                iss: "did:pkh:eip155:1:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".to_string(),
+                aud: "https://service.invalid/login".to_string(),
+                version: "1".to_string(),
+                nonce: "32891756".to_string(),
+                iat: "2021-09-30T16:25:24Z".to_string(),
+                statement: Some(
+                    "I accept the ServiceOrg Terms of Service: https://service.invalid/tos"
+                        .to_string(),
+                ),
+                resources: vec![
+                    "ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/"
+                        .to_string(),
+                    "https://example.com/my-web2-claim.json".to_string(),
+                ],
+                ..Default::default()
+            },
+            signature: CacaoSignature {
+                signature_type: EIP191.to_string(),
+                signature: Vec::new(),
+            },
+        };
+        assert_eq!(
+            "service.invalid wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.invalid/tos
+
+URI: https://service.invalid/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json",
+            cacao.message().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_siws_message_without_statement() {
+        assert_eq!(
+            "service.org wants you to sign in with your Solana account:
+FieM7so8cEQ9dbzjGABvZ1fb2Ua1nw1VCV5gYQ5LBDUM
+
+
+URI: did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK
+Version: 1
+Chain ID: 4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ
+Nonce: 740325
+Issued At: 2022-03-17T12:45:13.610Z
+Resources:
+- ceramic://*",
+            siws().message().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_siwe() {
+        siwe()
+            .verify(
+                SESSION,
+                &stream(),
+                Some(&model()),
+                time("2022-03-18T00:00:00Z"),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_verify_siws() {
+        siws()
+            .verify(SESSION, &stream(), None, time("2022-03-18T00:00:00Z"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tampered() {
+        let mut cacao = siwe();
+        cacao.payload.resources = vec!["ceramic://*".to_string()];
+        let err = cacao.verify_signature().unwrap_err();
+        assert!(
+            err.to_string().starts_with("CACAO signature was made by"),
+            "{}",
+            err
+        );
+
+        let mut cacao = siws();
+        cacao.payload.nonce = "000000".to_string();
+        let err = cacao.verify_signature().unwrap_err();
+        assert_eq!("CACAO signature is not valid", err.to_string());
+    }
+
+    #[test]
+    fn test_time_checks() {
+        let at = |s| siwe().verify(SESSION, &stream(), Some(&model()), time(s));
+        // Within the allowed clock skew
+        at("2022-03-24T12:50:00Z").unwrap();
+        assert_eq!(
+            "CACAO expired at 2022-03-24T12:45:13.610Z",
+            at("2022-03-24T12:51:00Z").unwrap_err().to_string()
+        );
+        // Unknown time
+        siwe()
+            .verify(SESSION, &stream(), Some(&model()), None)
+            .unwrap();
+
+        let mut cacao = siws();
+        cacao.payload.nbf = Some("2022-03-20T00:00:00Z".to_string());
+        let err = cacao
+            .verify(SESSION, &stream(), None, time("2022-03-18T00:00:00Z"))
+            .unwrap_err();
+        assert_eq!(
+            "CACAO is not valid before 2022-03-20T00:00:00Z",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_resources() {
+        let at = time("2022-03-18T00:00:00Z");
+        // Missing the model of the stream
+        let err = siwe().verify(SESSION, &stream(), None, at).unwrap_err();
+        assert_eq!(
+            format!("CACAO does not grant access to stream {}", STREAM),
+            err.to_string()
+        );
+        // A different model
+        let err = siwe()
+            .verify(SESSION, &stream(), Some(&stream()), at)
+            .unwrap_err();
+        assert_eq!(
+            format!("CACAO does not grant access to stream {}", STREAM),
+            err.to_string()
+        );
+        // Access to the specific stream, the signature no longer matches
+        let mut cacao = siwe();
+        cacao.payload.resources = vec![format!("ceramic://{}", STREAM)];
+        assert!(cacao
+            .verify(SESSION, &stream(), None, at)
+            .unwrap_err()
+            .to_string()
+            .starts_with("CACAO signature was made by"));
+    }
+
+    #[test]
+    fn test_audience() {
+        let other = "did:key:z6MkgSV3tAuw7gUWqKCUY7ae6uWNxqYgdwPhUJbJhF9EFXm9";
+        let err = siwe()
+            .verify(
+                other,
+                &stream(),
+                Some(&model()),
+                time("2022-03-18T00:00:00Z"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            format!(
+                "CACAO audience {} does not match the signer {}",
+                SESSION, other
+            ),
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let ipld = ipld!({
+            "h": {"t": "caip122"},
+            "p": {
+                "domain": "service.org",
+                "iss": siws().payload.iss,
+                "aud": SESSION,
+                "version": "1",
+                "nonce": "740325",
+                "iat": "2022-03-17T12:45:13.610Z",
+                "resources": ["ceramic://*"],
+            },
+            "s": {
+                "t": SOLANA_ED25519,
+                "s": Ipld::Bytes(hex::decode(SIWS_SIGNATURE).unwrap()),
+            },
+        });
+        assert_eq!(siws(), Cacao::try_from(&ipld).unwrap());
+    }
+}
@@ -0,0 +1,407 @@
+//! Provides typed representations of the events (commits) that make up a Ceramic stream.
+//!
+//! See https://cips.ceramic.network/CIPs/cip-69
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use libipld::{cid::Cid, Ipld};
+use multibase::Base;
+
+use crate::StreamId;
+
+/// An event of a Ceramic stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// An unsigned genesis commit.
+    Genesis(Genesis),
+    /// A genesis or data commit signed by a controller of the stream.
+    Signed(Signed),
+    /// A time event anchoring a commit of the stream, along with its proof.
+    Anchor(Anchor, AnchorProof),
+}
+
+impl Event {
+    /// Report the CID of the genesis commit of the stream, None for genesis commits.
+    pub fn id(&self) -> Option<Cid> {
+        match self {
+            Event::Genesis(_) => None,
+            Event::Signed(signed) => match &signed.payload {
+                Payload::Genesis(_) => None,
+                Payload::Data(data) => Some(data.id),
+            },
+            Event::Anchor(anchor, _) => Some(anchor.id),
+        }
+    }
+    /// Report the CID of the previous commit, None for genesis commits.
+    pub fn prev(&self) -> Option<Cid> {
+        match self {
+            Event::Genesis(_) => None,
+            Event::Signed(signed) => match &signed.payload {
+                Payload::Genesis(_) => None,
+                Payload::Data(data) => Some(data.prev),
+            },
+            Event::Anchor(anchor, _) => Some(anchor.prev),
+        }
+    }
+}
+
+/// The header of a genesis commit.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GenesisHeader {
+    /// DIDs allowed to write to the stream
+    pub controllers: Vec<String>,
+    /// Family of the stream, used by TileDocument streams
+    pub family: Option<String>,
+    /// Schema of the stream data, used by TileDocument streams
+    pub schema: Option<String>,
+    /// Tags of the stream, used by TileDocument streams
+    pub tags: Vec<String>,
+    /// Separator key identifying how the stream is grouped, i.e. "model"
+    pub sep: Option<String>,
+    /// Model the stream is an instance of
+    pub model: Option<StreamId>,
+    /// Value that makes the genesis commit unique
+    pub unique: Option<Ipld>,
+}
+
+/// A genesis commit creating a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Genesis {
+    /// Metadata of the stream
+    pub header: GenesisHeader,
+    /// Initial content of the stream
+    pub data: Option<Ipld>,
+}
+
+/// A data commit updating the content of a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Data {
+    /// CID of the genesis commit of the stream
+    pub id: Cid,
+    /// CID of the previous commit
+    pub prev: Cid,
+    /// Changes to the metadata of the stream
+    pub header: BTreeMap<String, Ipld>,
+    /// JSON patch operations applied to the content of the stream
+    pub data: Ipld,
+}
+
+/// The payload of a signed commit.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload {
+    /// A signed genesis commit
+    Genesis(Genesis),
+    /// A signed data commit
+    Data(Data),
+}
+
+/// A signature of a JWS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JwsSignature {
+    /// Protected header of the signature, JSON encoded
+    pub protected: Vec<u8>,
+    /// Signature over the protected header and payload
+    pub signature: Vec<u8>,
+}
+
+/// A dag-jose JWS whose payload is the CID of the signed block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jws {
+    /// CID of the signed payload block
+    pub link: Cid,
+    /// Raw payload of the JWS, the bytes of the link
+    pub payload: Vec<u8>,
+    /// Signatures over the payload
+    pub signatures: Vec<JwsSignature>,
+}
+
+/// A signed commit, the JWS envelope along with its decoded payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signed {
+    /// JWS envelope of the commit
+    pub jws: Jws,
+    /// Payload of the commit
+    pub payload: Payload,
+}
+
+/// An anchor commit proving the existence of a commit at a point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor {
+    /// CID of the genesis commit of the stream
+    pub id: Cid,
+    /// CID of the anchored commit
+    pub prev: Cid,
+    /// CID of the anchor proof
+    pub proof: Cid,
+    /// Path from the root of the merkle tree to the anchored commit
+    pub path: String,
+}
+
+/// The proof that a merkle root was recorded on a blockchain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnchorProof {
+    /// CAIP-2 identifier of the blockchain, i.e. eip155:1
+    pub chain_id: String,
+    /// Root of the merkle tree
+    pub root: Cid,
+    /// CID of the transaction that recorded the root
+    pub tx_hash: Cid,
+    /// Function signature of the transaction, i.e. f(bytes32)
+    pub tx_type: Option<String>,
+}
+
+impl TryFrom<&Ipld> for Genesis {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let genesis = map(ipld, "genesis commit")?;
+        let header = map(field(genesis, "header")?, "genesis header")?;
+        Ok(Self {
+            header: GenesisHeader {
+                controllers: strings(header, "controllers")?,
+                family: opt_string(header, "family")?,
+                schema: opt_string(header, "schema")?,
+                tags: strings(header, "tags")?,
+                sep: opt_string(header, "sep")?,
+                model: header
+                    .get("model")
+                    .map(|model| StreamId::try_from(bytes(model, "model")?.as_slice()))
+                    .transpose()?,
+                unique: header.get("unique").cloned(),
+            },
+            data: genesis.get("data").cloned(),
+        })
+    }
+}
+
+impl TryFrom<&Ipld> for Data {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let data = map(ipld, "data commit")?;
+        Ok(Self {
+            id: link(data, "id")?,
+            prev: link(data, "prev")?,
+            header: match data.get("header") {
+                Some(header) => map(header, "data header")?.clone(),
+                None => BTreeMap::new(),
+            },
+            data: field(data, "data")?.clone(),
+        })
+    }
+}
+
+impl TryFrom<&Ipld> for Payload {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        // Only data commits link to a previous commit
+        if map(ipld, "payload")?.contains_key("prev") {
+            Ok(Payload::Data(Data::try_from(ipld)?))
+        } else {
+            Ok(Payload::Genesis(Genesis::try_from(ipld)?))
+        }
+    }
+}
+
+impl TryFrom<&Ipld> for Jws {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let jws = map(ipld, "JWS")?;
+        let payload = bytes(field(jws, "payload")?, "payload")?;
+        // The link is only present once the envelope has been decoded by the dag-jose codec.
+        let link = match jws.get("link") {
+            Some(Ipld::Link(link)) => *link,
+            Some(_) => return Err(anyhow!("link must be a link")),
+            None => Cid::try_from(payload.as_slice())?,
+        };
+        let signatures = match field(jws, "signatures")? {
+            Ipld::List(signatures) => signatures
+                .iter()
+                .map(|signature| {
+                    let signature = map(signature, "signature")?;
+                    Ok(JwsSignature {
+                        protected: bytes(field(signature, "protected")?, "protected")?,
+                        signature: bytes(field(signature, "signature")?, "signature")?,
+                    })
+                })
+                .collect::<Result<Vec<JwsSignature>>>()?,
+            _ => return Err(anyhow!("signatures must be a list")),
+        };
+        Ok(Self {
+            link,
+            payload,
+            signatures,
+        })
+    }
+}
+
+impl TryFrom<&Ipld> for Anchor {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let anchor = map(ipld, "anchor commit")?;
+        Ok(Self {
+            id: link(anchor, "id")?,
+            prev: link(anchor, "prev")?,
+            proof: link(anchor, "proof")?,
+            path: string(anchor, "path")?,
+        })
+    }
+}
+
+impl TryFrom<&Ipld> for AnchorProof {
+    type Error = anyhow::Error;
+
+    fn try_from(ipld: &Ipld) -> Result<Self> {
+        let proof = map(ipld, "anchor proof")?;
+        Ok(Self {
+            chain_id: string(proof, "chainId")?,
+            root: link(proof, "root")?,
+            tx_hash: link(proof, "txHash")?,
+            tx_type: opt_string(proof, "txType")?,
+        })
+    }
+}
+
+fn map<'a>(ipld: &'a Ipld, name: &str) -> Result<&'a BTreeMap<String, Ipld>> {
+    match ipld {
+        Ipld::Map(map) => Ok(map),
+        _ => Err(anyhow!("{} must be a map", name)),
+    }
+}
+
+fn field<'a>(map: &'a BTreeMap<String, Ipld>, key: &str) -> Result<&'a Ipld> {
+    map.get(key).ok_or_else(|| anyhow!("missing field {}", key))
+}
+
+fn link(map: &BTreeMap<String, Ipld>, key: &str) -> Result<Cid> {
+    match field(map, key)? {
+        Ipld::Link(cid) => Ok(*cid),
+        _ => Err(anyhow!("{} must be a link", key)),
+    }
+}
+
+fn string(map: &BTreeMap<String, Ipld>, key: &str) -> Result<String> {
+    opt_string(map, key)?.ok_or_else(|| anyhow!("missing field {}", key))
+}
+
+fn opt_string(map: &BTreeMap<String, Ipld>, key: &str) -> Result<Option<String>> {
+    match map.get(key) {
+        Some(Ipld::String(s)) => Ok(Some(s.clone())),
+        Some(Ipld::Null) | None => Ok(None),
+        Some(_) => Err(anyhow!("{} must be a string", key)),
+    }
+}
+
+fn strings(map: &BTreeMap<String, Ipld>, key: &str) -> Result<Vec<String>> {
+    match map.get(key) {
+        Some(Ipld::List(list)) => list
+            .iter()
+            .map(|item| match item {
+                Ipld::String(s) => Ok(s.clone()),
+                _ => Err(anyhow!("{} must be a list of strings", key)),
+            })
+            .collect(),
+        Some(Ipld::Null) | None => Ok(Vec::new()),
+        Some(_) => Err(anyhow!("{} must be a list of strings", key)),
+    }
+}
+
+// Bytes are stored as raw bytes in blocks, however the dag-jose codec
+// represents them as unpadded base64url strings.
+fn bytes(ipld: &Ipld, name: &str) -> Result<Vec<u8>> {
+    match ipld {
+        Ipld::Bytes(bytes) => Ok(bytes.clone()),
+        Ipld::String(s) => Ok(Base::Base64Url.decode(s)?),
+        _ => Err(anyhow!("{} must be bytes", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use libipld::ipld;
+
+    use super::*;
+
+    const GENESIS: &str = "bagcqcerakszw2vsovxznyp5gfnpdj4cqm2xiv76yd24wkjewhhykovorwo6a";
+    const COMMIT: &str = "bagjqcgzaday6dzalvmy5ady2m5a5legq5zrbsnlxfc2bfxej532ds7htpova";
+
+    #[test]
+    fn test_jws_bytes_and_strings() {
+        let link = Cid::from_str(GENESIS).unwrap();
+        let expected = Jws {
+            link,
+            payload: link.to_bytes(),
+            signatures: vec![JwsSignature {
+                protected: br#"{"alg":"EdDSA"}"#.to_vec(),
+                signature: vec![1, 2, 3],
+            }],
+        };
+        // As stored in the block
+        let stored = ipld!({
+            "payload": Ipld::Bytes(link.to_bytes()),
+            "signatures": [{
+                "protected": Ipld::Bytes(br#"{"alg":"EdDSA"}"#.to_vec()),
+                "signature": Ipld::Bytes(vec![1, 2, 3]),
+            }],
+        });
+        assert_eq!(expected, Jws::try_from(&stored).unwrap());
+        // As decoded by the dag-jose codec
+        let decoded = ipld!({
+            "link": link,
+            "payload": Base::Base64Url.encode(link.to_bytes()),
+            "signatures": [{
+                "protected": "eyJhbGciOiJFZERTQSJ9",
+                "signature": "AQID",
+            }],
+        });
+        assert_eq!(expected, Jws::try_from(&decoded).unwrap());
+    }
+
+    #[test]
+    fn test_payload_kinds() {
+        let id = Cid::from_str(GENESIS).unwrap();
+        let prev = Cid::from_str(COMMIT).unwrap();
+        let genesis = ipld!({
+            "header": {"controllers": ["did:key:z6MkgSV3tAuw7gUWqKCUY7ae6uWNxqYgdwPhUJbJhF9EFXm9"]},
+        });
+        assert_eq!(
+            Payload::Genesis(Genesis {
+                header: GenesisHeader {
+                    controllers: vec![
+                        "did:key:z6MkgSV3tAuw7gUWqKCUY7ae6uWNxqYgdwPhUJbJhF9EFXm9".to_string()
+                    ],
+                    ..Default::default()
+                },
+                data: None,
+            }),
+            Payload::try_from(&genesis).unwrap()
+        );
+        let data = ipld!({
+            "id": id,
+            "prev": prev,
+            "data": [{"op": "add", "path": "/a", "value": 1}],
+        });
+        assert_eq!(
+            Payload::Data(Data {
+                id,
+                prev,
+                header: BTreeMap::new(),
+                data: ipld!([{"op": "add", "path": "/a", "value": 1}]),
+            }),
+            Payload::try_from(&data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let err = Anchor::try_from(&ipld!({"path": "0"})).unwrap_err();
+        assert_eq!("missing field id", err.to_string());
+        let err = Genesis::try_from(&ipld!({"data": {}})).unwrap_err();
+        assert_eq!("missing field header", err.to_string());
+    }
+}
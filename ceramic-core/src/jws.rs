@@ -0,0 +1,331 @@
+//! Verifies the signatures of dag-jose JWS envelopes issued by did:key DIDs.
+//!
+//! See https://w3c-ccg.github.io/did-method-key/
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use libipld::cid::Cid;
+use multibase::Base;
+use serde::Deserialize;
+
+use crate::event::{Jws, JwsSignature};
+
+// Multicodec codes of the public keys supported by did:key.
+const ED25519_PUB: u64 = 0xed;
+const SECP256K1_PUB: u64 = 0xe7;
+
+/// Algorithm used to sign a JWS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Ed25519 signatures
+    EdDSA,
+    /// ECDSA signatures using the secp256k1 curve and SHA-256
+    ES256K,
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "EdDSA" => Ok(Algorithm::EdDSA),
+            "ES256K" => Ok(Algorithm::ES256K),
+            _ => Err(anyhow!("unsupported JWS algorithm {}", s)),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Algorithm::EdDSA => write!(f, "EdDSA"),
+            Algorithm::ES256K => write!(f, "ES256K"),
+        }
+    }
+}
+
+/// The result of verifying a single signature of a JWS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// The signature was made by the issuer over the protected header and payload.
+    Valid {
+        /// DID of the issuer
+        did: String,
+        /// Algorithm of the signature
+        alg: Algorithm,
+    },
+    /// The signature does not match the key of the issuer, the protected header and payload.
+    Invalid {
+        /// DID of the issuer
+        did: String,
+        /// Algorithm of the signature
+        alg: Algorithm,
+    },
+}
+
+impl Verification {
+    /// Report whether the signature is valid.
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Verification::Valid { .. })
+    }
+    /// Report the DID of the issuer of the signature.
+    pub fn did(&self) -> &str {
+        match self {
+            Verification::Valid { did, .. } | Verification::Invalid { did, .. } => did,
+        }
+    }
+}
+
+/// The protected header of a JWS signature.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ProtectedHeader {
+    /// Algorithm of the signature
+    pub alg: String,
+    /// Key id of the signer
+    pub kid: Option<String>,
+    /// URI of a CACAO delegating authority to the signer, i.e. ipfs://<cid>
+    pub cap: Option<String>,
+}
+
+impl ProtectedHeader {
+    /// Report the DID of the signer.
+    pub fn did(&self) -> Result<&str> {
+        let kid = self
+            .kid
+            .as_deref()
+            .ok_or_else(|| anyhow!("protected header is missing kid"))?;
+        // The fragment of the kid identifies the key within the DID document,
+        // for did:key it is always the single key encoded in the DID itself.
+        Ok(kid.split('#').next().unwrap_or_default())
+    }
+    /// Report the Cid of the CACAO delegating authority to the signer, if any.
+    pub fn capability(&self) -> Result<Option<Cid>> {
+        self.cap
+            .as_deref()
+            .map(|cap| {
+                let cid = cap
+                    .strip_prefix("ipfs://")
+                    .ok_or_else(|| anyhow!("unsupported capability URI {}", cap))?;
+                Ok(Cid::from_str(cid)?)
+            })
+            .transpose()
+    }
+}
+
+impl TryFrom<&JwsSignature> for ProtectedHeader {
+    type Error = anyhow::Error;
+
+    fn try_from(signature: &JwsSignature) -> Result<Self> {
+        serde_json::from_slice(&signature.protected)
+            .map_err(|e| anyhow!("invalid protected header: {}", e))
+    }
+}
+
+enum PublicKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Secp256k1(k256::ecdsa::VerifyingKey),
+}
+
+/// Verify every signature of the JWS.
+///
+/// An error is returned when a signature cannot be checked,
+/// i.e. the issuer is not a did:key or the protected header is malformed.
+pub fn verify(jws: &Jws) -> Result<Vec<Verification>> {
+    if jws.signatures.is_empty() {
+        return Err(anyhow!("JWS has no signatures"));
+    }
+    jws.signatures
+        .iter()
+        .map(|signature| verify_signature(&jws.payload, signature))
+        .collect()
+}
+
+/// Verify a single signature over the payload of a JWS.
+pub fn verify_signature(payload: &[u8], signature: &JwsSignature) -> Result<Verification> {
+    let header = ProtectedHeader::try_from(signature)?;
+    let alg = Algorithm::from_str(&header.alg)?;
+    let did = header.did()?.to_string();
+    let key = resolve(&did)?;
+
+    let signing_input = format!(
+        "{}.{}",
+        Base::Base64Url.encode(&signature.protected),
+        Base::Base64Url.encode(payload)
+    );
+    let valid = match (alg, key) {
+        (Algorithm::EdDSA, PublicKey::Ed25519(key)) => {
+            use ed25519_dalek::Verifier;
+            ed25519_dalek::Signature::from_slice(&signature.signature)
+                .map(|sig| key.verify(signing_input.as_bytes(), &sig).is_ok())
+                .unwrap_or(false)
+        }
+        (Algorithm::ES256K, PublicKey::Secp256k1(key)) => {
+            use k256::ecdsa::signature::Verifier;
+            k256::ecdsa::Signature::from_slice(&signature.signature)
+                .map(|sig| key.verify(signing_input.as_bytes(), &sig).is_ok())
+                .unwrap_or(false)
+        }
+        (alg, _) => {
+            return Err(anyhow!(
+                "algorithm {} does not match the key type of {}",
+                alg,
+                did
+            ))
+        }
+    };
+    Ok(if valid {
+        Verification::Valid { did, alg }
+    } else {
+        Verification::Invalid { did, alg }
+    })
+}
+
+// Resolve the public key of a did:key DID.
+fn resolve(did: &str) -> Result<PublicKey> {
+    let id = did
+        .strip_prefix("did:key:")
+        .ok_or_else(|| anyhow!("unsupported DID method, expected did:key: {}", did))?;
+    let (_, bytes) = multibase::decode(id)?;
+    let (codec, key) = unsigned_varint::decode::u64(&bytes)?;
+    match codec {
+        ED25519_PUB => Ok(PublicKey::Ed25519(ed25519_dalek::VerifyingKey::from_bytes(
+            key.try_into()
+                .map_err(|_| anyhow!("invalid Ed25519 key length {}", key.len()))?,
+        )?)),
+        SECP256K1_PUB => Ok(PublicKey::Secp256k1(
+            k256::ecdsa::VerifyingKey::from_sec1_bytes(key)?,
+        )),
+        codec => Err(anyhow!("unsupported did:key key type 0x{:x}", codec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Signatures over the Cid bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae
+    // made by keys generated for these tests.
+    const PAYLOAD: &str = "bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae";
+    const ED25519_DID: &str = "did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK";
+    const ED25519_PROTECTED: &str = r#"{"alg":"EdDSA","kid":"did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}"#;
+    const ED25519_SIGNATURE: &str = "45af50b7c140b4eb925fe2dcfa150152f2f9076ae6a137565452f26f335814e037ef100fb237d5fca8664e0ee01835cceec8d4979c25c89012f0181153948303";
+    const SECP256K1_DID: &str = "did:key:zQ3shUDdqcqid6NhE9narGDt9DZfzsn2HN2gENPYmXuELFLH6";
+    const SECP256K1_PROTECTED: &str = r#"{"alg":"ES256K","kid":"did:key:zQ3shUDdqcqid6NhE9narGDt9DZfzsn2HN2gENPYmXuELFLH6#zQ3shUDdqcqid6NhE9narGDt9DZfzsn2HN2gENPYmXuELFLH6"}"#;
+    const SECP256K1_SIGNATURE: &str = "d9ebf46dea8a2baa3fe6078b819131f9dff2daee88282b364bbd6e63934c29187bc39b20fd020ea5ce7fd6f5268b3588cd06a71e4fb49553154eb940bab2ba31";
+
+    fn jws(protected: &str, signature: &str) -> Jws {
+        let link = Cid::from_str(PAYLOAD).unwrap();
+        Jws {
+            link,
+            payload: link.to_bytes(),
+            signatures: vec![JwsSignature {
+                protected: protected.as_bytes().to_vec(),
+                signature: hex::decode(signature).unwrap(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_verify_ed25519() {
+        assert_eq!(
+            vec![Verification::Valid {
+                did: ED25519_DID.to_string(),
+                alg: Algorithm::EdDSA,
+            }],
+            verify(&jws(ED25519_PROTECTED, ED25519_SIGNATURE)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_secp256k1() {
+        assert_eq!(
+            vec![Verification::Valid {
+                did: SECP256K1_DID.to_string(),
+                alg: Algorithm::ES256K,
+            }],
+            verify(&jws(SECP256K1_PROTECTED, SECP256K1_SIGNATURE)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_forged_signature() {
+        // Flip a bit of the signature
+        let mut forged = jws(ED25519_PROTECTED, ED25519_SIGNATURE);
+        forged.signatures[0].signature[0] ^= 1;
+        let verifications = verify(&forged).unwrap();
+        assert!(!verifications[0].is_valid());
+        assert_eq!(ED25519_DID, verifications[0].did());
+    }
+
+    #[test]
+    fn test_forged_payload() {
+        // Signature of a different payload
+        let mut forged = jws(SECP256K1_PROTECTED, SECP256K1_SIGNATURE);
+        let other =
+            Cid::from_str("bafyreie7jryvd6g7alrk4y4e7g7h5rtolpbl2dgdva6ebbgeopbpdjqaoa").unwrap();
+        forged.link = other;
+        forged.payload = other.to_bytes();
+        assert_eq!(
+            vec![Verification::Invalid {
+                did: SECP256K1_DID.to_string(),
+                alg: Algorithm::ES256K,
+            }],
+            verify(&forged).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_signature_by_other_key() {
+        // Claim the Ed25519 signature was made by a different Ed25519 key
+        let protected = ED25519_PROTECTED.replace(
+            "z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK",
+            "z6MkgSV3tAuw7gUWqKCUY7ae6uWNxqYgdwPhUJbJhF9EFXm9",
+        );
+        let verifications = verify(&jws(&protected, ED25519_SIGNATURE)).unwrap();
+        assert!(!verifications[0].is_valid());
+    }
+
+    #[test]
+    fn test_protected_header() {
+        let header = ProtectedHeader::try_from(&JwsSignature {
+            protected: br#"{"alg":"EdDSA","cap":"ipfs://bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae","kid":"did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}"#.to_vec(),
+            signature: vec![],
+        })
+        .unwrap();
+        assert_eq!(ED25519_DID, header.did().unwrap());
+        assert_eq!(
+            Some(Cid::from_str(PAYLOAD).unwrap()),
+            header.capability().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unverifiable() {
+        let err = verify(&jws(
+            &ED25519_PROTECTED.replace("EdDSA", "ES256K"),
+            ED25519_SIGNATURE,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            format!(
+                "algorithm ES256K does not match the key type of {}",
+                ED25519_DID
+            ),
+            err.to_string()
+        );
+        let err = verify(&jws(
+            r#"{"alg":"EdDSA","kid":"did:pkh:eip155:1:0x0000000000000000000000000000000000000000"}"#,
+            ED25519_SIGNATURE,
+        ))
+        .unwrap_err();
+        assert!(err.to_string().starts_with("unsupported DID method"));
+        let err = verify(&jws(r#"{"alg":"HS256"}"#, ED25519_SIGNATURE)).unwrap_err();
+        assert_eq!("unsupported JWS algorithm HS256", err.to_string());
+        let mut jws = jws(ED25519_PROTECTED, ED25519_SIGNATURE);
+        jws.signatures.clear();
+        assert_eq!(
+            "JWS has no signatures",
+            verify(&jws).unwrap_err().to_string()
+        );
+    }
+}
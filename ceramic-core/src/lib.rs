@@ -0,0 +1,10 @@
+//! Provides the core types of the Ceramic protocol.
+#![deny(warnings)]
+#![deny(missing_docs)]
+
+pub mod cacao;
+pub mod event;
+pub mod jws;
+mod stream_id;
+
+pub use stream_id::{CommitId, StreamId, StreamType, STREAMID_CODEC};
@@ -0,0 +1,361 @@
+//! Provides the StreamId and CommitId identifiers of Ceramic streams.
+//!
+//! See https://cips.ceramic.network/CIPs/cip-59
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use libipld::cid::Cid;
+use multibase::Base;
+
+/// Multicodec code identifying a Ceramic stream identifier.
+pub const STREAMID_CODEC: u64 = 0xce;
+
+// Prefixes that may precede the multibase encoded identifier.
+const PREFIXES: [&str; 2] = ["ceramic://", "/ceramic/"];
+
+/// The type of a Ceramic stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StreamType {
+    /// A TileDocument stream
+    Tile,
+    /// A Caip10Link stream
+    Caip10Link,
+    /// A ComposeDB model definition
+    Model,
+    /// A ComposeDB model instance document
+    ModelInstanceDocument,
+    /// A stream whose type cannot be loaded
+    Unloadable,
+    /// A stream type not known to this implementation
+    Unknown(u64),
+}
+
+impl StreamType {
+    /// Report the numeric code of the stream type.
+    pub fn code(&self) -> u64 {
+        match self {
+            StreamType::Tile => 0,
+            StreamType::Caip10Link => 1,
+            StreamType::Model => 2,
+            StreamType::ModelInstanceDocument => 3,
+            StreamType::Unloadable => 4,
+            StreamType::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u64> for StreamType {
+    fn from(code: u64) -> Self {
+        match code {
+            0 => StreamType::Tile,
+            1 => StreamType::Caip10Link,
+            2 => StreamType::Model,
+            3 => StreamType::ModelInstanceDocument,
+            4 => StreamType::Unloadable,
+            code => StreamType::Unknown(code),
+        }
+    }
+}
+
+impl Display for StreamType {
+    // Use the same names as js-ceramic
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamType::Tile => write!(f, "tile"),
+            StreamType::Caip10Link => write!(f, "caip10-link"),
+            StreamType::Model => write!(f, "model"),
+            StreamType::ModelInstanceDocument => write!(f, "MID"),
+            StreamType::Unloadable => write!(f, "UNLOADABLE"),
+            StreamType::Unknown(code) => write!(f, "unknown({})", code),
+        }
+    }
+}
+
+/// Identifies a Ceramic stream by its type and genesis commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StreamId {
+    stream_type: StreamType,
+    cid: Cid,
+}
+
+impl StreamId {
+    /// Construct a StreamId from the stream type and the CID of the genesis commit.
+    pub fn new(stream_type: StreamType, cid: Cid) -> Self {
+        Self { stream_type, cid }
+    }
+    /// Report the type of the stream.
+    pub fn stream_type(&self) -> StreamType {
+        self.stream_type
+    }
+    /// Report the CID of the genesis commit of the stream.
+    pub fn cid(&self) -> Cid {
+        self.cid
+    }
+    /// Construct a CommitId referring to the genesis commit of the stream.
+    pub fn at_genesis(&self) -> CommitId {
+        CommitId {
+            stream_id: *self,
+            commit: None,
+        }
+    }
+    /// Construct a CommitId referring to a specific commit of the stream.
+    pub fn at_commit(&self, commit: Cid) -> CommitId {
+        CommitId::new(*self, commit)
+    }
+    /// Encode the StreamId as bytes.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint(STREAMID_CODEC, &mut buf);
+        write_varint(self.stream_type.code(), &mut buf);
+        buf.extend(self.cid.to_bytes());
+        buf
+    }
+}
+
+impl TryFrom<&[u8]> for StreamId {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let (stream_id, rest) = read_stream_id(bytes)?;
+        if !rest.is_empty() {
+            return Err(anyhow!(
+                "unexpected {} trailing bytes, is this a CommitId?",
+                rest.len()
+            ));
+        }
+        Ok(stream_id)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        StreamId::try_from(decode(s)?.as_slice())
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", multibase::encode(Base::Base36Lower, self.to_vec()))
+    }
+}
+
+/// Identifies a specific commit within a Ceramic stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommitId {
+    stream_id: StreamId,
+    // None when the commit is the genesis commit of the stream.
+    commit: Option<Cid>,
+}
+
+impl CommitId {
+    /// Construct a CommitId from the StreamId and the CID of a commit within the stream.
+    pub fn new(stream_id: StreamId, commit: Cid) -> Self {
+        Self {
+            stream_id,
+            commit: (commit != stream_id.cid).then_some(commit),
+        }
+    }
+    /// Report the StreamId of the stream containing the commit.
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+    /// Report the type of the stream.
+    pub fn stream_type(&self) -> StreamType {
+        self.stream_id.stream_type
+    }
+    /// Report the CID of the genesis commit of the stream.
+    pub fn genesis(&self) -> Cid {
+        self.stream_id.cid
+    }
+    /// Report the CID of the commit.
+    pub fn commit(&self) -> Cid {
+        self.commit.unwrap_or(self.stream_id.cid)
+    }
+    /// Report whether the commit is the genesis commit of the stream.
+    pub fn is_genesis(&self) -> bool {
+        self.commit.is_none()
+    }
+    /// Encode the CommitId as bytes.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = self.stream_id.to_vec();
+        match self.commit {
+            Some(commit) => buf.extend(commit.to_bytes()),
+            // The genesis commit is encoded as a zero instead of repeating the CID.
+            None => write_varint(0, &mut buf),
+        }
+        buf
+    }
+}
+
+impl TryFrom<&[u8]> for CommitId {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let (stream_id, rest) = read_stream_id(bytes)?;
+        if rest.is_empty() {
+            return Err(anyhow!("missing commit, is this a StreamId?"));
+        }
+        let commit = if rest == [0] {
+            None
+        } else {
+            let mut reader = rest;
+            let commit = Cid::read_bytes(&mut reader)?;
+            if !reader.is_empty() {
+                return Err(anyhow!("unexpected {} trailing bytes", reader.len()));
+            }
+            Some(commit)
+        };
+        Ok(Self { stream_id, commit })
+    }
+}
+
+impl FromStr for CommitId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        CommitId::try_from(decode(s)?.as_slice())
+    }
+}
+
+impl Display for CommitId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", multibase::encode(Base::Base36Lower, self.to_vec()))
+    }
+}
+
+// Decode the multibase string into bytes, ignoring any URL like prefix.
+fn decode(s: &str) -> Result<Vec<u8>> {
+    let s = PREFIXES
+        .iter()
+        .find_map(|prefix| s.strip_prefix(prefix))
+        .unwrap_or(s);
+    let (_, bytes) = multibase::decode(s)?;
+    Ok(bytes)
+}
+
+// Read the StreamId from the front of the bytes returning the remaining bytes.
+fn read_stream_id(bytes: &[u8]) -> Result<(StreamId, &[u8])> {
+    let (codec, rest) = unsigned_varint::decode::u64(bytes)?;
+    if codec != STREAMID_CODEC {
+        return Err(anyhow!(
+            "invalid codec 0x{:x}, expected 0x{:x}",
+            codec,
+            STREAMID_CODEC
+        ));
+    }
+    let (stream_type, mut rest) = unsigned_varint::decode::u64(rest)?;
+    let cid = Cid::read_bytes(&mut rest)?;
+    Ok((StreamId::new(stream_type.into(), cid), rest))
+}
+
+fn write_varint(value: u64, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(unsigned_varint::encode::u64(
+        value,
+        &mut unsigned_varint::encode::u64_buffer(),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from js-ceramic
+    const GENESIS: &str = "bagcqcerakszw2vsovxznyp5gfnpdj4cqm2xiv76yd24wkjewhhykovorwo6a";
+    const COMMIT: &str = "bagjqcgzaday6dzalvmy5ady2m5a5legq5zrbsnlxfc2bfxej532ds7htpova";
+    const STREAM_ID: &str = "kjzl6cwe1jw147dvq16zluojmraqvwdmbh61dx9e0c59i344lcrsgqfohexp60s";
+    const GENESIS_COMMIT_ID: &str =
+        "k3y52l7qbv1frxwipl4hp7e6jlu4f6u8upm2xv0irmedfkm5cnutmezzi3u7mytj4";
+    const COMMIT_ID: &str = "k1dpgaqe3i64kjqcp801r3sn7ysi5i0k7nxvs7j351s7kewfzr3l7mdxnj7szwo4kr9mn2qki5nnj0cv836ythy1t1gya9s25cn1nexst3jxi5o3h6qprfyju";
+
+    #[test]
+    fn test_stream_id_round_trip() {
+        let stream_id = StreamId::from_str(STREAM_ID).unwrap();
+        assert_eq!(StreamType::Tile, stream_id.stream_type());
+        assert_eq!(Cid::from_str(GENESIS).unwrap(), stream_id.cid());
+        assert_eq!(STREAM_ID, stream_id.to_string());
+        assert_eq!(
+            stream_id,
+            StreamId::try_from(stream_id.to_vec().as_slice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stream_id_encode() {
+        let stream_id = StreamId::new(StreamType::Tile, Cid::from_str(GENESIS).unwrap());
+        assert_eq!(STREAM_ID, stream_id.to_string());
+    }
+
+    #[test]
+    fn test_stream_id_prefixes() {
+        let expected = StreamId::from_str(STREAM_ID).unwrap();
+        for prefix in PREFIXES {
+            let s = format!("{}{}", prefix, STREAM_ID);
+            assert_eq!(expected, StreamId::from_str(&s).unwrap(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_stream_type_codes() {
+        for code in 0..8 {
+            assert_eq!(code, StreamType::from(code).code());
+        }
+        assert_eq!(StreamType::Model, StreamType::from(2));
+        assert_eq!("MID", StreamType::from(3).to_string());
+        assert_eq!(StreamType::Unknown(7), StreamType::from(7));
+    }
+
+    #[test]
+    fn test_genesis_commit_id_round_trip() {
+        let commit_id = CommitId::from_str(GENESIS_COMMIT_ID).unwrap();
+        assert!(commit_id.is_genesis());
+        assert_eq!(
+            StreamId::from_str(STREAM_ID).unwrap(),
+            commit_id.stream_id()
+        );
+        assert_eq!(Cid::from_str(GENESIS).unwrap(), commit_id.commit());
+        assert_eq!(GENESIS_COMMIT_ID, commit_id.to_string());
+    }
+
+    #[test]
+    fn test_commit_id_round_trip() {
+        let commit_id = CommitId::from_str(COMMIT_ID).unwrap();
+        assert!(!commit_id.is_genesis());
+        assert_eq!(StreamType::Tile, commit_id.stream_type());
+        assert_eq!(Cid::from_str(GENESIS).unwrap(), commit_id.genesis());
+        assert_eq!(Cid::from_str(COMMIT).unwrap(), commit_id.commit());
+        assert_eq!(COMMIT_ID, commit_id.to_string());
+    }
+
+    #[test]
+    fn test_commit_id_from_stream_id() {
+        let stream_id = StreamId::from_str(STREAM_ID).unwrap();
+        assert_eq!(GENESIS_COMMIT_ID, stream_id.at_genesis().to_string());
+        // Committing at the genesis CID is the same as the genesis commit.
+        assert_eq!(
+            GENESIS_COMMIT_ID,
+            stream_id.at_commit(stream_id.cid()).to_string()
+        );
+        assert_eq!(
+            COMMIT_ID,
+            stream_id
+                .at_commit(Cid::from_str(COMMIT).unwrap())
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_mismatched_ids() {
+        assert!(StreamId::from_str(COMMIT_ID).is_err());
+        assert!(StreamId::from_str(GENESIS_COMMIT_ID).is_err());
+        assert!(CommitId::from_str(STREAM_ID).is_err());
+    }
+
+    #[test]
+    fn test_invalid_codec() {
+        let err =
+            StreamId::from_str(&multibase::encode(Base::Base36Lower, [0x01, 0x00])).unwrap_err();
+        assert_eq!("invalid codec 0x1, expected 0xce", err.to_string());
+    }
+}
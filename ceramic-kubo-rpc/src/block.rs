@@ -0,0 +1,75 @@
+//! Implements the block endpoints.
+use anyhow::anyhow;
+use iroh_api::{Bytes, Cid};
+use libipld::multihash::{Code, MultihashDigest};
+
+use crate::{dag, error::Error, IpfsDep};
+
+/// Codec of blocks that contain raw bytes.
+pub const RAW: u64 = 0x55;
+
+/// Get a block from IPFS.
+#[tracing::instrument(skip(client))]
+pub async fn get<T>(client: T, cid: Cid) -> Result<Bytes, Error>
+where
+    T: IpfsDep,
+{
+    client.block_get(cid).await
+}
+
+/// Store a block into IPFS.
+///
+/// The block is hashed using the multihash code `mhtype`,
+/// optionally truncating the digest to `mhlen` bytes.
+#[tracing::instrument(skip(client, blob))]
+pub async fn put<T>(
+    client: T,
+    cid_codec: u64,
+    mhtype: Code,
+    mhlen: Option<u8>,
+    blob: Vec<u8>,
+) -> Result<Cid, Error>
+where
+    T: IpfsDep,
+{
+    let mut hash = mhtype.digest(&blob);
+    if let Some(mhlen) = mhlen {
+        if usize::from(mhlen) > hash.digest().len() {
+            return Err(Error::Invalid(anyhow!(
+                "mhlen {} is larger than the digest length {}",
+                mhlen,
+                hash.digest().len()
+            )));
+        }
+        hash = hash.truncate(mhlen);
+    }
+    let cid = Cid::new_v1(cid_codec, hash);
+
+    let links = links(&cid, &blob).map_err(|e| match e {
+        Error::Internal(e) => Error::Invalid(e.context("decoding block")),
+        e => e,
+    })?;
+
+    client.put(cid, blob.into(), links).await?;
+    Ok(cid)
+}
+
+/// Report the size of a block in bytes.
+#[tracing::instrument(skip(client))]
+pub async fn stat<T>(client: T, cid: Cid) -> Result<u64, Error>
+where
+    T: IpfsDep,
+{
+    client.block_size(cid).await
+}
+
+/// Report the links of a block.
+///
+/// Raw blocks cannot contain links, all other blocks must be valid for their codec.
+pub fn links(cid: &Cid, blob: &[u8]) -> Result<Vec<Cid>, Error> {
+    let mut links: Vec<Cid> = Vec::new();
+    if cid.codec() != RAW {
+        dag::decode(cid, blob)?.references(&mut links);
+    }
+    Ok(links)
+}
@@ -0,0 +1,179 @@
+//! Implements validation of the CACAO capabilities referenced by signed Ceramic commits.
+use ceramic_core::{cacao::Cacao, event::Jws, jws::ProtectedHeader, StreamId};
+use chrono::{DateTime, Utc};
+use iroh_api::{Cid, IpfsPath};
+
+use crate::{error::Error, IpfsDep};
+
+/// Get a CACAO by its Cid.
+#[tracing::instrument(skip(client))]
+pub async fn get<T>(client: T, cid: Cid) -> Result<Cacao, Error>
+where
+    T: IpfsDep,
+{
+    let (_, node) = client.get(&IpfsPath::from_cid(cid)).await?;
+    Cacao::try_from(&node).map_err(Error::Invalid)
+}
+
+/// Verify the capabilities referenced by the `cap` header of each signature of a JWS.
+///
+/// Each capability must delegate to the signer the ability to write to the stream at the given time,
+/// the expiry of capabilities is not checked when the time is not known.
+/// The signatures themselves are not verified here.
+/// Returns for each signature the DID it was made on behalf of:
+/// the issuer of its capability, i.e. a did:pkh account, or the signer when it has no capability.
+#[tracing::instrument(skip(client, jws))]
+pub async fn verify<T>(
+    client: T,
+    jws: &Jws,
+    stream_id: &StreamId,
+    model: Option<&StreamId>,
+    at: Option<DateTime<Utc>>,
+) -> Result<Vec<String>, Error>
+where
+    T: IpfsDep,
+{
+    let mut authors = Vec::new();
+    for signature in &jws.signatures {
+        let header = ProtectedHeader::try_from(signature).map_err(Error::Invalid)?;
+        let signer = header.did().map_err(Error::Invalid)?;
+        match header.capability().map_err(Error::Invalid)? {
+            Some(cid) => {
+                let cacao = get(client.clone(), cid).await?;
+                cacao
+                    .verify(signer, stream_id, model, at)
+                    .map_err(Error::Invalid)?;
+                authors.push(cacao.payload.iss);
+            }
+            None => authors.push(signer.to_string()),
+        }
+    }
+    Ok(authors)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ceramic_core::event::JwsSignature;
+    use unimock::{matching, MockFn, Unimock};
+
+    use super::*;
+    use crate::{dag, IpfsDepMock};
+
+    // A SIWE CACAO delegating access to instances of a model
+    // from an Ethereum account to a session did:key, both generated for these tests.
+    const CACAO: (&str, &str) = (
+        "bafyreigcqxmpyzbzxm37lycyqdvf7tpyhuqs3yzc533ycskypf2fdtwkzy",
+        "a36168a1617467656970343336316170a96361756478386469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b636578707818323032322d30332d32345431323a34353a31332e3631305a636961747818323032322d30332d31375431323a34353a31332e3631305a63697373783b6469643a706b683a6569703135353a313a307832466438363662373038323232306330624237433130343134374637333245383330363242463937656e6f6e63656633323839313766646f6d61696e6b736572766963652e6f72676776657273696f6e6131697265736f7572636573817851636572616d69633a2f2f2a3f6d6f64656c3d6b6a7a6c36687666726277366337346863636c37743832333763377478626c65786c78686e7333306b797270363837726b376e346e7730766c3077783133306973746174656d656e74784149206163636570742074686520536572766963654f7267205465726d73206f6620536572766963653a2068747470733a2f2f736572766963652e6f72672f746f736173a261735841ec1e817e785202e6f76fd1fb01ea8e5747bfa992ab215be07126a9143e504f310db230cdb94d8bc7348772db2454ae539ec59eb326ea2920e24bc4ab9e09a92a1c617466656970313931",
+    );
+    const SESSION: &str = "did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK";
+    const MODEL: &str = "kjzl6hvfrbw6c74hccl7t8237c7txblexlxhns30kyrp687rk7n4nw0vl0wx130";
+    const STREAM: &str = "kjzl6kcym7w8y6zs5iabwwquzmodg17b8ob7sphtva2x0arl1n2srgth4twiym4";
+
+    // Construct a JWS signed by the session key, optionally referencing a capability.
+    fn jws(cap: Option<&str>) -> Jws {
+        let link =
+            Cid::from_str("bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae").unwrap();
+        let protected = match cap {
+            Some(cap) => format!(
+                r#"{{"alg":"EdDSA","cap":"ipfs://{}","kid":"{}#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}}"#,
+                cap, SESSION
+            ),
+            None => format!(
+                r#"{{"alg":"EdDSA","kid":"{}#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}}"#,
+                SESSION
+            ),
+        };
+        Jws {
+            link,
+            payload: link.to_bytes(),
+            signatures: vec![JwsSignature {
+                protected: protected.into_bytes(),
+                signature: vec![0; 64],
+            }],
+        }
+    }
+
+    fn mock_cacao() -> Unimock {
+        let cid = Cid::from_str(CACAO.0).unwrap();
+        let node = dag::decode(&cid, &hex::decode(CACAO.1).unwrap()).unwrap();
+        Unimock::new(
+            IpfsDepMock::get
+                .next_call(matching!(_))
+                .returns(Ok((cid, node))),
+        )
+    }
+
+    fn at(s: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc))
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let authors = verify(
+            mock_cacao(),
+            &jws(Some(CACAO.0)),
+            &StreamId::from_str(STREAM).unwrap(),
+            Some(&StreamId::from_str(MODEL).unwrap()),
+            at("2022-03-18T00:00:00Z"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            vec!["did:pkh:eip155:1:0x2Fd866b7082220c0bB7C104147F732E83062BF97".to_string()],
+            authors
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_without_capability() {
+        // Expect no calls to get
+        let authors = verify(
+            Unimock::new(()),
+            &jws(None),
+            &StreamId::from_str(STREAM).unwrap(),
+            None,
+            at("2022-03-18T00:00:00Z"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(vec![SESSION.to_string()], authors);
+    }
+
+    #[tokio::test]
+    async fn test_verify_expired() {
+        let err = verify(
+            mock_cacao(),
+            &jws(Some(CACAO.0)),
+            &StreamId::from_str(STREAM).unwrap(),
+            Some(&StreamId::from_str(MODEL).unwrap()),
+            at("2022-04-01T00:00:00Z"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            "invalid: CACAO expired at 2022-03-24T12:45:13.610Z",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_missing_capability() {
+        let mock = Unimock::new(
+            IpfsDepMock::get
+                .next_call(matching!(_))
+                .returns(Err(Error::NotFound)),
+        );
+        let err = verify(
+            mock,
+            &jws(Some(CACAO.0)),
+            &StreamId::from_str(STREAM).unwrap(),
+            None,
+            at("2022-03-18T00:00:00Z"),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::NotFound), "{:?}", err);
+    }
+}
@@ -0,0 +1,301 @@
+//! Provides streaming encoding and decoding of CAR files.
+//!
+//! See https://ipld.io/specs/transport/car/carv1/ and https://ipld.io/specs/transport/car/carv2/
+use std::io::Cursor;
+
+use anyhow::anyhow;
+use futures_util::{io::AsyncReadExt, AsyncRead};
+use iroh_api::{Bytes, Cid};
+use libipld::{
+    cbor::DagCborCodec,
+    ipld,
+    prelude::{Codec, Encode},
+    Ipld,
+};
+
+use crate::error::Error;
+
+// Length of the fixed CARv2 pragma and header that precede the inner CARv1 payload.
+const V2_PRAGMA_LEN: u64 = 11;
+const V2_HEADER_LEN: u64 = 40;
+// Largest header and section read, the same limits as Kubo.
+const MAX_HEADER_LEN: u64 = 2 << 20;
+const MAX_SECTION_LEN: u64 = 2 << 20;
+
+/// Encode a CARv1 header for the roots.
+pub(crate) fn header(roots: &[Cid]) -> Result<Bytes, Error> {
+    let header = ipld!({
+        "roots": roots.iter().copied().map(Ipld::Link).collect::<Vec<Ipld>>(),
+        "version": 1,
+    });
+    let mut data = Vec::new();
+    header
+        .encode(DagCborCodec, &mut data)
+        .map_err(Error::Internal)?;
+    let mut buf = Vec::with_capacity(data.len() + 10);
+    write_varint(data.len() as u64, &mut buf);
+    buf.extend(data);
+    Ok(buf.into())
+}
+
+/// Encode a CARv1 section for the block.
+pub(crate) fn section(cid: &Cid, data: &[u8]) -> Bytes {
+    let cid = cid.to_bytes();
+    let mut buf = Vec::with_capacity(cid.len() + data.len() + 10);
+    write_varint((cid.len() + data.len()) as u64, &mut buf);
+    buf.extend(cid);
+    buf.extend(data);
+    buf.into()
+}
+
+/// Reads the blocks of a CARv1 or CARv2 file without buffering the whole file.
+pub(crate) struct CarReader<R> {
+    reader: R,
+    roots: Vec<Cid>,
+    // Number of bytes of the CARv1 payload remaining, only known for CARv2 files.
+    remaining: Option<u64>,
+}
+
+impl<R> CarReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Read the header of the CAR file.
+    pub(crate) async fn new(mut reader: R) -> Result<Self, Error> {
+        let header = read_header(&mut reader).await?;
+        match version(&header)? {
+            1 => Ok(Self {
+                roots: roots(&header)?,
+                reader,
+                remaining: None,
+            }),
+            2 => {
+                let mut v2_header = [0u8; V2_HEADER_LEN as usize];
+                reader
+                    .read_exact(&mut v2_header)
+                    .await
+                    .map_err(invalid_car)?;
+                // Skip the 16 bytes of characteristics
+                let data_offset = u64::from_le_bytes(v2_header[16..24].try_into().unwrap());
+                let data_size = u64::from_le_bytes(v2_header[24..32].try_into().unwrap());
+                let padding = data_offset
+                    .checked_sub(V2_PRAGMA_LEN + V2_HEADER_LEN)
+                    .ok_or_else(|| Error::Invalid(anyhow!("invalid CARv2 data offset")))?;
+                futures_util::io::copy(
+                    &mut (&mut reader).take(padding),
+                    &mut futures_util::io::sink(),
+                )
+                .await
+                .map_err(invalid_car)?;
+
+                let mut inner = (&mut reader).take(data_size);
+                let header = read_header(&mut inner).await?;
+                if version(&header)? != 1 {
+                    return Err(Error::Invalid(anyhow!(
+                        "CARv2 must contain a CARv1 payload"
+                    )));
+                }
+                let remaining = inner.limit();
+                Ok(Self {
+                    roots: roots(&header)?,
+                    reader,
+                    remaining: Some(remaining),
+                })
+            }
+            version => Err(Error::Invalid(anyhow!(
+                "unsupported CAR version {}",
+                version
+            ))),
+        }
+    }
+    /// Report the roots of the CAR file.
+    pub(crate) fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+    /// Read the next block returning None once all blocks have been read.
+    pub(crate) async fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, Error> {
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        let (len, varint_len) = match read_varint(&mut self.reader).await? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        if len > MAX_SECTION_LEN {
+            return Err(Error::Invalid(anyhow!(
+                "CAR section of {} bytes exceeds the limit of {} bytes",
+                len,
+                MAX_SECTION_LEN
+            )));
+        }
+        let mut section = vec![0u8; len as usize];
+        self.reader
+            .read_exact(&mut section)
+            .await
+            .map_err(invalid_car)?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining
+                .checked_sub(varint_len + len)
+                .ok_or_else(|| Error::Invalid(anyhow!("CARv2 section exceeds data size")))?;
+        }
+
+        let mut cursor = Cursor::new(section);
+        let cid = Cid::read_bytes(&mut cursor).map_err(|e| Error::Invalid(e.into()))?;
+        let offset = cursor.position() as usize;
+        let mut data = cursor.into_inner();
+        data.drain(..offset);
+        Ok(Some((cid, data)))
+    }
+}
+
+fn invalid_car(err: std::io::Error) -> Error {
+    Error::Invalid(anyhow::Error::from(err).context("reading CAR"))
+}
+
+async fn read_header<R>(reader: &mut R) -> Result<Ipld, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let (len, _) = read_varint(reader)
+        .await?
+        .ok_or_else(|| Error::Invalid(anyhow!("missing CAR header")))?;
+    if len > MAX_HEADER_LEN {
+        return Err(Error::Invalid(anyhow!(
+            "CAR header of {} bytes exceeds the limit of {} bytes",
+            len,
+            MAX_HEADER_LEN
+        )));
+    }
+    let mut header = vec![0u8; len as usize];
+    reader.read_exact(&mut header).await.map_err(invalid_car)?;
+    DagCborCodec
+        .decode(&header)
+        .map_err(|e| Error::Invalid(e.context("decoding CAR header")))
+}
+
+fn version(header: &Ipld) -> Result<i128, Error> {
+    match header {
+        Ipld::Map(header) => match header.get("version") {
+            Some(Ipld::Integer(version)) => Ok(*version),
+            _ => Err(Error::Invalid(anyhow!("CAR header is missing version"))),
+        },
+        _ => Err(Error::Invalid(anyhow!("CAR header must be a map"))),
+    }
+}
+
+fn roots(header: &Ipld) -> Result<Vec<Cid>, Error> {
+    match header {
+        Ipld::Map(header) => match header.get("roots") {
+            Some(Ipld::List(roots)) => roots
+                .iter()
+                .map(|root| match root {
+                    Ipld::Link(cid) => Ok(*cid),
+                    _ => Err(Error::Invalid(anyhow!("CAR roots must be links"))),
+                })
+                .collect(),
+            _ => Err(Error::Invalid(anyhow!("CAR header is missing roots"))),
+        },
+        _ => Err(Error::Invalid(anyhow!("CAR header must be a map"))),
+    }
+}
+
+fn write_varint(mut n: u64, buf: &mut Vec<u8>) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+// Read an unsigned varint, returning the value and the number of bytes read,
+// or None if the reader is at EOF.
+async fn read_varint<R>(reader: &mut R) -> Result<Option<(u64, u64)>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut n: u64 = 0;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte).await.map_err(invalid_car)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(Error::Invalid(anyhow!("truncated varint in CAR")));
+        }
+        n |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some((n, i + 1)));
+        }
+    }
+    Err(Error::Invalid(anyhow!("varint in CAR is too long")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use futures_util::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_v1_round_trip() {
+        let root =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mut car = header(&[root]).unwrap().to_vec();
+        car.extend(section(&root, b"hello world"));
+
+        let mut reader = CarReader::new(Cursor::new(car)).await.unwrap();
+        assert_eq!(&[root], reader.roots());
+        assert_eq!(
+            Some((root, b"hello world".to_vec())),
+            reader.next_block().await.unwrap()
+        );
+        assert_eq!(None, reader.next_block().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let root =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mut payload = header(&[root]).unwrap().to_vec();
+        payload.extend(section(&root, b"hello world"));
+
+        let padding = 5;
+        let data_offset = V2_PRAGMA_LEN + V2_HEADER_LEN + padding;
+        let mut car = hex::decode("0aa16776657273696f6e02").unwrap();
+        car.extend([0u8; 16]);
+        car.extend(data_offset.to_le_bytes());
+        car.extend((payload.len() as u64).to_le_bytes());
+        car.extend(0u64.to_le_bytes());
+        car.extend(vec![0u8; padding as usize]);
+        car.extend(payload);
+        // Trailing index data must be ignored
+        car.extend([0xffu8; 8]);
+
+        let mut reader = CarReader::new(Cursor::new(car)).await.unwrap();
+        assert_eq!(&[root], reader.roots());
+        assert_eq!(
+            Some((root, b"hello world".to_vec())),
+            reader.next_block().await.unwrap()
+        );
+        assert_eq!(None, reader.next_block().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_size_limits() {
+        let mut car = Vec::new();
+        write_varint(MAX_HEADER_LEN + 1, &mut car);
+        assert!(matches!(
+            CarReader::new(Cursor::new(car)).await,
+            Err(Error::Invalid(_))
+        ));
+
+        let root =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mut car = header(&[root]).unwrap().to_vec();
+        write_varint(u64::MAX, &mut car);
+        let mut reader = CarReader::new(Cursor::new(car)).await.unwrap();
+        assert!(matches!(reader.next_block().await, Err(Error::Invalid(_))));
+    }
+}
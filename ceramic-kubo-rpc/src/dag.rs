@@ -1,9 +1,18 @@
 //! Implements the dag endpoints.
-use std::io::{Cursor, Read, Seek};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
 
 use anyhow::anyhow;
+use ceramic_core::{
+    event::Jws,
+    jws::{self, Verification},
+};
 use dag_jose::DagJoseCodec;
-use iroh_api::{Cid, IpfsPath};
+use futures_util::{future, stream, AsyncRead, Stream, StreamExt};
+use iroh_api::{Bytes, Cid, IpfsPath};
 use libipld::{
     cbor::DagCborCodec,
     multihash::{Code, MultihashDigest},
@@ -12,7 +21,14 @@ use libipld::{
     Ipld,
 };
 
-use crate::{error::Error, IpfsDep};
+use crate::{
+    block,
+    car::{self, CarReader},
+    error::Error,
+    metrics,
+    pin::{self, PinStore},
+    IpfsDep,
+};
 
 /// Get a DAG node from IPFS.
 #[tracing::instrument(skip(client, output_codec))]
@@ -22,18 +38,8 @@ where
     C: Codec,
     Ipld: Encode<C>,
 {
-    let (cid, bytes) = client.get(ipfs_path).await?;
-    let dag_data = match cid.codec() {
-        // dag-pb
-        0x70 => Ipld::decode(DagPbCodec, &mut Cursor::new(&bytes)).map_err(Error::Internal)?,
-        // dag-cbor
-        0x71 => Ipld::decode(DagCborCodec, &mut Cursor::new(&bytes)).map_err(Error::Internal)?,
-        // dag-jose
-        0x85 => Ipld::decode(DagJoseCodec, &mut Cursor::new(&bytes)).map_err(Error::Internal)?,
-        _ => {
-            return Err(Error::Invalid(anyhow!("unsupported codec {}", cid.codec())));
-        }
-    };
+    let (cid, dag_data) = client.get(ipfs_path).await?;
+    metrics::record_dag_get(cid.codec(), output_codec.into());
     let mut data: Vec<u8> = Vec::new();
     dag_data
         .encode(output_codec, &mut data)
@@ -66,10 +72,44 @@ where
 
     let hash = Code::Sha2_256.digest(&blob);
     let cid = Cid::new_v1(store_codec.into(), hash);
-    client.put(cid, blob.into(), vec![]).await?;
+
+    // Report all links of the stored node, including links nested within maps and lists,
+    // so the store can track the children of the node. Links are read from the encoded block
+    // as the input may not expose them, e.g. the payload link of a JWS in JSON.
+    let links = block::links(&cid, &blob)?;
+    metrics::record_dag_put(blob.len());
+    client.put(cid, blob.into(), links).await?;
     Ok(cid)
 }
 
+/// Verify the signatures of a dag-jose JWS, rejecting the node unless every signature is valid.
+///
+/// The data is rewound afterwards so that it can be stored.
+#[tracing::instrument(skip_all)]
+pub fn verify_signatures<I, R>(input_codec: I, data: &mut R) -> Result<Vec<Verification>, Error>
+where
+    I: Codec,
+    Ipld: Decode<I>,
+    R: Read + Seek,
+{
+    let start = data
+        .stream_position()
+        .map_err(|e| Error::Internal(e.into()))?;
+    let dag_data = Ipld::decode(input_codec, data).map_err(Error::Invalid)?;
+    data.seek(SeekFrom::Start(start))
+        .map_err(|e| Error::Internal(e.into()))?;
+
+    let envelope = Jws::try_from(&dag_data).map_err(Error::Invalid)?;
+    let verifications = jws::verify(&envelope).map_err(Error::Invalid)?;
+    if let Some(invalid) = verifications.iter().find(|v| !v.is_valid()) {
+        return Err(Error::Invalid(anyhow!(
+            "signature by {} is not valid",
+            invalid.did()
+        )));
+    }
+    Ok(verifications)
+}
+
 /// Resolve an IPLD block.
 #[tracing::instrument(skip(client))]
 pub async fn resolve<T>(client: T, path: &IpfsPath) -> Result<Cid, Error>
@@ -86,3 +126,347 @@ where
         )))?
         .to_owned())
 }
+
+/// Export the DAG reachable from the root as a CARv1 file.
+///
+/// Blocks are fetched and streamed in depth first order as the DAG is walked.
+pub fn export<T>(client: T, root: Cid) -> impl Stream<Item = Result<Bytes, Error>>
+where
+    T: IpfsDep,
+{
+    let state = (client, vec![root], BTreeSet::new());
+    stream::once(future::ready(car::header(&[root]))).chain(stream::try_unfold(
+        state,
+        |(client, mut stack, mut visited)| async move {
+            while let Some(cid) = stack.pop() {
+                if !visited.insert(cid) {
+                    continue;
+                }
+                let bytes = client.block_get(cid).await?;
+                // Push links in reverse so they are visited in order
+                stack.extend(block::links(&cid, &bytes)?.into_iter().rev());
+                return Ok(Some((car::section(&cid, &bytes), (client, stack, visited))));
+            }
+            Ok(None)
+        },
+    ))
+}
+
+/// A root of an imported CAR file.
+#[derive(Debug)]
+pub struct ImportedRoot {
+    /// Cid of the root.
+    pub cid: Cid,
+    /// Reason the root could not be pinned, when pinning was requested.
+    pub pin_error: Option<Error>,
+}
+
+/// Import the blocks of a CARv1 or CARv2 file, returning the roots of the file.
+///
+/// The hash of every block is verified against its Cid before it is stored.
+/// When pin_roots is true the roots are pinned recursively,
+/// a root that fails to pin is reported with its error instead of failing the import.
+#[tracing::instrument(skip(client, pins, reader))]
+pub async fn import<T, R>(
+    client: T,
+    pins: &PinStore,
+    reader: R,
+    pin_roots: bool,
+) -> Result<Vec<ImportedRoot>, Error>
+where
+    T: IpfsDep,
+    R: AsyncRead + Unpin,
+{
+    let mut car = CarReader::new(reader).await?;
+    while let Some((cid, blob)) = car.next_block().await? {
+        verify(&cid, &blob)?;
+        let links = block::links(&cid, &blob)?;
+        client.put(cid, blob.into(), links).await?;
+    }
+    let mut roots = Vec::new();
+    for cid in car.roots() {
+        let pin_error = if pin_roots {
+            pin::add(client.clone(), pins, *cid, true).await.err()
+        } else {
+            None
+        };
+        roots.push(ImportedRoot {
+            cid: *cid,
+            pin_error,
+        });
+    }
+    Ok(roots)
+}
+
+/// Verify the block hashes to the digest of its Cid.
+pub fn verify(cid: &Cid, blob: &[u8]) -> Result<(), Error> {
+    let code = Code::try_from(cid.hash().code()).map_err(|e| Error::Invalid(e.into()))?;
+    let hash = code.digest(blob).truncate(cid.hash().size());
+    if hash != *cid.hash() {
+        return Err(Error::Invalid(anyhow!(
+            "block data does not match the hash of Cid {}",
+            cid
+        )));
+    }
+    Ok(())
+}
+
+/// Decode a block into IPLD data using the codec of its Cid.
+pub(crate) fn decode(cid: &Cid, bytes: &[u8]) -> Result<Ipld, Error> {
+    match cid.codec() {
+        // dag-pb
+        0x70 => Ipld::decode(DagPbCodec, &mut Cursor::new(bytes)).map_err(Error::Internal),
+        // dag-cbor
+        0x71 => Ipld::decode(DagCborCodec, &mut Cursor::new(bytes)).map_err(Error::Internal),
+        // dag-jose
+        0x85 => Ipld::decode(DagJoseCodec, &mut Cursor::new(bytes)).map_err(Error::Internal),
+        _ => Err(Error::Invalid(anyhow!("unsupported codec {}", cid.codec()))),
+    }
+}
+
+/// Traverse a path starting at the root Cid, fetching and decoding each block along the way.
+///
+/// Links encountered while walking the path are followed into the linked block.
+/// Returns the Cid of the block containing the final node and the node itself.
+pub async fn traverse<F, Fut>(root: Cid, path: &[String], fetch: F) -> Result<(Cid, Ipld), Error>
+where
+    F: Fn(Cid) -> Fut,
+    Fut: Future<Output = Result<Bytes, Error>>,
+{
+    let mut cid = root;
+    let mut node = decode(&cid, &fetch(cid).await?)?;
+    for segment in path.iter().filter(|segment| !segment.is_empty()) {
+        let next = match &node {
+            Ipld::Map(map) => map
+                .get(segment)
+                .or_else(|| dag_pb_named_link(&cid, map, segment)),
+            Ipld::List(list) => segment.parse::<usize>().ok().and_then(|i| list.get(i)),
+            _ => None,
+        }
+        .ok_or(Error::NotFound)?
+        .clone();
+        node = match next {
+            Ipld::Link(link) => {
+                cid = link;
+                decode(&cid, &fetch(cid).await?)?
+            }
+            next => next,
+        };
+    }
+    Ok((cid, node))
+}
+
+// dag-pb nodes may also be pathed by the names of their links.
+fn dag_pb_named_link<'a>(
+    cid: &Cid,
+    node: &'a BTreeMap<String, Ipld>,
+    name: &str,
+) -> Option<&'a Ipld> {
+    if cid.codec() != 0x70 {
+        return None;
+    }
+    match node.get("Links") {
+        Some(Ipld::List(links)) => links.iter().find_map(|link| match link {
+            Ipld::Map(link) if link.get("Name") == Some(&Ipld::String(name.to_string())) => {
+                link.get("Hash")
+            }
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use futures_util::TryStreamExt;
+    use libipld::{ipld, json::DagJsonCodec};
+    use unimock::{matching, MockFn, Unimock};
+
+    use super::*;
+    use crate::{testing::cbor_block, IpfsDepMock};
+
+    #[tokio::test]
+    async fn test_traverse() {
+        let (leaf, leaf_bytes) = cbor_block(&ipld!({"payload": {"x": [1, 2, 3]}}));
+        let (root, root_bytes) = cbor_block(&ipld!({"link": leaf}));
+        let blocks = HashMap::from([(leaf, leaf_bytes), (root, root_bytes)]);
+        let fetch = |cid: Cid| {
+            let block = blocks.get(&cid).cloned();
+            async move { block.ok_or(Error::NotFound) }
+        };
+
+        let path: Vec<String> = vec!["link".into(), "payload".into(), "x".into(), "1".into()];
+        let (cid, node) = traverse(root, &path, fetch).await.unwrap();
+        assert_eq!(leaf, cid);
+        assert_eq!(Ipld::Integer(2), node);
+
+        let (cid, node) = traverse(root, &["link".to_string()], fetch).await.unwrap();
+        assert_eq!(leaf, cid);
+        assert_eq!(ipld!({"payload": {"x": [1, 2, 3]}}), node);
+
+        let path: Vec<String> = vec!["link".into(), "missing".into()];
+        assert!(matches!(
+            traverse(root, &path, fetch).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_traverse_dag_jose_and_dag_pb() {
+        let (leaf, leaf_bytes) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let mut pb_bytes: Vec<u8> = Vec::new();
+        ipld!({"Links": [{"Hash": leaf, "Name": "leaf", "Tsize": leaf_bytes.len() as u64}]})
+            .encode(DagPbCodec, &mut pb_bytes)
+            .unwrap();
+        let pb = Cid::new_v1(0x70, Code::Sha2_256.digest(&pb_bytes));
+        // The payload of a dag-jose JWS envelope is exposed as the link of the decoded node.
+        let mut jose_bytes: Vec<u8> = Vec::new();
+        ipld!({
+            "payload": Ipld::Bytes(pb.to_bytes()),
+            "signatures": [{
+                "protected": Ipld::Bytes(br#"{"alg":"EdDSA"}"#.to_vec()),
+                "signature": Ipld::Bytes(vec![0; 64]),
+            }],
+        })
+        .encode(DagCborCodec, &mut jose_bytes)
+        .unwrap();
+        let jose = Cid::new_v1(0x85, Code::Sha2_256.digest(&jose_bytes));
+        let blocks = HashMap::from([
+            (leaf, leaf_bytes),
+            (pb, pb_bytes.into()),
+            (jose, jose_bytes.into()),
+        ]);
+        let fetch = |cid: Cid| {
+            let block = blocks.get(&cid).cloned();
+            async move { block.ok_or(Error::NotFound) }
+        };
+
+        // dag-pb links are followed by name
+        let path: Vec<String> = vec!["link".into(), "leaf".into(), "x".into(), "1".into()];
+        let (cid, node) = traverse(jose, &path, fetch).await.unwrap();
+        assert_eq!(leaf, cid);
+        assert_eq!(Ipld::Integer(2), node);
+
+        // and by position in the Links list
+        let path: Vec<String> = vec!["link".into(), "Links".into(), "0".into(), "Hash".into()];
+        let (cid, node) = traverse(jose, &path, fetch).await.unwrap();
+        assert_eq!(leaf, cid);
+        assert_eq!(ipld!({"x": [1, 2, 3]}), node);
+
+        let (cid, node) = traverse(jose, &["link".to_string()], fetch).await.unwrap();
+        assert_eq!(pb, cid);
+        assert!(matches!(node, Ipld::Map(map) if map.contains_key("Links")));
+
+        let path: Vec<String> = vec!["link".into(), "missing".into()];
+        assert!(matches!(
+            traverse(jose, &path, fetch).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_put_nested_links() {
+        let a =
+            Cid::from_str("bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724").unwrap();
+        let b =
+            Cid::from_str("bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom").unwrap();
+        let c = Cid::from_str("QmQPeNsJPyVWPFDVHb77w8G42Fvo15z4bG2X8D2GhfbSXc").unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(matching!((_, _, links) if *links == vec![a, b, c]))
+                .returns(Ok(())),
+        );
+        let input = format!(
+            r#"{{"a":{{"/":"{a}"}},"list":[{{"nested":{{"/":"{b}"}}}}],"map":{{"x":{{"y":{{"/":"{c}"}}}}}}}}"#
+        );
+        put(
+            mock,
+            DagJsonCodec,
+            DagCborCodec,
+            &mut Cursor::new(input.as_bytes()),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_dag_jose_links() {
+        // Construct a dag-jose JWS envelope whose payload is the Cid of a linked dag-cbor block.
+        let (payload, _) = cbor_block(&ipld!({"hello": "world"}));
+        let envelope = ipld!({
+            "payload": Ipld::Bytes(payload.to_bytes()),
+            "signatures": [{
+                "protected": Ipld::Bytes(br#"{"alg":"EdDSA"}"#.to_vec()),
+                "signature": Ipld::Bytes(vec![0; 64]),
+            }],
+        });
+        let mut input: Vec<u8> = Vec::new();
+        envelope.encode(DagCborCodec, &mut input).unwrap();
+
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(
+                    matching!((cid, _, links) if cid.codec() == 0x85 && *links == vec![payload]),
+                )
+                .returns(Ok(())),
+        );
+        put(mock, DagJoseCodec, DagJoseCodec, &mut Cursor::new(input))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_import() {
+        let (leaf, leaf_bytes) = cbor_block(&ipld!({"leaf": true}));
+        let (root, root_bytes) = cbor_block(&ipld!({"a": leaf, "b": leaf}));
+        let blocks = HashMap::from([(leaf, leaf_bytes.clone()), (root, root_bytes.clone())]);
+        let mock = Unimock::new(
+            IpfsDepMock::block_get
+                .each_call(matching!(_))
+                .answers(move |cid| blocks.get(&cid).cloned().ok_or(Error::NotFound)),
+        );
+        let car: Vec<Bytes> = export(mock, root).try_collect().await.unwrap();
+        let car = car.concat();
+
+        let mock = Unimock::new((
+            IpfsDepMock::put
+                .next_call(matching!((c, b, _) if *c == root && *b == root_bytes))
+                .returns(Ok(())),
+            IpfsDepMock::put
+                .next_call(matching!((c, b, _) if *c == leaf && *b == leaf_bytes))
+                .returns(Ok(())),
+        ));
+        let roots = import(
+            mock,
+            &PinStore::memory(),
+            futures_util::io::Cursor::new(car),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            vec![root],
+            roots.iter().map(|root| root.cid).collect::<Vec<Cid>>()
+        );
+        assert!(roots[0].pin_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_bad_hash() {
+        let (root, _) = cbor_block(&ipld!({"leaf": true}));
+        let mut car = car::header(&[root]).unwrap().to_vec();
+        car.extend(car::section(&root, b"not the block data"));
+        assert!(matches!(
+            import(
+                Unimock::new(()),
+                &PinStore::memory(),
+                futures_util::io::Cursor::new(car),
+                false
+            )
+            .await,
+            Err(Error::Invalid(_))
+        ));
+    }
+}
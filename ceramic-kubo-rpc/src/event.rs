@@ -0,0 +1,195 @@
+//! Implements decoding of Ceramic events stored in IPFS.
+use anyhow::anyhow;
+use ceramic_core::event::{Anchor, AnchorProof, Event, Genesis, Jws, Payload, Signed};
+use iroh_api::{Cid, IpfsPath};
+use libipld::Ipld;
+
+use crate::{error::Error, IpfsDep};
+
+/// Get a Ceramic event, fetching the blocks it links to, i.e. signed payloads and anchor proofs.
+#[tracing::instrument(skip(client))]
+pub async fn get<T>(client: T, cid: Cid) -> Result<Event, Error>
+where
+    T: IpfsDep,
+{
+    let node = get_node(&client, cid).await?;
+    match cid.codec() {
+        // dag-jose
+        0x85 => {
+            let jws = Jws::try_from(&node).map_err(Error::Invalid)?;
+            let payload = get_node(&client, jws.link).await?;
+            let payload = Payload::try_from(&payload).map_err(Error::Invalid)?;
+            Ok(Event::Signed(Signed { jws, payload }))
+        }
+        // dag-cbor
+        0x71 => {
+            if is_anchor(&node) {
+                let anchor = Anchor::try_from(&node).map_err(Error::Invalid)?;
+                let proof = get_node(&client, anchor.proof).await?;
+                let proof = AnchorProof::try_from(&proof).map_err(Error::Invalid)?;
+                Ok(Event::Anchor(anchor, proof))
+            } else {
+                Ok(Event::Genesis(
+                    Genesis::try_from(&node).map_err(Error::Invalid)?,
+                ))
+            }
+        }
+        codec => Err(Error::Invalid(anyhow!(
+            "unsupported event codec 0x{:x}",
+            codec
+        ))),
+    }
+}
+
+async fn get_node<T>(client: &T, cid: Cid) -> Result<Ipld, Error>
+where
+    T: IpfsDep,
+{
+    let (_, node) = client.get(&IpfsPath::from_cid(cid)).await?;
+    Ok(node)
+}
+
+// Unsigned commits are either genesis commits or anchor commits,
+// only anchor commits have a proof.
+fn is_anchor(node: &Ipld) -> bool {
+    matches!(node, Ipld::Map(map) if map.contains_key("proof"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ceramic_core::{event::GenesisHeader, StreamId};
+    use libipld::ipld;
+
+    use super::*;
+    use crate::testing::{
+        mock_blocks, DID, MID_ANCHOR, MID_DATA, MID_DATA_PAYLOAD, MID_GENESIS, MID_GENESIS_PAYLOAD,
+        MID_PROOF, MID_ROOT, UNSIGNED_GENESIS,
+    };
+
+    fn cid(fixture: (&str, &str)) -> Cid {
+        Cid::from_str(fixture.0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_signed_genesis() {
+        let event = get(
+            mock_blocks(&[MID_GENESIS, MID_GENESIS_PAYLOAD]),
+            cid(MID_GENESIS),
+        )
+        .await
+        .unwrap();
+        assert_eq!(None, event.id());
+        assert_eq!(None, event.prev());
+        let signed = match event {
+            Event::Signed(signed) => signed,
+            event => panic!("expected signed event, got {:?}", event),
+        };
+        assert_eq!(cid(MID_GENESIS_PAYLOAD), signed.jws.link);
+        assert_eq!(1, signed.jws.signatures.len());
+        assert_eq!(64, signed.jws.signatures[0].signature.len());
+        assert_eq!(
+            Payload::Genesis(Genesis {
+                header: GenesisHeader {
+                    controllers: vec![DID.to_string()],
+                    sep: Some("model".to_string()),
+                    model: Some(
+                        StreamId::from_str(
+                            "kjzl6hvfrbw6c74hccl7t8237c7txblexlxhns30kyrp687rk7n4nw0vl0wx130"
+                        )
+                        .unwrap()
+                    ),
+                    unique: Some(Ipld::Bytes(
+                        hex::decode("a1b2c3d4e5f60718293a4b5c").unwrap()
+                    )),
+                    ..Default::default()
+                },
+                data: Some(ipld!({"name": "alice", "age": 30})),
+            }),
+            signed.payload
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signed_data() {
+        let event = get(mock_blocks(&[MID_DATA, MID_DATA_PAYLOAD]), cid(MID_DATA))
+            .await
+            .unwrap();
+        assert_eq!(Some(cid(MID_GENESIS)), event.id());
+        assert_eq!(Some(cid(MID_GENESIS)), event.prev());
+        let data = match event {
+            Event::Signed(Signed {
+                payload: Payload::Data(data),
+                ..
+            }) => data,
+            event => panic!("expected signed data event, got {:?}", event),
+        };
+        assert!(data.header.is_empty());
+        assert_eq!(
+            ipld!([{"op": "replace", "path": "/age", "value": 31}]),
+            data.data
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anchor() {
+        let event = get(mock_blocks(&[MID_ANCHOR, MID_PROOF]), cid(MID_ANCHOR))
+            .await
+            .unwrap();
+        assert_eq!(
+            Event::Anchor(
+                Anchor {
+                    id: cid(MID_GENESIS),
+                    prev: cid(MID_DATA),
+                    proof: cid(MID_PROOF),
+                    path: "0".to_string(),
+                },
+                AnchorProof {
+                    chain_id: "eip155:1".to_string(),
+                    root: cid(MID_ROOT),
+                    tx_hash: Cid::from_str(
+                        "bagjqcgzadnnzzsz6ruaguurq32n5ui77shw4pfgu6vsbavqigc2bquuoirwa"
+                    )
+                    .unwrap(),
+                    tx_type: Some("f(bytes32)".to_string()),
+                }
+            ),
+            event
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_genesis() {
+        let event = get(mock_blocks(&[UNSIGNED_GENESIS]), cid(UNSIGNED_GENESIS))
+            .await
+            .unwrap();
+        assert_eq!(
+            Event::Genesis(Genesis {
+                header: GenesisHeader {
+                    controllers: vec![DID.to_string()],
+                    family: Some("IDX".to_string()),
+                    ..Default::default()
+                },
+                data: None,
+            }),
+            event
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_payload() {
+        let err = get(mock_blocks(&[MID_DATA]), cid(MID_DATA))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_not_an_event() {
+        let err = get(mock_blocks(&[MID_ROOT]), cid(MID_ROOT))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Invalid(_)), "{:?}", err);
+    }
+}
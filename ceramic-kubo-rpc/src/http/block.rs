@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use actix_multipart::Multipart;
+use actix_web::{http::header::ContentType, web, HttpResponse, Scope};
+use anyhow::anyhow;
+use iroh_api::Cid;
+use libipld::multihash::Code;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block,
+    error::Error,
+    http::{read_file, AppState},
+    IpfsDep,
+};
+pub fn scope<T>() -> Scope
+where
+    T: IpfsDep + 'static,
+{
+    web::scope("/block")
+        .service(web::resource("/get").route(web::post().to(block_get::<T>)))
+        .service(web::resource("/put").route(web::post().to(block_put::<T>)))
+        .service(web::resource("/stat").route(web::post().to(block_stat::<T>)))
+}
+
+// used to provide default to query structs
+fn raw() -> String {
+    "raw".to_string()
+}
+fn sha2_256() -> String {
+    "sha2-256".to_string()
+}
+fn full_length() -> i32 {
+    -1
+}
+
+fn parse_codec(codec: &str) -> Result<u64, Error> {
+    match codec {
+        "raw" => Ok(block::RAW),
+        "dag-pb" => Ok(0x70),
+        "dag-cbor" => Ok(0x71),
+        "dag-jose" => Ok(0x85),
+        _ => Err(Error::Invalid(anyhow!(
+            "unsupported cid-codec \"{}\"",
+            codec
+        ))),
+    }
+}
+
+fn parse_mhtype(mhtype: &str) -> Result<Code, Error> {
+    match mhtype {
+        "sha2-256" => Ok(Code::Sha2_256),
+        "sha2-512" => Ok(Code::Sha2_512),
+        _ => Err(Error::Invalid(anyhow!("unsupported mhtype \"{}\"", mhtype))),
+    }
+}
+
+#[derive(Serialize)]
+struct BlockResponse {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "Size")]
+    size: u64,
+}
+
+impl BlockResponse {
+    fn into_http_response(self) -> Result<HttpResponse, Error> {
+        let body = serde_json::to_vec(&self).map_err(|e| Error::Internal(e.into()))?;
+        Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GetQuery {
+    arg: String,
+}
+
+#[tracing::instrument(skip(data))]
+async fn block_get<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<GetQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let cid = Cid::from_str(query.arg.as_str()).map_err(|e| Error::Invalid(e.into()))?;
+    let bytes = block::get(data.api.clone(), cid).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::octet_stream())
+        .body(bytes))
+}
+
+#[derive(Debug, Deserialize)]
+struct PutQuery {
+    #[serde(rename = "cid-codec", default = "raw")]
+    cid_codec: String,
+    #[serde(default = "sha2_256")]
+    mhtype: String,
+    #[serde(default = "full_length")]
+    mhlen: i32,
+}
+
+#[tracing::instrument(skip(data, payload))]
+async fn block_put<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<PutQuery>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let cid_codec = parse_codec(query.cid_codec.as_str())?;
+    let mhtype = parse_mhtype(query.mhtype.as_str())?;
+    let mhlen = if query.mhlen < 0 {
+        None
+    } else {
+        Some(
+            u8::try_from(query.mhlen)
+                .map_err(|_| Error::Invalid(anyhow!("invalid mhlen {}", query.mhlen)))?,
+        )
+    };
+
+    let input_bytes = read_file(&mut payload).await?;
+
+    let size = input_bytes.len() as u64;
+    let cid = block::put(data.api.clone(), cid_codec, mhtype, mhlen, input_bytes).await?;
+    return BlockResponse {
+        key: cid.to_string(),
+        size,
+    }
+    .into_http_response();
+}
+
+#[derive(Debug, Deserialize)]
+struct StatQuery {
+    arg: String,
+}
+
+#[tracing::instrument(skip(data))]
+async fn block_stat<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<StatQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let cid = Cid::from_str(query.arg.as_str()).map_err(|e| Error::Invalid(e.into()))?;
+    let size = block::stat(data.api.clone(), cid).await?;
+    BlockResponse {
+        key: cid.to_string(),
+        size,
+    }
+    .into_http_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    use crate::http::tests::{assert_body_binary, assert_body_json, build_server};
+
+    use actix_multipart_rfc7578::client::multipart;
+    use actix_web::{body, test};
+    use expect_test::expect;
+    use iroh_api::Bytes;
+    use unimock::MockFn;
+    use unimock::{matching, Unimock};
+
+    use crate::IpfsDepMock;
+
+    #[actix_web::test]
+    async fn test_block_get() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == cid))
+                .returns(Ok(Bytes::from_static(b"hello world"))),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/block/get?arg=bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            "application/octet-stream",
+            resp.headers().get("Content-Type").unwrap()
+        );
+        assert_body_binary(resp.into_body(), expect!["68656c6c6f20776f726c64"]).await;
+    }
+
+    #[actix_web::test]
+    async fn test_block_put() {
+        let mock = Unimock::new(
+            // Expect call to put with raw cid and no links
+            IpfsDepMock::put
+                .next_call(matching!((c, _, links) if *c == Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap() && links.is_empty()))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new("hello world"), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/block/put")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Key": "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+                  "Size": 11
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_block_put_dag_cbor() {
+        // Test data from:
+        // https://ipld.io/specs/codecs/dag-json/fixtures/cross-codec/#array-mixed
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(matching!((c, _, _) if *c == Cid::from_str("bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724").unwrap()))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        let file_bytes = hex::decode("8c1b0016db6db6db6db71a000100001901f40200202238ff3aa5f702b33b0016db6db6db6db74261316fc48c6175657320c39f76c49b746521").unwrap();
+        form.add_reader_file("file", Cursor::new(file_bytes), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/block/put?cid-codec=dag-cbor&mhtype=sha2-256")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Key": "bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724",
+                  "Size": 57
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_block_stat() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::block_size
+                .next_call(matching!((c) if *c == cid))
+                .returns(Ok(11)),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/block/stat?arg=bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Key": "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+                  "Size": 11
+                }"#]],
+        )
+        .await;
+    }
+}
@@ -1,14 +1,20 @@
-use std::{io::Cursor, str::FromStr};
+use std::{io, io::Cursor, str::FromStr};
 
 use actix_multipart::Multipart;
 use actix_web::{http::header::ContentType, web, HttpResponse, Scope};
 use anyhow::anyhow;
-use futures_util::StreamExt;
-use iroh_api::IpfsPath;
+use dag_jose::DagJoseCodec;
+use futures_util::{StreamExt, TryStreamExt};
+use iroh_api::{Cid, IpfsPath};
 use libipld::{cbor::DagCborCodec, ipld, json::DagJsonCodec, prelude::Encode};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{dag, error::Error, http::AppState, IpfsDep};
+use crate::{
+    dag,
+    error::Error,
+    http::{read_file, AppState},
+    IpfsDep,
+};
 pub fn scope<T>() -> Scope
 where
     T: IpfsDep + 'static,
@@ -17,10 +23,13 @@ where
         .service(web::resource("/get").route(web::post().to(dag_get::<T>)))
         .service(web::resource("/put").route(web::post().to(dag_put::<T>)))
         .service(web::resource("/resolve").route(web::post().to(resolve::<T>)))
+        .service(web::resource("/import").route(web::post().to(import::<T>)))
+        .service(web::resource("/export").route(web::post().to(export::<T>)))
 }
 
 const DAG_CBOR: &str = "dag-cbor";
 const DAG_JSON: &str = "dag-json";
+const DAG_JOSE: &str = "dag-jose";
 
 fn dag_cbor() -> String {
     DAG_CBOR.to_string()
@@ -29,6 +38,9 @@ fn dag_cbor() -> String {
 fn dag_json() -> String {
     DAG_JSON.to_string()
 }
+fn pin_roots() -> bool {
+    true
+}
 
 #[derive(Debug, Deserialize)]
 struct GetQuery {
@@ -66,6 +78,9 @@ struct PutQuery {
     store_codec: String,
     #[serde(rename = "input-codec", default = "dag_json")]
     input_codec: String,
+    // Only dag-jose nodes carry signatures, other store codecs ignore this flag.
+    #[serde(rename = "verify-signatures", default)]
+    verify_signatures: bool,
 }
 
 #[tracing::instrument(skip(data, payload))]
@@ -77,55 +92,62 @@ async fn dag_put<T>(
 where
     T: IpfsDep,
 {
-    while let Some(item) = payload.next().await {
-        let mut field = item.map_err(|e| {
-            Error::Internal(Into::<anyhow::Error>::into(e).context("reading multipart field"))
-        })?;
-        if field.name() == "file" {
-            let mut input_bytes: Vec<u8> = Vec::new();
-            while let Some(chunk) = field.next().await {
-                input_bytes.extend(
-                    &chunk
-                        .map_err(|e| {
-                            Error::Internal(
-                                Into::<anyhow::Error>::into(e).context("reading multipart chunk"),
-                            )
-                        })?
-                        .to_vec(),
-                )
-            }
+    let input_bytes = read_file(&mut payload).await?;
 
-            let cid = match (query.input_codec.as_str(), query.store_codec.as_str()) {
-                (DAG_JSON, DAG_CBOR) => {
-                    dag::put(
-                        data.api.clone(),
-                        DagJsonCodec,
-                        DagCborCodec,
-                        &mut Cursor::new(input_bytes),
-                    )
-                    .await?
-                }
-                _ => {
-                    return Err(Error::Invalid(anyhow!(
-                        "unsupported input-codec, store-codec combination \"{}\", \"{}\"",
-                        query.input_codec,
-                        query.store_codec,
-                    )));
-                }
-            };
-
-            let response = ipld!({
-                "Cid": cid,
-            });
-
-            let mut data = Vec::new();
-            response.encode(DagJsonCodec, &mut data).unwrap();
-            return Ok(HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .body(data));
+    let cid = match (query.input_codec.as_str(), query.store_codec.as_str()) {
+        (DAG_JSON, DAG_CBOR) => {
+            dag::put(
+                data.api.clone(),
+                DagJsonCodec,
+                DagCborCodec,
+                &mut Cursor::new(input_bytes),
+            )
+            .await?
         }
-    }
-    Err(Error::Invalid(anyhow!("missing multipart field 'file'")))
+        (DAG_CBOR, DAG_CBOR) => {
+            dag::put(
+                data.api.clone(),
+                DagCborCodec,
+                DagCborCodec,
+                &mut Cursor::new(input_bytes),
+            )
+            .await?
+        }
+        // Raw JOSE JSON, i.e. the general JWS/JWE JSON serialization
+        (DAG_JSON, DAG_JOSE) => {
+            let mut input = Cursor::new(input_bytes);
+            if query.verify_signatures {
+                dag::verify_signatures(DagJsonCodec, &mut input)?;
+            }
+            dag::put(data.api.clone(), DagJsonCodec, DagJoseCodec, &mut input).await?
+        }
+        // The dag-jose encoding is itself dag-cbor, so we decode dag-cbor input
+        // as dag-jose in order to validate the JOSE envelope.
+        (DAG_CBOR, DAG_JOSE) | (DAG_JOSE, DAG_JOSE) => {
+            let mut input = Cursor::new(input_bytes);
+            if query.verify_signatures {
+                dag::verify_signatures(DagJoseCodec, &mut input)?;
+            }
+            dag::put(data.api.clone(), DagJoseCodec, DagJoseCodec, &mut input).await?
+        }
+        _ => {
+            return Err(Error::Invalid(anyhow!(
+                "unsupported input-codec, store-codec combination \"{}\", \"{}\"",
+                query.input_codec,
+                query.store_codec,
+            )));
+        }
+    };
+
+    let response = ipld!({
+        "Cid": cid,
+    });
+
+    let mut data = Vec::new();
+    response.encode(DagJsonCodec, &mut data).unwrap();
+    return Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(data));
 }
 
 #[derive(Debug, Deserialize)]
@@ -156,6 +178,94 @@ where
         .body(data))
 }
 
+#[derive(Debug, Deserialize)]
+struct ImportQuery {
+    #[serde(rename = "pin-roots", default = "pin_roots")]
+    pin_roots: bool,
+}
+
+#[tracing::instrument(skip(data, payload))]
+async fn import<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<ImportQuery>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    #[derive(Serialize)]
+    struct ImportResponse {
+        #[serde(rename = "Root")]
+        root: Root,
+    }
+
+    #[derive(Serialize)]
+    struct Root {
+        #[serde(rename = "Cid")]
+        cid: CidJson,
+        #[serde(rename = "PinErrorMsg")]
+        pin_error_msg: String,
+    }
+
+    #[derive(Serialize)]
+    struct CidJson {
+        #[serde(rename = "/")]
+        cid: String,
+    }
+
+    let mut body = Vec::new();
+    // Each file part of the request is a separate CAR file
+    while let Some(item) = payload.next().await {
+        let field = item.map_err(|e| {
+            Error::Internal(Into::<anyhow::Error>::into(e).context("reading multipart field"))
+        })?;
+        let reader = field
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+            .into_async_read();
+        let roots = dag::import(data.api.clone(), &data.pins, reader, query.pin_roots).await?;
+        for root in roots {
+            serde_json::to_writer(
+                &mut body,
+                &ImportResponse {
+                    root: Root {
+                        cid: CidJson {
+                            cid: root.cid.to_string(),
+                        },
+                        pin_error_msg: root
+                            .pin_error
+                            .map(|err| err.to_string())
+                            .unwrap_or_default(),
+                    },
+                },
+            )
+            .map_err(|e| Error::Internal(e.into()))?;
+            body.push(b'\n');
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    arg: String,
+}
+
+#[tracing::instrument(skip(data))]
+async fn export<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep + 'static,
+{
+    let root = Cid::from_str(query.arg.as_str()).map_err(|e| Error::Invalid(e.into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::octet_stream())
+        .streaming(dag::export(data.api.clone(), root)))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -166,7 +276,11 @@ mod tests {
     use actix_multipart_rfc7578::client::multipart;
     use actix_web::{body, test};
     use expect_test::expect;
-    use iroh_api::{Bytes, Cid};
+    use iroh_api::Bytes;
+    use libipld::{
+        multihash::{Code, MultihashDigest},
+        Ipld,
+    };
     use unimock::MockFn;
     use unimock::{matching, Unimock};
 
@@ -176,13 +290,15 @@ mod tests {
     async fn test_dag_get_json() {
         // Test data from:
         // https://ipld.io/specs/codecs/dag-pb/fixtures/cross-codec/#dagpb_data_some
-        let bytes: Bytes = hex::decode("0a050001020304")
-            .expect("should be valid hex data")
-            .into();
-        let mock = Unimock::new(IpfsDepMock::get.some_call(matching!(_)).returns(Ok((
-            Cid::try_from("bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom").unwrap(),
-            bytes,
-        ))));
+        let cid =
+            Cid::try_from("bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom").unwrap();
+        let bytes = hex::decode("0a050001020304").expect("should be valid hex data");
+        let node = dag::decode(&cid, &bytes).unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::get
+                .some_call(matching!(_))
+                .returns(Ok((cid, node))),
+        );
         let server = build_server(mock).await;
         let req = test::TestRequest::post()
             .uri("/dag/get?arg=bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom")
@@ -212,13 +328,15 @@ mod tests {
     async fn test_dag_get_cbor() {
         // Test data from:
         // https://ipld.io/specs/codecs/dag-pb/fixtures/cross-codec/#dagpb_data_some
-        let bytes: Bytes = hex::decode("0a050001020304")
-            .expect("should be valid hex data")
-            .into();
-        let mock = Unimock::new(IpfsDepMock::get.some_call(matching!(_)).returns(Ok((
-            Cid::try_from("bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom").unwrap(),
-            bytes,
-        ))));
+        let cid =
+            Cid::try_from("bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom").unwrap();
+        let bytes = hex::decode("0a050001020304").expect("should be valid hex data");
+        let node = dag::decode(&cid, &bytes).unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::get
+                .some_call(matching!(_))
+                .returns(Ok((cid, node))),
+        );
         let server = build_server(mock).await;
         let req = test::TestRequest::post()
             .uri("/dag/get?arg=bafybeibazl2z4vqp2tmwcfag6wirmtpnomxknqcgrauj7m2yisrz3qjbom&output-codec=dag-cbor")
@@ -281,6 +399,175 @@ mod tests {
         )
         .await;
     }
+    #[actix_web::test]
+    async fn test_dag_put_jose() {
+        // Construct the dag-cbor encoding of a JWS whose payload is a Cid
+        let payload =
+            Cid::from_str("bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724").unwrap();
+        let jws = ipld!({
+            "payload": Ipld::Bytes(payload.to_bytes()),
+            "signatures": [{
+                "protected": Ipld::Bytes(br#"{"alg":"EdDSA"}"#.to_vec()),
+                "signature": Ipld::Bytes(vec![0; 64]),
+            }],
+        });
+        let mut file_bytes = Vec::new();
+        jws.encode(DagCborCodec, &mut file_bytes).unwrap();
+
+        let mock = Unimock::new(
+            // Expect call to put with dag-jose cid
+            IpfsDepMock::put
+                .next_call(matching!((c, _, links) if c.codec() == 0x85 && *links == vec![payload]))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new(file_bytes), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/put?store-codec=dag-jose&input-codec=dag-cbor")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        let body_json: serde_json::Value =
+            serde_json::from_slice(body::to_bytes(resp.into_body()).await.unwrap().as_ref())
+                .unwrap();
+        let cid = Cid::from_str(body_json["Cid"]["/"].as_str().unwrap()).unwrap();
+        assert_eq!(0x85, cid.codec());
+    }
+
+    // Construct the dag-cbor encoding of a JWS signed by a did:key generated for these tests.
+    fn signed_jws(signature: &str) -> (Cid, Vec<u8>) {
+        let payload =
+            Cid::from_str("bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae").unwrap();
+        let jws = ipld!({
+            "payload": Ipld::Bytes(payload.to_bytes()),
+            "signatures": [{
+                "protected": Ipld::Bytes(br#"{"alg":"EdDSA","kid":"did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}"#.to_vec()),
+                "signature": Ipld::Bytes(hex::decode(signature).unwrap()),
+            }],
+        });
+        let mut file_bytes = Vec::new();
+        jws.encode(DagCborCodec, &mut file_bytes).unwrap();
+        (payload, file_bytes)
+    }
+
+    async fn put_verified(mock: Unimock, file_bytes: Vec<u8>) -> actix_web::dev::ServiceResponse {
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new(file_bytes), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/put?store-codec=dag-jose&input-codec=dag-cbor&verify-signatures=true")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        test::call_service(&server, req).await
+    }
+
+    #[actix_web::test]
+    async fn test_dag_put_verify_signatures() {
+        let (payload, file_bytes) = signed_jws("45af50b7c140b4eb925fe2dcfa150152f2f9076ae6a137565452f26f335814e037ef100fb237d5fca8664e0ee01835cceec8d4979c25c89012f0181153948303");
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(matching!((c, _, links) if c.codec() == 0x85 && *links == vec![payload]))
+                .returns(Ok(())),
+        );
+        let resp = put_verified(mock, file_bytes).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_dag_put_jose_json() {
+        let signature = "45af50b7c140b4eb925fe2dcfa150152f2f9076ae6a137565452f26f335814e037ef100fb237d5fca8664e0ee01835cceec8d4979c25c89012f0181153948303";
+        let (payload, jose_bytes) = signed_jws(signature);
+        let cid = Cid::new_v1(0x85, Code::Sha2_256.digest(&jose_bytes));
+        // The general JWS JSON serialization of the same envelope
+        let b64 = |bytes: &[u8]| multibase::Base::Base64Url.encode(bytes);
+        let file_bytes = serde_json::to_vec(&serde_json::json!({
+            "payload": b64(&payload.to_bytes()),
+            "signatures": [{
+                "protected": b64(br#"{"alg":"EdDSA","kid":"did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK#z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK"}"#),
+                "signature": b64(&hex::decode(signature).unwrap()),
+            }],
+        }))
+        .unwrap();
+
+        // Expect the dag-jose block of the envelope, linking to its payload
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(matching!((c, blob, links) if *c == cid && blob.as_ref() == jose_bytes.as_slice() && *links == vec![payload]))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new(file_bytes), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/put?store-codec=dag-jose&input-codec=dag-json&verify-signatures=true")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        let body_json: serde_json::Value =
+            serde_json::from_slice(body::to_bytes(resp.into_body()).await.unwrap().as_ref())
+                .unwrap();
+        assert_eq!(cid.to_string(), body_json["Cid"]["/"].as_str().unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_dag_put_verify_signatures_forged() {
+        // The first byte of the signature has been modified
+        let (_, file_bytes) = signed_jws("44af50b7c140b4eb925fe2dcfa150152f2f9076ae6a137565452f26f335814e037ef100fb237d5fca8664e0ee01835cceec8d4979c25c89012f0181153948303");
+        // Expect no calls to put
+        let resp = put_verified(Unimock::new(()), file_bytes).await;
+        assert_eq!(400, resp.status().as_u16());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Code": 0,
+                  "Message": "invalid: signature by did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK is not valid",
+                  "Type": "error"
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_dag_put_unsupported_codecs() {
+        let server = build_server(Unimock::new(())).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new("{}"), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/put?store-codec=dag-json&input-codec=dag-jose")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(400, resp.status().as_u16());
+    }
+
     #[actix_web::test]
     async fn test_dag_resolve() {
         // Test data uses getting started guide for IPFS:
@@ -312,4 +599,105 @@ mod tests {
         )
         .await;
     }
+
+    #[actix_web::test]
+    async fn test_dag_export_import() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mock = Unimock::new(
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == cid))
+                .returns(Ok(Bytes::from_static(b"hello world"))),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/dag/export?arg=bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        let car = body::to_bytes(resp.into_body()).await.unwrap();
+        assert_body_binary(
+            car.clone(),
+            expect!["3aa265726f6f747381d82a58250001551220b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde96776657273696f6e012f01551220b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde968656c6c6f20776f726c64"],
+        )
+        .await;
+
+        let mock = Unimock::new(
+            IpfsDepMock::put
+                .next_call(matching!((c, _, _) if *c == cid))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new(car), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/import?pin-roots=false")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Root": {
+                    "Cid": {
+                      "/": "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+                    },
+                    "PinErrorMsg": ""
+                  }
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_dag_import_pin_error() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mut car = crate::car::header(&[cid]).unwrap().to_vec();
+        car.extend(crate::car::section(&cid, b"hello world"));
+        let mock = Unimock::new((
+            IpfsDepMock::put
+                .next_call(matching!((c, _, _) if *c == cid))
+                .returns(Ok(())),
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == cid))
+                .returns(Err(Error::NotFound)),
+        ));
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new(car), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/dag/import?pin-roots=true")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Root": {
+                    "Cid": {
+                      "/": "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+                    },
+                    "PinErrorMsg": "not found"
+                  }
+                }"#]],
+        )
+        .await;
+    }
 }
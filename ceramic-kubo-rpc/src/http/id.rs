@@ -0,0 +1,123 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Resource};
+use multiaddr::Protocol;
+use multibase::Base;
+use serde::Serialize;
+
+use crate::{error::Error, http::AppState, id, IpfsDep};
+pub fn resource<T>() -> Resource
+where
+    T: IpfsDep + 'static,
+{
+    web::resource("/id").route(web::post().to(id::<T>))
+}
+
+#[tracing::instrument(skip(data))]
+async fn id<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let info = id::id(data.api.clone()).await?;
+
+    #[derive(Serialize)]
+    struct IdResponse {
+        #[serde(rename = "ID")]
+        id: String,
+        #[serde(rename = "PublicKey")]
+        public_key: String,
+        #[serde(rename = "Addresses")]
+        addresses: Vec<String>,
+        #[serde(rename = "AgentVersion")]
+        agent_version: String,
+        #[serde(rename = "ProtocolVersion")]
+        protocol_version: String,
+        #[serde(rename = "Protocols")]
+        protocols: Vec<String>,
+    }
+
+    // Peer Ids of Ed25519 keys inline the protobuf encoded public key using the identity hash.
+    let public_key = if info.peer_id.as_ref().code() == 0 {
+        Base::Base64Pad.encode(info.peer_id.as_ref().digest())
+    } else {
+        "".to_string()
+    };
+
+    let id_resp = IdResponse {
+        id: info.peer_id.to_string(),
+        public_key,
+        addresses: info
+            .listen_addrs
+            .iter()
+            .chain(info.observed_addrs.iter())
+            .map(|addr| {
+                addr.clone()
+                    .with(Protocol::P2p(info.peer_id.into()))
+                    .to_string()
+            })
+            .collect(),
+        agent_version: info.agent_version,
+        protocol_version: info.protocol_version,
+        protocols: info.protocols,
+    };
+    let body = serde_json::to_vec(&id_resp).map_err(|e| Error::Internal(e.into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    use crate::http::tests::{assert_body_json, build_server};
+
+    use actix_web::test;
+    use expect_test::expect;
+    use iroh_api::{Multiaddr, PeerId};
+    use unimock::MockFn;
+    use unimock::{matching, Unimock};
+
+    use crate::{IpfsDepMock, PeerInfo};
+
+    #[actix_web::test]
+    async fn test_id() {
+        let mock = Unimock::new(
+            IpfsDepMock::id
+                .next_call(matching!(()))
+                .returns(Ok(PeerInfo {
+                    peer_id: PeerId::from_str(
+                        "12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t",
+                    )
+                    .unwrap(),
+                    listen_addrs: vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/4001").unwrap()],
+                    observed_addrs: vec![Multiaddr::from_str("/ip4/1.1.1.1/tcp/4001").unwrap()],
+                    protocol_version: "ipfs/0.1.0".to_string(),
+                    agent_version: "iroh/0.2.0".to_string(),
+                    protocols: vec!["/ipfs/bitswap/1.2.0".to_string()],
+                })),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post().uri("/id").to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "ID": "12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t",
+                  "PublicKey": "CAESIFot9i9WIcZd4hqOc/GTPC9upHiq2AIavNJdTxJPc6y1",
+                  "Addresses": [
+                    "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t",
+                    "/ip4/1.1.1.1/tcp/4001/p2p/12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t"
+                  ],
+                  "AgentVersion": "iroh/0.2.0",
+                  "ProtocolVersion": "ipfs/0.1.0",
+                  "Protocols": [
+                    "/ipfs/bitswap/1.2.0"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+}
@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse, Resource};
+
+use crate::{error::Error, metrics};
+
+pub fn resource() -> Resource {
+    web::resource("/metrics").route(web::get().to(get_metrics))
+}
+
+#[tracing::instrument]
+async fn get_metrics() -> Result<HttpResponse, Error> {
+    let mut body = Vec::new();
+    metrics::encode(&mut body).map_err(|e| Error::Internal(e.into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::build_server;
+
+    use actix_web::{body, test, App};
+    use unimock::Unimock;
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let server = build_server(Unimock::new(())).await;
+        for uri in ["/version", "/dag/get?arg=invalid", "/dag/get"] {
+            let req = test::TestRequest::post().uri(uri).to_request();
+            test::call_service(&server, req).await;
+        }
+
+        let metrics_server = test::init_service(App::new().service(resource())).await;
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&metrics_server, req).await;
+        assert!(resp.status().is_success());
+        let body = body::to_bytes(resp.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        for line in [
+            r#"kubo_rpc_requests_total{endpoint="version"}"#,
+            r#"kubo_rpc_request_duration_seconds_count{endpoint="dag/get"}"#,
+            r#"kubo_rpc_errors_total{endpoint="dag/get",error="invalid"}"#,
+            r#"kubo_rpc_errors_total{endpoint="dag/get",error="request"}"#,
+        ] {
+            assert!(body.contains(line), "missing {} in\n{}", line, body);
+        }
+    }
+}
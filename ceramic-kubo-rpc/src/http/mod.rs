@@ -1,18 +1,28 @@
 //! Provides an http implementation of the Kubo RPC methods.
-use std::net;
+use std::{future::Future, net, time::Instant};
 
+use actix_multipart::Multipart;
 use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
     error,
     http::{header::ContentType, StatusCode},
     web, App, HttpResponse, HttpServer,
 };
+use anyhow::anyhow;
+use futures_util::StreamExt;
 use serde::Serialize;
 use tracing_actix_web::TracingLogger;
 
-use crate::{error::Error, IpfsDep};
+use crate::{error::Error, pin::PinStore, version::Version, IpfsDep};
 
+mod block;
 mod dag;
+mod id;
+mod metrics;
+mod pin;
+mod pubsub;
 mod swarm;
+mod version;
 
 #[derive(Clone)]
 struct AppState<T>
@@ -20,6 +30,33 @@ where
     T: IpfsDep,
 {
     api: T,
+    pins: PinStore,
+    version: Version,
+}
+
+// Read the contents of the multipart field named "file".
+async fn read_file(payload: &mut Multipart) -> Result<Vec<u8>, Error> {
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| {
+            Error::Internal(Into::<anyhow::Error>::into(e).context("reading multipart field"))
+        })?;
+        if field.name() == "file" {
+            let mut input_bytes: Vec<u8> = Vec::new();
+            while let Some(chunk) = field.next().await {
+                input_bytes.extend(
+                    &chunk
+                        .map_err(|e| {
+                            Error::Internal(
+                                Into::<anyhow::Error>::into(e).context("reading multipart chunk"),
+                            )
+                        })?
+                        .to_vec(),
+                )
+            }
+            return Ok(input_bytes);
+        }
+    }
+    Err(Error::Invalid(anyhow!("missing multipart field 'file'")))
 }
 
 /// Start the Kubo RPC mimic server.
@@ -27,19 +64,31 @@ where
 /// Block until shutdown.
 /// Automatically registers shutdown listeners for interrupt and kill signals.
 /// See https://actix.rs/docs/server/#graceful-shutdown
-pub async fn serve<T, A>(api: T, addrs: A) -> std::io::Result<()>
+pub async fn serve<T, A>(api: T, pins: PinStore, version: Version, addrs: A) -> std::io::Result<()>
 where
     T: IpfsDep + Send + Clone + 'static,
     A: net::ToSocketAddrs,
 {
     HttpServer::new(move || {
         App::new()
+            .wrap_fn(record_metrics)
             .wrap(TracingLogger::default())
-            .app_data(web::Data::new(AppState { api: api.clone() }))
+            .app_data(web::Data::new(AppState {
+                api: api.clone(),
+                pins: pins.clone(),
+                version: version.clone(),
+            }))
             .service(
                 web::scope("/api/v0")
+                    .service(block::scope::<T>())
                     .service(dag::scope::<T>())
-                    .service(swarm::scope::<T>()),
+                    .service(id::resource::<T>())
+                    .service(pin::scope::<T>())
+                    .service(pubsub::scope::<T>())
+                    // /repo/gc is not served, the Iroh store API cannot remove blocks.
+                    // Blocks are garbage collected offline with `ceramic-one gc`.
+                    .service(swarm::scope::<T>())
+                    .service(version::resource::<T>()),
             )
     })
     .bind(addrs)?
@@ -47,6 +96,68 @@ where
     .await
 }
 
+/// Serve the metrics of the Kubo RPC methods on `/metrics` in the Prometheus text format.
+///
+/// Block until shutdown.
+pub async fn serve_metrics<A>(addrs: A) -> std::io::Result<()>
+where
+    A: net::ToSocketAddrs,
+{
+    HttpServer::new(|| App::new().service(metrics::resource()))
+        .bind(addrs)?
+        .run()
+        .await
+}
+
+// Record the endpoint, duration and error of each request.
+fn record_metrics<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let start = Instant::now();
+    let res = srv.call(req);
+    async move {
+        let res = res.await?;
+        // Label by route pattern so that unknown paths do not each create a new label.
+        let endpoint = res
+            .request()
+            .match_pattern()
+            .map(|pattern| {
+                pattern
+                    .trim_start_matches("/api/v0")
+                    .trim_start_matches('/')
+                    .to_string()
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        let error = res.response().error().map(|err| {
+            err.as_error::<Error>()
+                .map(crate::metrics::error_label)
+                .unwrap_or("request")
+        });
+        crate::metrics::record_request(&endpoint, start.elapsed(), error);
+        Ok(res)
+    }
+}
+
+// Response of the endpoints that report a list of strings.
+#[derive(Serialize)]
+struct StringsResponse {
+    #[serde(rename = "Strings")]
+    strings: Vec<String>,
+}
+
+impl StringsResponse {
+    fn into_http_response(self) -> Result<HttpResponse, Error> {
+        let body = serde_json::to_vec(&self).map_err(|e| Error::Internal(e.into()))?;
+        Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body))
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorJson<'a> {
     #[serde(rename = "Message")]
@@ -88,22 +199,56 @@ mod tests {
         dev::ServiceResponse,
         test, web, App,
     };
-    use expect_test::Expect;
-    use unimock::Unimock;
+    use expect_test::{expect, Expect};
+    use iroh_api::PeerId;
+
+    use crate::{memory::MemoryIpfs, PeerInfo};
 
     /// Test helper function to build a application server
-    pub async fn build_server(
-        mock: impl IpfsDep + 'static,
+    pub async fn build_server<T>(
+        api: T,
+    ) -> impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+    >
+    where
+        T: IpfsDep + 'static,
+    {
+        build_server_with_pins(api, PinStore::memory()).await
+    }
+
+    /// Test helper function to build a application server using the provided pins
+    pub async fn build_server_with_pins<T>(
+        api: T,
+        pins: PinStore,
     ) -> impl actix_web::dev::Service<
         actix_http::Request,
         Response = ServiceResponse,
         Error = actix_web::Error,
-    > {
+    >
+    where
+        T: IpfsDep + 'static,
+    {
         test::init_service(
             App::new()
-                .app_data(web::Data::new(AppState { api: mock }))
-                .service(super::dag::scope::<Unimock>())
-                .service(super::swarm::scope::<Unimock>()),
+                .wrap_fn(record_metrics)
+                .app_data(web::Data::new(AppState {
+                    api,
+                    pins,
+                    version: Version {
+                        version: "0.1.0".to_string(),
+                        commit: "git:test".to_string(),
+                        system: "test/test".to_string(),
+                    },
+                }))
+                .service(super::block::scope::<T>())
+                .service(super::dag::scope::<T>())
+                .service(super::id::resource::<T>())
+                .service(super::pin::scope::<T>())
+                .service(super::pubsub::scope::<T>())
+                .service(super::swarm::scope::<T>())
+                .service(super::version::resource::<T>()),
         )
         .await
     }
@@ -130,4 +275,54 @@ mod tests {
         let bytes = hex::encode(&body::to_bytes(body).await.unwrap());
         expect.assert_eq(&bytes);
     }
+
+    #[actix_web::test]
+    async fn test_memory_ipfs() {
+        let ipfs = MemoryIpfs::new(PeerInfo {
+            peer_id: "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp"
+                .parse::<PeerId>()
+                .unwrap(),
+            listen_addrs: vec![],
+            observed_addrs: vec![],
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "test".to_string(),
+            protocols: vec![],
+        });
+        let server = build_server(ipfs).await;
+
+        let mut form = actix_multipart_rfc7578::client::multipart::Form::default();
+        form.add_reader_file(
+            "file",
+            std::io::Cursor::new(r#"{"hello":"world","list":[1,2]}"#),
+            "",
+        );
+        let ct = form.content_type();
+        let body = body::to_bytes(actix_multipart_rfc7578::client::multipart::Body::from(form))
+            .await
+            .unwrap();
+        let req = test::TestRequest::post()
+            .uri("/dag/put")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Cid": {
+                    "/": "bafyreiad3feyfjok4hemmoxcxj76hje4ti2laspfzoxttsshd6gm3w7nyu"
+                  }
+                }"#]],
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/dag/get?arg=/ipfs/bafyreiad3feyfjok4hemmoxcxj76hje4ti2laspfzoxttsshd6gm3w7nyu/list/1")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(resp.into_body(), expect!["2"]).await;
+    }
 }
@@ -0,0 +1,256 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Scope};
+use iroh_api::IpfsPath;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dag,
+    error::Error,
+    http::AppState,
+    pin::{self, PinType},
+    IpfsDep,
+};
+pub fn scope<T>() -> Scope
+where
+    T: IpfsDep + 'static,
+{
+    web::scope("/pin")
+        .service(web::resource("/add").route(web::post().to(pin_add::<T>)))
+        .service(web::resource("/rm").route(web::post().to(pin_rm::<T>)))
+        .service(web::resource("/ls").route(web::post().to(pin_ls::<T>)))
+}
+
+// used to provide default to query structs
+fn recursive() -> bool {
+    true
+}
+fn all() -> String {
+    "all".to_string()
+}
+
+#[derive(Serialize)]
+struct PinsResponse {
+    #[serde(rename = "Pins")]
+    pins: Vec<String>,
+}
+
+impl PinsResponse {
+    fn into_http_response(self) -> Result<HttpResponse, Error> {
+        let body = serde_json::to_vec(&self).map_err(|e| Error::Internal(e.into()))?;
+        Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(body))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AddQuery {
+    arg: String,
+    #[serde(default = "recursive")]
+    recursive: bool,
+}
+
+#[tracing::instrument(skip(data))]
+async fn pin_add<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<AddQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let path = IpfsPath::from_str(query.arg.as_str()).map_err(Error::Invalid)?;
+    let cid = dag::resolve(data.api.clone(), &path).await?;
+    pin::add(data.api.clone(), &data.pins, cid, query.recursive).await?;
+    PinsResponse {
+        pins: vec![cid.to_string()],
+    }
+    .into_http_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct RmQuery {
+    arg: String,
+    #[serde(default = "recursive")]
+    recursive: bool,
+}
+
+#[tracing::instrument(skip(data))]
+async fn pin_rm<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<RmQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let path = IpfsPath::from_str(query.arg.as_str()).map_err(Error::Invalid)?;
+    let cid = dag::resolve(data.api.clone(), &path).await?;
+    pin::rm(&data.pins, cid, query.recursive).await?;
+    PinsResponse {
+        pins: vec![cid.to_string()],
+    }
+    .into_http_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct LsQuery {
+    #[serde(rename = "type", default = "all")]
+    typ: String,
+}
+
+#[tracing::instrument(skip(data))]
+async fn pin_ls<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<LsQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let typ = match query.typ.as_str() {
+        "all" => None,
+        typ => Some(PinType::from_str(typ)?),
+    };
+    let pins = pin::ls(data.api.clone(), &data.pins, typ).await?;
+
+    #[derive(Serialize)]
+    struct LsResponse {
+        #[serde(rename = "Keys")]
+        keys: BTreeMap<String, Key>,
+    }
+
+    #[derive(Serialize)]
+    struct Key {
+        #[serde(rename = "Type")]
+        typ: String,
+    }
+
+    let ls_resp = LsResponse {
+        keys: pins
+            .into_iter()
+            .map(|(cid, typ)| {
+                (
+                    cid.to_string(),
+                    Key {
+                        typ: typ.to_string(),
+                    },
+                )
+            })
+            .collect(),
+    };
+    let body = serde_json::to_vec(&ls_resp).map_err(|e| Error::Internal(e.into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        http::tests::{assert_body_json, build_server_with_pins},
+        pin::PinStore,
+    };
+
+    use actix_web::test;
+    use expect_test::expect;
+    use iroh_api::{Bytes, Cid};
+    use unimock::MockFn;
+    use unimock::{matching, Unimock};
+
+    use crate::IpfsDepMock;
+
+    #[actix_web::test]
+    async fn test_pin_add() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mock = Unimock::new((
+            IpfsDepMock::resolve
+                .next_call(matching!(_))
+                .returns(Ok(vec![cid])),
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == cid))
+                .returns(Ok(Bytes::from_static(b"hello world"))),
+        ));
+        let pins = PinStore::memory();
+        let server = build_server_with_pins(mock, pins.clone()).await;
+        let req = test::TestRequest::post()
+            .uri("/pin/add?arg=bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Pins": [
+                    "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+                  ]
+                }"#]],
+        )
+        .await;
+        assert_eq!(Some(PinType::Recursive), pins.get(&cid));
+    }
+
+    #[actix_web::test]
+    async fn test_pin_rm() {
+        let cid =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let mock = Unimock::new((
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == cid))
+                .returns(Ok(Bytes::from_static(b"hello world"))),
+            IpfsDepMock::resolve
+                .next_call(matching!(_))
+                .returns(Ok(vec![cid])),
+        ));
+        let pins = PinStore::memory();
+        pin::add(mock.clone(), &pins, cid, false).await.unwrap();
+        let server = build_server_with_pins(mock, pins.clone()).await;
+        let req = test::TestRequest::post()
+            .uri("/pin/rm?arg=bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(None, pins.get(&cid));
+    }
+
+    #[actix_web::test]
+    async fn test_pin_ls() {
+        let direct =
+            Cid::from_str("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+        let recursive =
+            Cid::from_str("bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724").unwrap();
+        let mock = Unimock::new((
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == direct))
+                .returns(Ok(Bytes::from_static(b"hello world"))),
+            IpfsDepMock::block_get
+                .next_call(matching!((c) if *c == recursive))
+                .returns(Ok(hex::decode("8c1b0016db6db6db6db71a000100001901f40200202238ff3aa5f702b33b0016db6db6db6db74261316fc48c6175657320c39f76c49b746521").unwrap().into())),
+        ));
+        let pins = PinStore::memory();
+        pin::add(mock.clone(), &pins, direct, false).await.unwrap();
+        pin::add(mock.clone(), &pins, recursive, true)
+            .await
+            .unwrap();
+        let server = build_server_with_pins(mock, pins).await;
+        let req = test::TestRequest::post()
+            .uri("/pin/ls?type=direct")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Keys": {
+                    "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e": {
+                      "Type": "direct"
+                    }
+                  }
+                }"#]],
+        )
+        .await;
+    }
+}
@@ -0,0 +1,266 @@
+use actix_multipart::Multipart;
+use actix_web::{http::header::ContentType, web, HttpResponse, Scope};
+use futures_util::StreamExt;
+use iroh_api::Bytes;
+use multibase::Base;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    http::{read_file, AppState, StringsResponse},
+    pubsub, IpfsDep,
+};
+pub fn scope<T>() -> Scope
+where
+    T: IpfsDep + 'static,
+{
+    web::scope("/pubsub")
+        .service(web::resource("/ls").route(web::post().to(pubsub_ls::<T>)))
+        .service(web::resource("/peers").route(web::post().to(pubsub_peers::<T>)))
+        .service(web::resource("/pub").route(web::post().to(pubsub_pub::<T>)))
+        .service(web::resource("/sub").route(web::post().to(pubsub_sub::<T>)))
+}
+
+// Kubo encodes topics and message data using multibase.
+fn decode_topic(topic: &str) -> Result<String, Error> {
+    let (_, bytes) = multibase::decode(topic).map_err(|e| Error::Invalid(e.into()))?;
+    String::from_utf8(bytes).map_err(|e| Error::Invalid(e.into()))
+}
+fn encode(data: impl AsRef<[u8]>) -> String {
+    multibase::encode(Base::Base64Url, data)
+}
+
+#[tracing::instrument(skip(data))]
+async fn pubsub_ls<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let topics = pubsub::topics(data.api.clone()).await?;
+    StringsResponse {
+        strings: topics.iter().map(encode).collect(),
+    }
+    .into_http_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct PeersQuery {
+    arg: Option<String>,
+}
+
+#[tracing::instrument(skip(data))]
+async fn pubsub_peers<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<PeersQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let topic = query.arg.as_deref().map(decode_topic).transpose()?;
+    let peers = pubsub::peers(data.api.clone(), topic).await?;
+    StringsResponse {
+        strings: peers.iter().map(|peer| peer.to_string()).collect(),
+    }
+    .into_http_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct PubQuery {
+    arg: String,
+}
+
+#[tracing::instrument(skip(data, payload))]
+async fn pubsub_pub<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<PubQuery>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let topic = decode_topic(&query.arg)?;
+    let input_bytes = read_file(&mut payload).await?;
+
+    pubsub::publish(data.api.clone(), topic, input_bytes.into()).await?;
+    return Ok(HttpResponse::Ok().finish());
+}
+
+#[derive(Debug, Deserialize)]
+struct SubQuery {
+    arg: String,
+}
+
+#[derive(Serialize)]
+struct MessageResponse {
+    from: String,
+    data: String,
+    seqno: String,
+    #[serde(rename = "topicIDs")]
+    topic_ids: Vec<String>,
+}
+
+#[tracing::instrument(skip(data))]
+async fn pubsub_sub<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<SubQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let topic = decode_topic(&query.arg)?;
+    let messages = pubsub::subscribe(data.api.clone(), topic).await?;
+    // Stream each message as a line of JSON
+    let body = messages.map(|message| -> Result<Bytes, Error> {
+        let message = message?;
+        let mut line = serde_json::to_vec(&MessageResponse {
+            from: message
+                .from
+                .map(|peer| peer.to_string())
+                .unwrap_or_default(),
+            data: encode(&message.data),
+            seqno: encode(message.seqno.unwrap_or_default().to_be_bytes()),
+            topic_ids: vec![encode(&message.topic)],
+        })
+        .map_err(|e| Error::Internal(e.into()))?;
+        line.push(b'\n');
+        Ok(line.into())
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, str::FromStr};
+
+    use super::*;
+
+    use crate::http::tests::{assert_body_json, build_server};
+
+    use actix_multipart_rfc7578::client::multipart;
+    use actix_web::{body, test};
+    use expect_test::expect;
+    use futures_util::stream;
+    use iroh_api::PeerId;
+    use libp2p::gossipsub::{GossipsubEvent, GossipsubMessage, MessageId, TopicHash};
+    use unimock::MockFn;
+    use unimock::{matching, Unimock};
+
+    use crate::IpfsDepMock;
+
+    #[actix_web::test]
+    async fn test_pubsub_ls() {
+        let mock = Unimock::new(
+            IpfsDepMock::topics
+                .next_call(matching!(()))
+                .returns(Ok(vec!["/ceramic/mainnet".to_string()])),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post().uri("/pubsub/ls").to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Strings": [
+                    "uL2NlcmFtaWMvbWFpbm5ldA"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_pubsub_peers() {
+        let mock = Unimock::new(
+            IpfsDepMock::topic_peers
+                .next_call(matching!((Some(t)) if t == "/ceramic/mainnet"))
+                .returns(Ok(vec![
+                    PeerId::from_str("12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp")
+                        .unwrap(),
+                    PeerId::from_str("12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU")
+                        .unwrap(),
+                ])),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/pubsub/peers?arg=uL2NlcmFtaWMvbWFpbm5ldA")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Strings": [
+                    "12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU",
+                    "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn test_pubsub_pub() {
+        let mock = Unimock::new(
+            IpfsDepMock::publish
+                .next_call(matching!((t, d) if t == "/ceramic/mainnet" && d.as_ref() == b"hello"))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+
+        let mut form = multipart::Form::default();
+        form.add_reader_file("file", Cursor::new("hello"), "");
+
+        let ct = form.content_type();
+        let body = body::to_bytes(multipart::Body::from(form)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/pubsub/pub?arg=uL2NlcmFtaWMvbWFpbm5ldA")
+            .insert_header(("Content-Type", ct))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_pubsub_sub() {
+        let mock = Unimock::new(
+            IpfsDepMock::subscribe
+                .next_call(matching!((t) if t == "/ceramic/mainnet"))
+                .answers(|topic| {
+                    let source =
+                        PeerId::from_str("12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp")
+                            .unwrap();
+                    let message = |data: &str, seqno: u64| -> anyhow::Result<GossipsubEvent> {
+                        Ok(GossipsubEvent::Message {
+                            propagation_source: source,
+                            message_id: MessageId::new(data.as_bytes()),
+                            message: GossipsubMessage {
+                                source: Some(source),
+                                data: data.as_bytes().to_vec(),
+                                sequence_number: Some(seqno),
+                                topic: TopicHash::from_raw(topic.clone()),
+                            },
+                        })
+                    };
+                    Ok(stream::iter(vec![message("hello", 1), message("world", 2)]).boxed())
+                }),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/pubsub/sub?arg=uL2NlcmFtaWMvbWFpbm5ldA")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        let body = body::to_bytes(resp.into_body()).await.unwrap();
+        expect![[r#"
+            {"from":"12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp","data":"uaGVsbG8","seqno":"uAAAAAAAAAAE","topicIDs":["uL2NlcmFtaWMvbWFpbm5ldA"]}
+            {"from":"12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp","data":"ud29ybGQ","seqno":"uAAAAAAAAAAI","topicIDs":["uL2NlcmFtaWMvbWFpbm5ldA"]}
+        "#]]
+        .assert_eq(std::str::from_utf8(&body).unwrap());
+    }
+}
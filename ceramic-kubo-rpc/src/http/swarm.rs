@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 
 use actix_web::{http::header::ContentType, web, HttpResponse, Scope};
 use anyhow::anyhow;
@@ -7,7 +7,11 @@ use multiaddr::Protocol;
 use serde::Deserialize;
 use serde::Serialize;
 
-use crate::{error::Error, http::AppState, swarm, IpfsDep};
+use crate::{
+    error::Error,
+    http::{AppState, StringsResponse},
+    swarm, IpfsDep,
+};
 pub fn scope<T>() -> Scope
 where
     T: IpfsDep + 'static,
@@ -15,25 +19,55 @@ where
     web::scope("/swarm")
         .service(web::resource("/peers").route(web::post().to(swarm_peers::<T>)))
         .service(web::resource("/connect").route(web::post().to(swarm_connect::<T>)))
+        .service(web::resource("/disconnect").route(web::post().to(swarm_disconnect::<T>)))
+        .service(web::resource("/addrs").route(web::post().to(swarm_addrs::<T>)))
+        .service(web::resource("/addrs/local").route(web::post().to(swarm_addrs_local::<T>)))
+        .service(web::resource("/addrs/listen").route(web::post().to(swarm_addrs_listen::<T>)))
+}
+
+// Parse the peer Id from the /p2p component of a multiaddr.
+fn peer_id_from_multiaddr(ma: &Multiaddr) -> Result<PeerId, Error> {
+    let mh = ma
+        .iter()
+        .flat_map(|proto| {
+            if let Protocol::P2p(mh) = proto {
+                vec![mh]
+            } else {
+                vec![]
+            }
+        })
+        .next()
+        .ok_or_else(|| Error::Invalid(anyhow!("multiaddr does not contain p2p peer Id")))?;
+    PeerId::from_multihash(mh).map_err(|_e| Error::Invalid(anyhow!("invalid peer Id")))
+}
+
+#[derive(Debug, Deserialize)]
+struct PeersQuery {
+    #[serde(default)]
+    verbose: bool,
 }
 
 #[tracing::instrument(skip(data))]
-async fn swarm_peers<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+async fn swarm_peers<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<PeersQuery>,
+) -> Result<HttpResponse, Error>
 where
     T: IpfsDep,
 {
-    let peers: Vec<Peer> = swarm::peers(data.api.clone())
+    // Report each address of a peer as a separate entry
+    let peers: Vec<Peer> = swarm::peers(data.api.clone(), query.verbose)
         .await?
         .into_iter()
-        .map(|(k, v)| Peer {
-            addr: v
-                .get(0)
-                .map(|a| a.to_string())
-                .unwrap_or_else(|| "".to_string()),
-            direction: 0,
-            latency: "".to_string(),
-            muxer: "".to_string(),
-            peer: k.to_string(),
+        .flat_map(|(k, v)| {
+            v.addrs
+                .iter()
+                .map(|addr| Peer {
+                    addr: addr.to_string(),
+                    peer: k.to_string(),
+                    protocols: query.verbose.then(|| v.protocols.clone()),
+                })
+                .collect::<Vec<Peer>>()
         })
         .collect();
 
@@ -43,18 +77,16 @@ where
         peers: Vec<Peer>,
     }
 
+    // The p2p RPC of Iroh does not expose the direction, latency or muxer of its connections,
+    // they are omitted as Kubo omits them when they are empty.
     #[derive(Serialize)]
     struct Peer {
         #[serde(rename = "Addr")]
         addr: String,
-        #[serde(rename = "Direction")]
-        direction: i32,
-        #[serde(rename = "Latency")]
-        latency: String,
-        #[serde(rename = "Muxer")]
-        muxer: String,
         #[serde(rename = "Peer")]
         peer: String,
+        #[serde(rename = "Protocols", skip_serializing_if = "Option::is_none")]
+        protocols: Option<Vec<String>>,
     }
 
     let peers = PeersResponse { peers };
@@ -78,37 +110,115 @@ where
     T: IpfsDep,
 {
     let ma = Multiaddr::from_str(query.arg.as_str()).map_err(|e| Error::Invalid(e.into()))?;
-    let mh = ma
-        .iter()
-        .flat_map(|proto| {
-            if let Protocol::P2p(mh) = proto {
-                vec![mh]
-            } else {
-                vec![]
-            }
-        })
-        .next()
-        .ok_or_else(|| Error::Invalid(anyhow!("multiaddr does not contain p2p peer Id")))?;
-    let peer_id =
-        PeerId::from_multihash(mh).map_err(|_e| Error::Invalid(anyhow!("invalid peer Id")))?;
+    let peer_id = peer_id_from_multiaddr(&ma)?;
 
     swarm::connect(data.api.clone(), peer_id, vec![ma]).await?;
 
+    StringsResponse {
+        strings: vec![format!("connect {} success", peer_id)],
+    }
+    .into_http_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct DisconnectQuery {
+    arg: String,
+}
+
+#[tracing::instrument(skip(data))]
+async fn swarm_disconnect<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<DisconnectQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let ma = Multiaddr::from_str(query.arg.as_str()).map_err(|e| Error::Invalid(e.into()))?;
+    let peer_id = peer_id_from_multiaddr(&ma)?;
+
+    swarm::disconnect(data.api.clone(), peer_id).await?;
+
+    StringsResponse {
+        strings: vec![format!("disconnect {} success", peer_id)],
+    }
+    .into_http_response()
+}
+
+#[tracing::instrument(skip(data))]
+async fn swarm_addrs<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
     #[derive(Serialize)]
-    struct ConnectResponse {
-        #[serde(rename = "Strings")]
-        strings: Vec<String>,
+    struct AddrsResponse {
+        #[serde(rename = "Addrs")]
+        addrs: BTreeMap<String, Vec<String>>,
     }
 
-    let connect_resp = ConnectResponse {
-        strings: vec![format!("connect {} success", peer_id)],
+    let addrs_resp = AddrsResponse {
+        addrs: swarm::addrs(data.api.clone())
+            .await?
+            .into_iter()
+            .map(|(peer_id, addrs)| {
+                (
+                    peer_id.to_string(),
+                    addrs.iter().map(|addr| addr.to_string()).collect(),
+                )
+            })
+            .collect(),
     };
-    let body = serde_json::to_vec(&connect_resp).map_err(|e| Error::Internal(e.into()))?;
+    let body = serde_json::to_vec(&addrs_resp).map_err(|e| Error::Internal(e.into()))?;
     Ok(HttpResponse::Ok()
         .content_type(ContentType::json())
         .body(body))
 }
 
+#[derive(Debug, Deserialize)]
+struct AddrsLocalQuery {
+    #[serde(default)]
+    id: bool,
+}
+
+#[tracing::instrument(skip(data))]
+async fn swarm_addrs_local<T>(
+    data: web::Data<AppState<T>>,
+    query: web::Query<AddrsLocalQuery>,
+) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    let addrs = swarm::local_addrs(data.api.clone()).await?;
+    let addrs = if query.id {
+        // Include the peer Id of the current node in each address
+        let peer_id = data.api.id().await?.peer_id;
+        addrs
+            .into_iter()
+            .map(|addr| addr.with(Protocol::P2p(peer_id.into())))
+            .collect()
+    } else {
+        addrs
+    };
+    StringsResponse {
+        strings: addrs.iter().map(|addr| addr.to_string()).collect(),
+    }
+    .into_http_response()
+}
+
+#[tracing::instrument(skip(data))]
+async fn swarm_addrs_listen<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    StringsResponse {
+        strings: swarm::listen_addrs(data.api.clone())
+            .await?
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect(),
+    }
+    .into_http_response()
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -122,7 +232,15 @@ mod tests {
     use unimock::MockFn;
     use unimock::{matching, Unimock};
 
-    use crate::IpfsDepMock;
+    use crate::{IpfsDepMock, PeerConnection, PeerInfo};
+
+    // Construct a connection with only addresses known
+    fn connection(addrs: Vec<Multiaddr>) -> PeerConnection {
+        PeerConnection {
+            addrs,
+            protocols: vec![],
+        }
+    }
     #[actix_web::test]
     async fn test_swarm_connect() {
         let mock = Unimock::new(
@@ -155,17 +273,23 @@ mod tests {
     async fn test_swarm_peers() {
         let mock = Unimock::new(
             IpfsDepMock::peers
-                .next_call(matching!(()))
+                .next_call(matching!(false))
                 .returns(Ok(HashMap::from([
                     (
                         PeerId::from_str("12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp")
                             .unwrap(),
-                        vec![Multiaddr::from_str("/ip4/98.165.227.74/udp/15685/quic").unwrap()],
+                        connection(vec![Multiaddr::from_str(
+                            "/ip4/98.165.227.74/udp/15685/quic",
+                        )
+                        .unwrap()]),
                     ),
                     (
                         PeerId::from_str("12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU")
                             .unwrap(),
-                        vec![Multiaddr::from_str("/ip4/95.211.198.178/udp/4001/quic").unwrap()],
+                        connection(vec![Multiaddr::from_str(
+                            "/ip4/95.211.198.178/udp/4001/quic",
+                        )
+                        .unwrap()]),
                     ),
                 ]))),
         );
@@ -184,16 +308,10 @@ mod tests {
                   "Peers": [
                     {
                       "Addr": "/ip4/95.211.198.178/udp/4001/quic",
-                      "Direction": 0,
-                      "Latency": "",
-                      "Muxer": "",
                       "Peer": "12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU"
                     },
                     {
                       "Addr": "/ip4/98.165.227.74/udp/15685/quic",
-                      "Direction": 0,
-                      "Latency": "",
-                      "Muxer": "",
                       "Peer": "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp"
                     }
                   ]
@@ -201,4 +319,167 @@ mod tests {
         )
         .await;
     }
+
+    #[actix_web::test]
+    async fn test_swarm_peers_verbose() {
+        let mock = Unimock::new(IpfsDepMock::peers.next_call(matching!(true)).returns(Ok(
+            HashMap::from([(
+                PeerId::from_str("12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp").unwrap(),
+                PeerConnection {
+                    addrs: vec![
+                        Multiaddr::from_str("/ip4/98.165.227.74/udp/15685/quic").unwrap(),
+                        Multiaddr::from_str("/ip4/98.165.227.74/tcp/4001").unwrap(),
+                    ],
+                    protocols: vec!["/ipfs/bitswap/1.2.0".to_string()],
+                },
+            )]),
+        )));
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/swarm/peers?verbose=true")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Peers": [
+                    {
+                      "Addr": "/ip4/98.165.227.74/udp/15685/quic",
+                      "Peer": "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp",
+                      "Protocols": [
+                        "/ipfs/bitswap/1.2.0"
+                      ]
+                    },
+                    {
+                      "Addr": "/ip4/98.165.227.74/tcp/4001",
+                      "Peer": "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp",
+                      "Protocols": [
+                        "/ipfs/bitswap/1.2.0"
+                      ]
+                    }
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+    #[actix_web::test]
+    async fn test_swarm_disconnect() {
+        let mock = Unimock::new(
+            IpfsDepMock::disconnect
+                .next_call(matching!((p) if *p == PeerId::from_str("12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t").unwrap()))
+                .returns(Ok(())),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/swarm/disconnect?arg=/ip4/1.1.1.1/tcp/4001/p2p/12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Strings": [
+                    "disconnect 12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t success"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+    #[actix_web::test]
+    async fn test_swarm_addrs() {
+        let mock = Unimock::new(IpfsDepMock::peers.next_call(matching!(())).returns(Ok(
+            HashMap::from([(
+                PeerId::from_str("12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp").unwrap(),
+                connection(vec![
+                    Multiaddr::from_str("/ip4/98.165.227.74/udp/15685/quic").unwrap(),
+                    Multiaddr::from_str("/ip4/98.165.227.74/tcp/4001").unwrap(),
+                ]),
+            )]),
+        )));
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post().uri("/swarm/addrs").to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Addrs": {
+                    "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp": [
+                      "/ip4/98.165.227.74/udp/15685/quic",
+                      "/ip4/98.165.227.74/tcp/4001"
+                    ]
+                  }
+                }"#]],
+        )
+        .await;
+    }
+    #[actix_web::test]
+    async fn test_swarm_addrs_local() {
+        let peer_id =
+            PeerId::from_str("12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t").unwrap();
+        let info = PeerInfo {
+            peer_id,
+            listen_addrs: vec![Multiaddr::from_str("/ip4/127.0.0.1/tcp/4001").unwrap()],
+            observed_addrs: vec![
+                Multiaddr::from_str("/ip4/1.1.1.1/tcp/4001").unwrap(),
+                Multiaddr::from_str("/ip4/127.0.0.1/tcp/4001").unwrap(),
+            ],
+            protocol_version: "".to_string(),
+            agent_version: "".to_string(),
+            protocols: vec![],
+        };
+        let mock = Unimock::new((
+            IpfsDepMock::id
+                .next_call(matching!(()))
+                .returns(Ok(info.clone())),
+            IpfsDepMock::id.next_call(matching!(())).returns(Ok(info)),
+        ));
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/swarm/addrs/local?id=true")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Strings": [
+                    "/ip4/127.0.0.1/tcp/4001/p2p/12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t",
+                    "/ip4/1.1.1.1/tcp/4001/p2p/12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
+    #[actix_web::test]
+    async fn test_swarm_addrs_listen() {
+        let mock = Unimock::new(
+            IpfsDepMock::listeners
+                .next_call(matching!(()))
+                .returns(Ok(vec![
+                    Multiaddr::from_str("/ip4/0.0.0.0/tcp/4001").unwrap()
+                ])),
+        );
+        let server = build_server(mock).await;
+        let req = test::TestRequest::post()
+            .uri("/swarm/addrs/listen")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Strings": [
+                    "/ip4/0.0.0.0/tcp/4001"
+                  ]
+                }"#]],
+        )
+        .await;
+    }
 }
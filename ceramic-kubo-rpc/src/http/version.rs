@@ -0,0 +1,63 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Resource};
+use serde::Serialize;
+
+use crate::{error::Error, http::AppState, IpfsDep};
+pub fn resource<T>() -> Resource
+where
+    T: IpfsDep + 'static,
+{
+    web::resource("/version").route(web::post().to(version::<T>))
+}
+
+#[tracing::instrument(skip(data))]
+async fn version<T>(data: web::Data<AppState<T>>) -> Result<HttpResponse, Error>
+where
+    T: IpfsDep,
+{
+    #[derive(Serialize)]
+    struct VersionResponse<'a> {
+        #[serde(rename = "Version")]
+        version: &'a str,
+        #[serde(rename = "Commit")]
+        commit: &'a str,
+        #[serde(rename = "System")]
+        system: &'a str,
+    }
+
+    let version_resp = VersionResponse {
+        version: &data.version.version,
+        commit: &data.version.commit,
+        system: &data.version.system,
+    };
+    let body = serde_json::to_vec(&version_resp).map_err(|e| Error::Internal(e.into()))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::tests::{assert_body_json, build_server};
+
+    use actix_web::test;
+    use expect_test::expect;
+    use unimock::Unimock;
+
+    #[actix_web::test]
+    async fn test_version() {
+        let server = build_server(Unimock::new(())).await;
+        let req = test::TestRequest::post().uri("/version").to_request();
+        let resp = test::call_service(&server, req).await;
+        assert!(resp.status().is_success());
+        assert_body_json(
+            resp.into_body(),
+            expect![[r#"
+                {
+                  "Version": "0.1.0",
+                  "Commit": "git:test",
+                  "System": "test/test"
+                }"#]],
+        )
+        .await;
+    }
+}
@@ -0,0 +1,11 @@
+//! Implements the id endpoint.
+use crate::{error::Error, IpfsDep, PeerInfo};
+
+/// Get information about the local peer.
+#[tracing::instrument(skip(client))]
+pub async fn id<T>(client: T) -> Result<PeerInfo, Error>
+where
+    T: IpfsDep,
+{
+    client.id().await
+}
@@ -10,17 +10,59 @@ use std::collections::HashMap;
 
 use anyhow::anyhow;
 use async_trait::async_trait;
+use futures_util::stream::{BoxStream, StreamExt};
 use iroh_api::{Api, Bytes, Cid, IpfsPath, Multiaddr, PeerId};
+use libipld::Ipld;
+use libp2p::gossipsub::{GossipsubEvent, TopicHash};
 use unimock::unimock;
 
+pub mod block;
+pub mod cacao;
+mod car;
 pub mod dag;
 pub mod error;
+pub mod event;
 #[cfg(feature = "http")]
 pub mod http;
+pub mod id;
+pub mod memory;
+pub mod metrics;
+pub mod pin;
+pub mod pubsub;
+pub mod repo;
 pub mod swarm;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod version;
 
 use crate::error::Error;
 
+/// Information about a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// Id of the peer.
+    pub peer_id: PeerId,
+    /// Addresses on which the peer is listening.
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Addresses of the peer as observed by other peers.
+    pub observed_addrs: Vec<Multiaddr>,
+    /// Version of the libp2p protocol used by the peer.
+    pub protocol_version: String,
+    /// Version of the software of the peer.
+    pub agent_version: String,
+    /// Protocols supported by the peer.
+    pub protocols: Vec<String>,
+}
+
+/// Details of the connection to a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerConnection {
+    /// Addresses of the peer.
+    pub addrs: Vec<Multiaddr>,
+    /// Protocols supported by the peer, empty unless requested.
+    pub protocols: Vec<String>,
+}
+
 /// Defines the behavior this crate needs from IPFS in order to serve Kubo RPC calls.
 /// The trait serves two purposes:
 ///     1. We are explicit about the API surface area we consume from IPFS.
@@ -28,36 +70,101 @@ use crate::error::Error;
 #[unimock(api=IpfsDepMock)]
 #[async_trait]
 pub trait IpfsDep: Clone {
-    /// Get a DAG node from IPFS returning the Cid of the resolved path and the bytes of the node.
-    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Bytes), Error>;
+    /// Get information about the local peer.
+    async fn id(&self) -> Result<PeerInfo, Error>;
+    /// Get a DAG node from IPFS returning the Cid of the block containing the resolved path
+    /// and the decoded data of the node.
+    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Ipld), Error>;
+    /// Get a block from IPFS.
+    async fn block_get(&self, cid: Cid) -> Result<Bytes, Error>;
+    /// Report the size of a block in bytes.
+    async fn block_size(&self, cid: Cid) -> Result<u64, Error>;
+    /// List the Cids of all blocks stored locally.
+    async fn blocks(&self) -> Result<Vec<Cid>, Error>;
+    /// Remove a block from the local store, returning the size of the removed block in bytes.
+    async fn block_rm(&self, cid: Cid) -> Result<u64, Error>;
     /// Store a DAG node into IFPS.
     async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<(), Error>;
     /// Resolve an IPLD block.
     async fn resolve(&self, ipfs_path: &IpfsPath) -> Result<Vec<Cid>, Error>;
-    /// Report all connected peers of the current node.
-    async fn peers(&self) -> Result<HashMap<PeerId, Vec<Multiaddr>>, Error>;
+    /// Report all connected peers of the current node,
+    /// looking up the protocols of each peer only when requested.
+    async fn peers(&self, protocols: bool) -> Result<HashMap<PeerId, PeerConnection>, Error>;
     /// Connect to a specific peer node.
     async fn connect(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<(), Error>;
+    /// Disconnect from a specific peer node.
+    async fn disconnect(&self, peer_id: PeerId) -> Result<(), Error>;
+    /// Report the addresses on which the current node is listening.
+    async fn listeners(&self) -> Result<Vec<Multiaddr>, Error>;
+    /// Publish a message on a pub/sub topic.
+    async fn publish(&self, topic: String, data: Bytes) -> Result<(), Error>;
+    /// Subscribe to a pub/sub topic.
+    async fn subscribe(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, anyhow::Result<GossipsubEvent>>, Error>;
+    /// List the pub/sub topics to which we are currently subscribed.
+    async fn topics(&self) -> Result<Vec<String>, Error>;
+    /// List the peers we are connected to via pub/sub, optionally only peers on a specific topic.
+    async fn topic_peers(&self, topic: Option<String>) -> Result<Vec<PeerId>, Error>;
 }
 
 #[async_trait]
 impl IpfsDep for Api {
-    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Bytes), Error> {
-        // TODO(nathanielc): Iroh does not have support for DAG-JOSE,
+    async fn id(&self) -> Result<PeerInfo, Error> {
+        let lookup = self
+            .client()
+            .try_p2p()
+            .map_err(Error::Internal)?
+            .lookup_local()
+            .await
+            .map_err(Error::Internal)?;
+        Ok(PeerInfo {
+            peer_id: lookup.peer_id,
+            listen_addrs: lookup.listen_addrs,
+            observed_addrs: lookup.observed_addrs,
+            protocol_version: lookup.protocol_version,
+            agent_version: lookup.agent_version,
+            protocols: lookup.protocols,
+        })
+    }
+    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Ipld), Error> {
+        // Iroh does not have support for DAG-JOSE,
         // therefore it cannot traverse paths as it cannot decode the intermediate steps.
-        // We use `get_raw` below so that we can still get the data but as a result we do not support
-        // any path below the Cid.
-        if !ipfs_path.tail().is_empty() {
-            return Err(Error::Invalid(anyhow!(
-                "IPFS paths with path elements are not yet supported"
-            )));
-        }
-
-        if let Some(cid) = ipfs_path.cid() {
-            Ok((*cid, self.get_raw(*cid).await.map_err(Error::Internal)?))
-        } else {
-            Err(Error::Invalid(anyhow!("IPFS path does not refer to a CID")))
-        }
+        // Instead we use `get_raw` to fetch each block and traverse the path ourselves.
+        let cid = ipfs_path
+            .cid()
+            .ok_or_else(|| Error::Invalid(anyhow!("IPFS path does not refer to a CID")))?;
+        dag::traverse(*cid, ipfs_path.tail(), |cid| async move {
+            self.get_raw(cid).await.map_err(Error::Internal)
+        })
+        .await
+    }
+    async fn block_get(&self, cid: Cid) -> Result<Bytes, Error> {
+        self.get_raw(cid).await.map_err(Error::Internal)
+    }
+    async fn block_size(&self, cid: Cid) -> Result<u64, Error> {
+        self.client()
+            .try_store()
+            .map_err(Error::Internal)?
+            .get_size(cid)
+            .await
+            .map_err(Error::Internal)?
+            .ok_or(Error::NotFound)
+    }
+    async fn blocks(&self) -> Result<Vec<Cid>, Error> {
+        // The Iroh store API cannot iterate its blocks,
+        // garbage collection must open the database of the store directly.
+        Err(Error::Internal(anyhow!(
+            "listing blocks is not supported by the Iroh store API"
+        )))
+    }
+    async fn block_rm(&self, _cid: Cid) -> Result<u64, Error> {
+        // The Iroh store API cannot delete blocks,
+        // garbage collection must open the database of the store directly.
+        Err(Error::Internal(anyhow!(
+            "removing blocks is not supported by the Iroh store API"
+        )))
     }
     async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<(), Error> {
         Ok(self
@@ -71,22 +178,100 @@ impl IpfsDep for Api {
     async fn resolve(&self, ipfs_path: &IpfsPath) -> Result<Vec<Cid>, Error> {
         Ok(self.resolve(ipfs_path).await.map_err(Error::Internal)?)
     }
-    async fn peers(&self) -> Result<HashMap<PeerId, Vec<Multiaddr>>, Error> {
+    async fn peers(&self, protocols: bool) -> Result<HashMap<PeerId, PeerConnection>, Error> {
+        let p2p = self.client().try_p2p().map_err(Error::Internal)?;
+        let peers = p2p.get_peers().await.map_err(Error::Internal)?;
+        let mut connections = HashMap::with_capacity(peers.len());
+        for (peer_id, addrs) in peers {
+            // Peers may disconnect before we can look them up, in which case the protocols are
+            // simply unknown.
+            let protocols = if protocols {
+                match p2p.lookup(peer_id, None).await {
+                    Ok(lookup) => lookup.protocols,
+                    Err(_) => Vec::new(),
+                }
+            } else {
+                Vec::new()
+            };
+            connections.insert(peer_id, PeerConnection { addrs, protocols });
+        }
+        Ok(connections)
+    }
+    async fn connect(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<(), Error> {
         Ok(self
             .client()
             .try_p2p()
             .map_err(Error::Internal)?
-            .get_peers()
+            .connect(peer_id, addrs)
             .await
             .map_err(Error::Internal)?)
     }
-    async fn connect(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<(), Error> {
+    async fn disconnect(&self, peer_id: PeerId) -> Result<(), Error> {
         Ok(self
             .client()
             .try_p2p()
             .map_err(Error::Internal)?
-            .connect(peer_id, addrs)
+            .disconnect(peer_id)
+            .await
+            .map_err(Error::Internal)?)
+    }
+    async fn listeners(&self) -> Result<Vec<Multiaddr>, Error> {
+        Ok(self
+            .client()
+            .try_p2p()
+            .map_err(Error::Internal)?
+            .listeners()
             .await
             .map_err(Error::Internal)?)
     }
+    async fn publish(&self, topic: String, data: Bytes) -> Result<(), Error> {
+        self.client()
+            .try_p2p()
+            .map_err(Error::Internal)?
+            .gossipsub_publish(TopicHash::from_raw(topic), data)
+            .await
+            .map_err(Error::Internal)?;
+        Ok(())
+    }
+    async fn subscribe(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, anyhow::Result<GossipsubEvent>>, Error> {
+        Ok(self
+            .p2p()
+            .map_err(Error::Internal)?
+            .subscribe(topic)
+            .await
+            .map_err(Error::Internal)?
+            .boxed())
+    }
+    async fn topics(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .client()
+            .try_p2p()
+            .map_err(Error::Internal)?
+            .gossipsub_topics()
+            .await
+            .map_err(Error::Internal)?
+            .into_iter()
+            .map(|topic| topic.into_string())
+            .collect())
+    }
+    async fn topic_peers(&self, topic: Option<String>) -> Result<Vec<PeerId>, Error> {
+        let topic = topic.map(TopicHash::from_raw);
+        Ok(self
+            .client()
+            .try_p2p()
+            .map_err(Error::Internal)?
+            .gossipsub_all_peers()
+            .await
+            .map_err(Error::Internal)?
+            .into_iter()
+            .filter(|(_, topics)| match &topic {
+                Some(topic) => topics.contains(topic),
+                None => true,
+            })
+            .map(|(peer_id, _)| peer_id)
+            .collect())
+    }
 }
@@ -0,0 +1,360 @@
+//! Provides an in-memory implementation of [`IpfsDep`].
+//!
+//! The node stores blocks in memory and simulates its network with a table of reachable peers,
+//! which makes it suitable for embedding and for testing without a block store or a network.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures_util::{
+    channel::mpsc::{self, UnboundedSender},
+    stream::{BoxStream, StreamExt},
+};
+use iroh_api::{Bytes, Cid, IpfsPath, Multiaddr, PeerId};
+use libipld::Ipld;
+use libp2p::gossipsub::{GossipsubEvent, GossipsubMessage, MessageId, TopicHash};
+
+use crate::{dag, error::Error, IpfsDep, PeerConnection, PeerInfo};
+
+/// In-memory IPFS node.
+///
+/// Clones share the same blocks, peers and subscriptions.
+#[derive(Clone)]
+pub struct MemoryIpfs {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    info: PeerInfo,
+    blocks: BTreeMap<Cid, Bytes>,
+    // Peers that can be connected and the topics to which they are subscribed.
+    reachable: HashMap<PeerId, (PeerInfo, BTreeSet<String>)>,
+    connections: HashMap<PeerId, PeerConnection>,
+    subscribers: BTreeMap<String, Vec<UnboundedSender<GossipsubEvent>>>,
+    published: Vec<(String, Bytes)>,
+    sequence_number: u64,
+}
+
+impl MemoryIpfs {
+    /// Construct a node without any blocks or peers.
+    pub fn new(info: PeerInfo) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                info,
+                blocks: BTreeMap::new(),
+                reachable: HashMap::new(),
+                connections: HashMap::new(),
+                subscribers: BTreeMap::new(),
+                published: Vec::new(),
+                sequence_number: 0,
+            })),
+        }
+    }
+    /// Make a peer reachable so that it can be connected, the peer is subscribed to the topics.
+    pub fn add_peer(&self, info: PeerInfo, topics: Vec<String>) {
+        self.lock()
+            .reachable
+            .insert(info.peer_id, (info, topics.into_iter().collect()));
+    }
+    /// Deliver a message on a pub/sub topic from a peer to the subscribers of the topic.
+    pub fn receive(&self, from: PeerId, topic: String, data: Bytes) {
+        let mut state = self.lock();
+        state.sequence_number += 1;
+        let message = GossipsubMessage {
+            source: Some(from),
+            data: data.to_vec(),
+            sequence_number: Some(state.sequence_number),
+            topic: TopicHash::from_raw(topic.clone()),
+        };
+        let message_id = MessageId::new(&state.sequence_number.to_be_bytes());
+        if let Some(subscribers) = state.subscribers.get_mut(&topic) {
+            // Subscribers that are no longer listening are dropped.
+            subscribers.retain(|subscriber| {
+                subscriber
+                    .unbounded_send(GossipsubEvent::Message {
+                        propagation_source: from,
+                        message_id: message_id.clone(),
+                        message: message.clone(),
+                    })
+                    .is_ok()
+            });
+        }
+    }
+    /// Report the messages published by this node in the order they were published.
+    pub fn published(&self) -> Vec<(String, Bytes)> {
+        self.lock().published.clone()
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("memory ipfs lock poisoned")
+    }
+    fn block(&self, cid: Cid) -> Result<Bytes, Error> {
+        self.lock().blocks.get(&cid).cloned().ok_or(Error::NotFound)
+    }
+}
+
+#[async_trait]
+impl IpfsDep for MemoryIpfs {
+    async fn id(&self) -> Result<PeerInfo, Error> {
+        Ok(self.lock().info.clone())
+    }
+    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Ipld), Error> {
+        let cid = ipfs_path
+            .cid()
+            .ok_or_else(|| Error::Invalid(anyhow!("IPFS path does not refer to a CID")))?;
+        dag::traverse(*cid, ipfs_path.tail(), |cid| {
+            let block = self.block(cid);
+            async move { block }
+        })
+        .await
+    }
+    async fn block_get(&self, cid: Cid) -> Result<Bytes, Error> {
+        self.block(cid)
+    }
+    async fn block_size(&self, cid: Cid) -> Result<u64, Error> {
+        Ok(self.block(cid)?.len() as u64)
+    }
+    async fn blocks(&self) -> Result<Vec<Cid>, Error> {
+        Ok(self.lock().blocks.keys().copied().collect())
+    }
+    async fn block_rm(&self, cid: Cid) -> Result<u64, Error> {
+        self.lock()
+            .blocks
+            .remove(&cid)
+            .map(|block| block.len() as u64)
+            .ok_or(Error::NotFound)
+    }
+    async fn put(&self, cid: Cid, blob: Bytes, _links: Vec<Cid>) -> Result<(), Error> {
+        self.lock().blocks.insert(cid, blob);
+        Ok(())
+    }
+    async fn resolve(&self, ipfs_path: &IpfsPath) -> Result<Vec<Cid>, Error> {
+        let cid = ipfs_path
+            .cid()
+            .ok_or_else(|| Error::Invalid(anyhow!("IPFS path does not refer to a CID")))?;
+        // Report the Cid of every block traversed along the path.
+        let traversed = Mutex::new(Vec::new());
+        dag::traverse(*cid, ipfs_path.tail(), |cid| {
+            traversed.lock().expect("traversed lock poisoned").push(cid);
+            let block = self.block(cid);
+            async move { block }
+        })
+        .await?;
+        Ok(traversed.into_inner().expect("traversed lock poisoned"))
+    }
+    async fn peers(&self, protocols: bool) -> Result<HashMap<PeerId, PeerConnection>, Error> {
+        let mut connections = self.lock().connections.clone();
+        if !protocols {
+            for connection in connections.values_mut() {
+                connection.protocols.clear();
+            }
+        }
+        Ok(connections)
+    }
+    async fn connect(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<(), Error> {
+        let mut state = self.lock();
+        let (info, _) = state
+            .reachable
+            .get(&peer_id)
+            .ok_or_else(|| Error::Internal(anyhow!("peer {} is not reachable", peer_id)))?;
+        let connection = PeerConnection {
+            addrs: if addrs.is_empty() {
+                info.listen_addrs.clone()
+            } else {
+                addrs
+            },
+            protocols: info.protocols.clone(),
+        };
+        state.connections.insert(peer_id, connection);
+        Ok(())
+    }
+    async fn disconnect(&self, peer_id: PeerId) -> Result<(), Error> {
+        self.lock().connections.remove(&peer_id);
+        Ok(())
+    }
+    async fn listeners(&self) -> Result<Vec<Multiaddr>, Error> {
+        Ok(self.lock().info.listen_addrs.clone())
+    }
+    async fn publish(&self, topic: String, data: Bytes) -> Result<(), Error> {
+        // Like gossipsub, messages are not delivered to the subscribers of the node itself.
+        self.lock().published.push((topic, data));
+        Ok(())
+    }
+    async fn subscribe(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, anyhow::Result<GossipsubEvent>>, Error> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.lock()
+            .subscribers
+            .entry(topic)
+            .or_default()
+            .push(sender);
+        Ok(receiver.map(Ok).boxed())
+    }
+    async fn topics(&self) -> Result<Vec<String>, Error> {
+        let mut state = self.lock();
+        for subscribers in state.subscribers.values_mut() {
+            subscribers.retain(|subscriber| !subscriber.is_closed());
+        }
+        state
+            .subscribers
+            .retain(|_, subscribers| !subscribers.is_empty());
+        Ok(state.subscribers.keys().cloned().collect())
+    }
+    async fn topic_peers(&self, topic: Option<String>) -> Result<Vec<PeerId>, Error> {
+        let state = self.lock();
+        Ok(state
+            .connections
+            .keys()
+            .filter(|peer_id| match (&topic, state.reachable.get(*peer_id)) {
+                (Some(topic), Some((_, topics))) => topics.contains(topic),
+                (None, Some((_, topics))) => !topics.is_empty(),
+                (_, None) => false,
+            })
+            .copied()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use libipld::{
+        cbor::DagCborCodec,
+        ipld,
+        multihash::{Code, MultihashDigest},
+        prelude::Encode,
+    };
+
+    use super::*;
+
+    fn peer_info(peer_id: &str, addr: &str) -> PeerInfo {
+        PeerInfo {
+            peer_id: PeerId::from_str(peer_id).unwrap(),
+            listen_addrs: vec![Multiaddr::from_str(addr).unwrap()],
+            observed_addrs: vec![],
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "test".to_string(),
+            protocols: vec!["/ipfs/id/1.0.0".to_string()],
+        }
+    }
+
+    fn node() -> MemoryIpfs {
+        MemoryIpfs::new(peer_info(
+            "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp",
+            "/ip4/127.0.0.1/tcp/4001",
+        ))
+    }
+
+    async fn put_cbor(ipfs: &MemoryIpfs, data: Ipld) -> Cid {
+        let mut blob: Vec<u8> = Vec::new();
+        data.encode(DagCborCodec, &mut blob).unwrap();
+        let cid = Cid::new_v1(DagCborCodec.into(), Code::Sha2_256.digest(&blob));
+        ipfs.put(cid, blob.into(), vec![]).await.unwrap();
+        cid
+    }
+
+    #[tokio::test]
+    async fn test_blocks() {
+        let ipfs = node();
+        let leaf = put_cbor(&ipfs, ipld!({"x": [1, 2, 3]})).await;
+        let root = put_cbor(&ipfs, ipld!({"link": leaf})).await;
+
+        let path = IpfsPath::from_str(&format!("/ipfs/{}/link/x/1", root)).unwrap();
+        assert_eq!((leaf, Ipld::Integer(2)), ipfs.get(&path).await.unwrap());
+        assert_eq!(vec![root, leaf], ipfs.resolve(&path).await.unwrap());
+
+        let mut blocks = vec![root, leaf];
+        blocks.sort();
+        assert_eq!(blocks, ipfs.blocks().await.unwrap());
+
+        let size = ipfs.block_size(leaf).await.unwrap();
+        assert_eq!(size, ipfs.block_get(leaf).await.unwrap().len() as u64);
+        assert_eq!(size, ipfs.block_rm(leaf).await.unwrap());
+        assert!(matches!(ipfs.block_get(leaf).await, Err(Error::NotFound)));
+        assert!(matches!(ipfs.get(&path).await, Err(Error::NotFound)));
+        assert!(matches!(ipfs.block_rm(leaf).await, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_peers() {
+        let ipfs = node();
+        let peer = peer_info(
+            "12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU",
+            "/ip4/10.0.0.1/tcp/4001",
+        );
+        let unreachable =
+            PeerId::from_str("12D3KooWFtPWZ1uHShnbvmxYJGmygUfTVmcb6iSQfiAm4XnmsQ8t").unwrap();
+        ipfs.add_peer(peer.clone(), vec!["/ceramic/local".to_string()]);
+
+        assert!(ipfs.connect(unreachable, vec![]).await.is_err());
+        ipfs.connect(peer.peer_id, vec![]).await.unwrap();
+        assert_eq!(
+            HashMap::from([(
+                peer.peer_id,
+                PeerConnection {
+                    addrs: peer.listen_addrs.clone(),
+                    protocols: peer.protocols.clone(),
+                }
+            )]),
+            ipfs.peers(true).await.unwrap()
+        );
+        assert!(ipfs.peers(false).await.unwrap()[&peer.peer_id]
+            .protocols
+            .is_empty());
+        assert_eq!(
+            vec![peer.peer_id],
+            ipfs.topic_peers(Some("/ceramic/local".to_string()))
+                .await
+                .unwrap()
+        );
+        assert!(ipfs
+            .topic_peers(Some("/ceramic/other".to_string()))
+            .await
+            .unwrap()
+            .is_empty());
+
+        ipfs.disconnect(peer.peer_id).await.unwrap();
+        assert!(ipfs.peers(false).await.unwrap().is_empty());
+        assert!(ipfs.topic_peers(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pubsub() {
+        let ipfs = node();
+        let from =
+            PeerId::from_str("12D3KooWBSyp3QZQBFakvXT2uqT2L5ZmTNnpYNXgyVZq5YB3P7DU").unwrap();
+        let topic = "/ceramic/local".to_string();
+        let mut messages = Box::pin(
+            crate::pubsub::subscribe(ipfs.clone(), topic.clone())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(vec![topic.clone()], ipfs.topics().await.unwrap());
+
+        ipfs.publish(topic.clone(), Bytes::from("mine"))
+            .await
+            .unwrap();
+        ipfs.receive(from, topic.clone(), Bytes::from("hello"));
+        ipfs.receive(from, "/ceramic/other".to_string(), Bytes::from("ignored"));
+        assert_eq!(vec![(topic.clone(), Bytes::from("mine"))], ipfs.published());
+
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(
+            crate::pubsub::Message {
+                from: Some(from),
+                data: Bytes::from("hello"),
+                seqno: Some(1),
+                topic: topic.clone(),
+            },
+            message
+        );
+
+        drop(messages);
+        assert!(ipfs.topics().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+//! Records Prometheus metrics of the Kubo RPC methods.
+//!
+//! Metrics are recorded in a registry shared by the whole process and are encoded in the
+//! OpenMetrics text format with [`encode`].
+use std::{io::Write, time::Duration};
+
+use once_cell::sync::Lazy;
+use prometheus_client::{
+    encoding::text::{self, Encode},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::{Registry, Unit},
+};
+
+use crate::error::Error;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode)]
+struct EndpointLabels {
+    endpoint: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode)]
+struct ErrorLabels {
+    endpoint: String,
+    error: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode)]
+struct CodecLabels {
+    codec: String,
+    output_codec: String,
+}
+
+struct Metrics {
+    registry: Registry,
+    requests: Family<EndpointLabels, Counter>,
+    request_duration: Family<EndpointLabels, Histogram, fn() -> Histogram>,
+    errors: Family<ErrorLabels, Counter>,
+    dag_put_block_size: Histogram,
+    dag_get: Family<CodecLabels, Counter>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut registry = <Registry>::with_prefix("kubo_rpc");
+
+        let requests = Family::<EndpointLabels, Counter>::default();
+        registry.register(
+            "requests",
+            "Number of requests by endpoint",
+            Box::new(requests.clone()),
+        );
+        let request_duration =
+            Family::<EndpointLabels, Histogram, fn() -> Histogram>::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.0, 16))
+            });
+        registry.register_with_unit(
+            "request_duration",
+            "Duration of requests by endpoint",
+            Unit::Seconds,
+            Box::new(request_duration.clone()),
+        );
+        let errors = Family::<ErrorLabels, Counter>::default();
+        registry.register(
+            "errors",
+            "Number of failed requests by endpoint and error",
+            Box::new(errors.clone()),
+        );
+        let dag_put_block_size = Histogram::new(exponential_buckets(64.0, 2.0, 16));
+        registry.register_with_unit(
+            "dag_put_block_size",
+            "Size of the blocks stored by dag put",
+            Unit::Bytes,
+            Box::new(dag_put_block_size.clone()),
+        );
+        let dag_get = Family::<CodecLabels, Counter>::default();
+        registry.register(
+            "dag_get",
+            "Number of nodes read by dag get by the codec of the block and the output codec",
+            Box::new(dag_get.clone()),
+        );
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            errors,
+            dag_put_block_size,
+            dag_get,
+        }
+    }
+}
+
+/// Record a request to an endpoint, failed requests are labelled with their error.
+pub fn record_request(endpoint: &str, duration: Duration, error: Option<&str>) {
+    let labels = EndpointLabels {
+        endpoint: endpoint.to_string(),
+    };
+    METRICS.requests.get_or_create(&labels).inc();
+    METRICS
+        .request_duration
+        .get_or_create(&labels)
+        .observe(duration.as_secs_f64());
+    if let Some(error) = error {
+        METRICS
+            .errors
+            .get_or_create(&ErrorLabels {
+                endpoint: labels.endpoint,
+                error: error.to_string(),
+            })
+            .inc();
+    }
+}
+
+/// Label of the variant of an error.
+pub fn error_label(err: &Error) -> &'static str {
+    match err {
+        Error::NotFound => "not_found",
+        Error::Invalid(_) => "invalid",
+        Error::Internal(_) => "internal",
+    }
+}
+
+/// Record the size of a block stored by dag put.
+pub fn record_dag_put(size: usize) {
+    METRICS.dag_put_block_size.observe(size as f64);
+}
+
+/// Record a node read by dag get with the codec of its block and the output codec.
+pub fn record_dag_get(codec: u64, output_codec: u64) {
+    METRICS
+        .dag_get
+        .get_or_create(&CodecLabels {
+            codec: codec_name(codec),
+            output_codec: codec_name(output_codec),
+        })
+        .inc();
+}
+
+/// Encode all metrics in the OpenMetrics text format.
+pub fn encode<W: Write>(writer: &mut W) -> std::io::Result<()> {
+    text::encode(writer, &METRICS.registry)
+}
+
+fn codec_name(codec: u64) -> String {
+    match codec {
+        0x55 => "raw".to_string(),
+        0x70 => "dag-pb".to_string(),
+        0x71 => "dag-cbor".to_string(),
+        0x85 => "dag-jose".to_string(),
+        0x0129 => "dag-json".to_string(),
+        _ => format!("{:#x}", codec),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded() -> String {
+        let mut buf = Vec::new();
+        encode(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_metrics() {
+        record_request("test/ok", Duration::from_millis(3), None);
+        record_request(
+            "test/fail",
+            Duration::from_millis(3),
+            Some(error_label(&Error::NotFound)),
+        );
+        record_dag_get(0x85, 0x0129);
+        record_dag_put(100);
+
+        let metrics = encoded();
+        for line in [
+            r#"kubo_rpc_requests_total{endpoint="test/ok"} 1"#,
+            r#"kubo_rpc_requests_total{endpoint="test/fail"} 1"#,
+            r#"kubo_rpc_request_duration_seconds_bucket{endpoint="test/ok",le="0.004"} 1"#,
+            r#"kubo_rpc_errors_total{endpoint="test/fail",error="not_found"} 1"#,
+            r#"kubo_rpc_dag_get_total{codec="dag-jose",output_codec="dag-json"} 1"#,
+            "# TYPE kubo_rpc_dag_put_block_size_bytes histogram",
+        ] {
+            assert!(metrics.contains(line), "missing {} in\n{}", line, metrics);
+        }
+        assert!(!metrics.contains(r#"endpoint="test/ok",error"#));
+    }
+}
@@ -0,0 +1,335 @@
+//! Implements the pin related endpoints.
+//!
+//! Pins are recorded durably in a [`PinStore`] and identify the roots of the DAGs that must be
+//! retained by the node.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    io::Write,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use futures_util::lock::{Mutex as AsyncMutex, OwnedMutexGuard};
+use iroh_api::Cid;
+
+use crate::{block, error::Error, IpfsDep};
+
+/// Type of a pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PinType {
+    /// Only the block itself is pinned.
+    Direct,
+    /// The block and all blocks reachable from it are pinned.
+    Recursive,
+    /// The block is pinned because it is reachable from a recursive pin.
+    Indirect,
+}
+
+impl fmt::Display for PinType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinType::Direct => write!(f, "direct"),
+            PinType::Recursive => write!(f, "recursive"),
+            PinType::Indirect => write!(f, "indirect"),
+        }
+    }
+}
+
+impl FromStr for PinType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "direct" => Ok(PinType::Direct),
+            "recursive" => Ok(PinType::Recursive),
+            "indirect" => Ok(PinType::Indirect),
+            _ => Err(Error::Invalid(anyhow!("invalid pin type \"{}\"", s))),
+        }
+    }
+}
+
+/// Durable record of the direct and recursive pins of the node.
+///
+/// Pins are stored in a file with one pin per line, each line is the Cid followed by the pin type.
+#[derive(Debug, Clone)]
+pub struct PinStore {
+    path: Option<PathBuf>,
+    pins: Arc<Mutex<BTreeMap<Cid, PinType>>>,
+    // Held while pins change and during garbage collection,
+    // so blocks being pinned are never swept.
+    update: Arc<AsyncMutex<()>>,
+}
+
+impl PinStore {
+    /// Open the pin store at path, creating it if it does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let mut pins = BTreeMap::new();
+        let exists = path.exists();
+        if exists {
+            let data = fs::read_to_string(&path).map_err(|e| Error::Internal(e.into()))?;
+            for line in data.lines().filter(|line| !line.is_empty()) {
+                let (cid, typ) = line.split_once(' ').ok_or_else(|| {
+                    Error::Internal(anyhow!("malformed pin store entry \"{}\"", line))
+                })?;
+                let cid = Cid::from_str(cid).map_err(|e| Error::Internal(e.into()))?;
+                pins.insert(cid, typ.parse()?);
+            }
+        }
+        let store = Self {
+            path: Some(path),
+            pins: Arc::new(Mutex::new(pins)),
+            update: Arc::new(AsyncMutex::new(())),
+        };
+        if !exists {
+            store.persist(&BTreeMap::new())?;
+        }
+        Ok(store)
+    }
+    /// Construct a pin store that is not persisted.
+    pub fn memory() -> Self {
+        Self {
+            path: None,
+            pins: Arc::new(Mutex::new(BTreeMap::new())),
+            update: Arc::new(AsyncMutex::new(())),
+        }
+    }
+    /// Report the direct and recursive pins.
+    pub fn pins(&self) -> BTreeMap<Cid, PinType> {
+        self.pins.lock().expect("pin store lock poisoned").clone()
+    }
+    /// Report the pin type of the Cid if it is pinned directly or recursively.
+    pub fn get(&self, cid: &Cid) -> Option<PinType> {
+        self.pins
+            .lock()
+            .expect("pin store lock poisoned")
+            .get(cid)
+            .copied()
+    }
+    /// Prevent pins from changing until the returned guard is dropped.
+    pub async fn lock(&self) -> OwnedMutexGuard<()> {
+        self.update.clone().lock_owned().await
+    }
+    // Changes are persisted before they are visible in memory so a failed write changes nothing.
+    fn insert(&self, cid: Cid, typ: PinType) -> Result<(), Error> {
+        let mut pins = self.pins.lock().expect("pin store lock poisoned");
+        let mut updated = pins.clone();
+        updated.insert(cid, typ);
+        self.persist(&updated)?;
+        *pins = updated;
+        Ok(())
+    }
+    fn remove(&self, cid: &Cid) -> Result<(), Error> {
+        let mut pins = self.pins.lock().expect("pin store lock poisoned");
+        let mut updated = pins.clone();
+        updated.remove(cid);
+        self.persist(&updated)?;
+        *pins = updated;
+        Ok(())
+    }
+    // Write all pins to a temporary file and atomically rename it into place.
+    fn persist(&self, pins: &BTreeMap<Cid, PinType>) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let tmp = path.with_extension("tmp");
+            let mut file = fs::File::create(&tmp).map_err(|e| Error::Internal(e.into()))?;
+            for (cid, typ) in pins {
+                writeln!(file, "{} {}", cid, typ).map_err(|e| Error::Internal(e.into()))?;
+            }
+            file.sync_all().map_err(|e| Error::Internal(e.into()))?;
+            fs::rename(&tmp, path).map_err(|e| Error::Internal(e.into()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Pin a block, when recursive all blocks reachable from the block are also pinned.
+///
+/// All pinned blocks are fetched so they are available locally.
+#[tracing::instrument(skip(client, pins))]
+pub async fn add<T>(client: T, pins: &PinStore, cid: Cid, recursive: bool) -> Result<(), Error>
+where
+    T: IpfsDep,
+{
+    let _guard = pins.lock().await;
+    if recursive {
+        walk(client, cid).await?;
+        pins.insert(cid, PinType::Recursive)
+    } else {
+        if pins.get(&cid) == Some(PinType::Recursive) {
+            return Err(Error::Invalid(anyhow!(
+                "{} already pinned recursively",
+                cid
+            )));
+        }
+        client.block_get(cid).await?;
+        pins.insert(cid, PinType::Direct)
+    }
+}
+
+/// Remove a pin.
+///
+/// Recursive pins can only be removed when recursive is true.
+#[tracing::instrument(skip(pins))]
+pub async fn rm(pins: &PinStore, cid: Cid, recursive: bool) -> Result<(), Error> {
+    let _guard = pins.lock().await;
+    match pins.get(&cid) {
+        Some(PinType::Recursive) if !recursive => {
+            Err(Error::Invalid(anyhow!("{} is pinned recursively", cid)))
+        }
+        Some(_) => pins.remove(&cid),
+        None => Err(Error::NotFound),
+    }
+}
+
+/// List pinned blocks, optionally only pins of a specific type.
+///
+/// Indirect pins are only computed when requested as they require walking every recursive pin.
+#[tracing::instrument(skip(client, pins))]
+pub async fn ls<T>(
+    client: T,
+    pins: &PinStore,
+    typ: Option<PinType>,
+) -> Result<BTreeMap<Cid, PinType>, Error>
+where
+    T: IpfsDep,
+{
+    let roots = pins.pins();
+    let mut listed: BTreeMap<Cid, PinType> = roots
+        .iter()
+        .filter(|(_, t)| typ.is_none() || typ == Some(**t))
+        .map(|(cid, t)| (*cid, *t))
+        .collect();
+    if typ.is_none() || typ == Some(PinType::Indirect) {
+        for (root, _) in roots.iter().filter(|(_, t)| **t == PinType::Recursive) {
+            for cid in walk(client.clone(), *root).await? {
+                if !roots.contains_key(&cid) {
+                    listed.insert(cid, PinType::Indirect);
+                }
+            }
+        }
+    }
+    Ok(listed)
+}
+
+/// Walk the DAG starting at root, reporting the Cids of all reachable blocks including the root.
+#[tracing::instrument(skip(client))]
+pub async fn walk<T>(client: T, root: Cid) -> Result<BTreeSet<Cid>, Error>
+where
+    T: IpfsDep,
+{
+    let mut visited = BTreeSet::new();
+    let mut queue = vec![root];
+    while let Some(cid) = queue.pop() {
+        if !visited.insert(cid) {
+            continue;
+        }
+        let bytes = client.block_get(cid).await?;
+        queue.extend(block::links(&cid, &bytes)?);
+    }
+    Ok(visited)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use libipld::ipld;
+    use unimock::{matching, MockFn, Unimock};
+
+    use super::*;
+    use crate::{testing::cbor_block, IpfsDepMock};
+
+    // Build a mock that serves the blocks of a small DAG: root -> (a, b), a -> b
+    fn dag_mock() -> (Unimock, Cid, Cid, Cid) {
+        let (b, b_bytes) = cbor_block(&ipld!({"b": true}));
+        let (a, a_bytes) = cbor_block(&ipld!({"a": true, "next": b}));
+        let (root, root_bytes) = cbor_block(&ipld!({"links": [a, b]}));
+        let blocks = HashMap::from([(root, root_bytes), (a, a_bytes), (b, b_bytes)]);
+        let mock = Unimock::new(
+            IpfsDepMock::block_get
+                .each_call(matching!(_))
+                .answers(move |cid| blocks.get(&cid).cloned().ok_or(Error::NotFound)),
+        );
+        (mock, root, a, b)
+    }
+
+    #[tokio::test]
+    async fn test_walk() {
+        let (mock, root, a, b) = dag_mock();
+        assert_eq!(
+            BTreeSet::from([root, a, b]),
+            walk(mock, root).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_ls_rm() {
+        let (mock, root, a, b) = dag_mock();
+        let pins = PinStore::memory();
+        add(mock.clone(), &pins, root, true).await.unwrap();
+        add(mock.clone(), &pins, a, false).await.unwrap();
+
+        assert_eq!(
+            BTreeMap::from([
+                (root, PinType::Recursive),
+                (a, PinType::Direct),
+                (b, PinType::Indirect)
+            ]),
+            ls(mock.clone(), &pins, None).await.unwrap()
+        );
+        assert_eq!(
+            BTreeMap::from([(a, PinType::Direct)]),
+            ls(mock.clone(), &pins, Some(PinType::Direct))
+                .await
+                .unwrap()
+        );
+
+        assert!(matches!(
+            rm(&pins, root, false).await,
+            Err(Error::Invalid(_))
+        ));
+        rm(&pins, root, true).await.unwrap();
+        assert!(matches!(rm(&pins, root, true).await, Err(Error::NotFound)));
+        assert_eq!(
+            BTreeMap::from([(a, PinType::Direct)]),
+            ls(mock, &pins, None).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pin_store_persists() {
+        let (mock, root, a, _) = dag_mock();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins");
+
+        let pins = PinStore::open(&path).unwrap();
+        assert!(path.exists());
+        add(mock.clone(), &pins, root, true).await.unwrap();
+        add(mock, &pins, a, false).await.unwrap();
+
+        let pins = PinStore::open(&path).unwrap();
+        assert_eq!(
+            BTreeMap::from([(root, PinType::Recursive), (a, PinType::Direct)]),
+            pins.pins()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pin_store_failed_persist() {
+        let (mock, root, _, _) = dag_mock();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins");
+
+        let pins = PinStore::open(&path).unwrap();
+        // A directory in place of the temporary file makes every write fail.
+        fs::create_dir(path.with_extension("tmp")).unwrap();
+        assert!(matches!(
+            add(mock, &pins, root, true).await,
+            Err(Error::Internal(_))
+        ));
+        assert_eq!(None, pins.get(&root));
+    }
+}
@@ -0,0 +1,73 @@
+//! Implements the pubsub related endpoints.
+use futures_util::{future, Stream, StreamExt};
+use iroh_api::{Bytes, PeerId};
+use libp2p::gossipsub::GossipsubEvent;
+
+use crate::{error::Error, IpfsDep};
+
+/// A message received on a pub/sub topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Peer that authored the message, if known.
+    pub from: Option<PeerId>,
+    /// Content of the message.
+    pub data: Bytes,
+    /// Sequence number of the message, if known.
+    pub seqno: Option<u64>,
+    /// Topic of the message.
+    pub topic: String,
+}
+
+/// Publish a message on a pub/sub topic.
+#[tracing::instrument(skip(client, data))]
+pub async fn publish<T>(client: T, topic: String, data: Bytes) -> Result<(), Error>
+where
+    T: IpfsDep,
+{
+    client.publish(topic, data).await
+}
+
+/// Subscribe to a pub/sub topic returning a stream of the messages received on the topic.
+#[tracing::instrument(skip(client))]
+pub async fn subscribe<T>(
+    client: T,
+    topic: String,
+) -> Result<impl Stream<Item = Result<Message, Error>>, Error>
+where
+    T: IpfsDep,
+{
+    Ok(client.subscribe(topic).await?.filter_map(|event| {
+        future::ready(match event {
+            Ok(GossipsubEvent::Message { message, .. }) => Some(Ok(Message {
+                from: message.source,
+                data: message.data.into(),
+                seqno: message.sequence_number,
+                topic: message.topic.into_string(),
+            })),
+            // Ignore other gossipsub events, i.e. peers (un)subscribing
+            Ok(_) => None,
+            Err(e) => Some(Err(Error::Internal(e.context("receiving pub/sub message")))),
+        })
+    }))
+}
+
+/// List the pub/sub topics to which we are currently subscribed.
+#[tracing::instrument(skip(client))]
+pub async fn topics<T>(client: T) -> Result<Vec<String>, Error>
+where
+    T: IpfsDep,
+{
+    client.topics().await
+}
+
+/// List the peers we are connected to via pub/sub, optionally only peers on a specific topic.
+#[tracing::instrument(skip(client))]
+pub async fn peers<T>(client: T, topic: Option<String>) -> Result<Vec<PeerId>, Error>
+where
+    T: IpfsDep,
+{
+    let mut peers = client.topic_peers(topic).await?;
+    // Sort peers for consistent ordering
+    peers.sort();
+    Ok(peers)
+}
@@ -0,0 +1,158 @@
+//! Implements garbage collection of the repository.
+use std::collections::BTreeSet;
+
+use futures_util::{stream, Stream, StreamExt};
+use iroh_api::Cid;
+
+use crate::{
+    error::Error,
+    pin::{self, PinStore, PinType},
+    IpfsDep,
+};
+
+/// A block removed by garbage collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Removed {
+    /// Cid of the removed block.
+    pub cid: Cid,
+    /// Size of the removed block in bytes.
+    pub size: u64,
+}
+
+/// Garbage collect all blocks that are not reachable from a pin.
+///
+/// Blocks are marked by walking every pin and all unmarked blocks are then swept from the store.
+/// Only blocks present before marking begins are considered for removal,
+/// so blocks stored concurrently with garbage collection are retained.
+/// Blocks are matched by codec and multihash, so a block pinned by its CIDv0 is retained
+/// when the store lists it by its CIDv1 and vice versa.
+/// Pins cannot change until the returned stream is dropped.
+///
+/// Returns a stream of the removed blocks.
+#[tracing::instrument(skip(client, pins))]
+pub async fn gc<T>(
+    client: T,
+    pins: &PinStore,
+) -> Result<impl Stream<Item = Result<Removed, Error>>, Error>
+where
+    T: IpfsDep + 'static,
+{
+    let guard = pins.lock().await;
+    let blocks = client.blocks().await?;
+    let marked: BTreeSet<Cid> = mark(client.clone(), pins).await?.iter().map(v1).collect();
+    let unmarked: Vec<Cid> = blocks
+        .into_iter()
+        .filter(|cid| !marked.contains(&v1(cid)))
+        .collect();
+    Ok(stream::iter(unmarked).then(move |cid| {
+        let _guard = &guard;
+        let client = client.clone();
+        async move {
+            let size = client.block_rm(cid).await?;
+            Ok(Removed { cid, size })
+        }
+    }))
+}
+
+// Report the Cids of all blocks reachable from a pin.
+async fn mark<T>(client: T, pins: &PinStore) -> Result<BTreeSet<Cid>, Error>
+where
+    T: IpfsDep,
+{
+    let mut marked = BTreeSet::new();
+    for (cid, typ) in pins.pins() {
+        match typ {
+            PinType::Recursive => marked.extend(pin::walk(client.clone(), cid).await?),
+            PinType::Direct | PinType::Indirect => {
+                marked.insert(cid);
+            }
+        }
+    }
+    Ok(marked)
+}
+
+// Version 1 of a Cid, the Cids of both versions refer to the same block.
+fn v1(cid: &Cid) -> Cid {
+    Cid::new_v1(cid.codec(), *cid.hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures_util::{FutureExt, TryStreamExt};
+    use libipld::{
+        ipld,
+        multihash::{Code, MultihashDigest},
+    };
+    use unimock::{matching, MockFn, Unimock};
+
+    use super::*;
+    use crate::{testing::cbor_block, IpfsDepMock};
+
+    #[tokio::test]
+    async fn test_gc() {
+        let (leaf, leaf_bytes) = cbor_block(&ipld!({"leaf": true}));
+        let (root, root_bytes) = cbor_block(&ipld!({"next": leaf}));
+        let (direct, direct_bytes) = cbor_block(&ipld!({"direct": true}));
+        let (garbage, garbage_bytes) = cbor_block(&ipld!({"garbage": true}));
+        let garbage_size = garbage_bytes.len() as u64;
+        let blocks = HashMap::from([
+            (leaf, leaf_bytes),
+            (root, root_bytes),
+            (direct, direct_bytes),
+            (garbage, garbage_bytes),
+        ]);
+        let all: Vec<Cid> = blocks.keys().copied().collect();
+        let mock = Unimock::new((
+            IpfsDepMock::blocks
+                .each_call(matching!(()))
+                .answers(move |_| Ok(all.clone())),
+            IpfsDepMock::block_get
+                .each_call(matching!(_))
+                .answers(move |cid| blocks.get(&cid).cloned().ok_or(Error::NotFound)),
+            IpfsDepMock::block_rm
+                .each_call(matching!((c) if *c == garbage))
+                .answers(move |_| Ok(garbage_size)),
+        ));
+
+        let pins = PinStore::memory();
+        pin::add(mock.clone(), &pins, root, true).await.unwrap();
+        pin::add(mock.clone(), &pins, direct, false).await.unwrap();
+
+        let removed = gc(mock, &pins).await.unwrap();
+        // Pins cannot change until garbage collection completes
+        assert!(pin::rm(&pins, direct, false).now_or_never().is_none());
+        let removed: Vec<Removed> = removed.try_collect().await.unwrap();
+        assert_eq!(
+            vec![Removed {
+                cid: garbage,
+                size: garbage_size
+            }],
+            removed
+        );
+        pin::rm(&pins, direct, false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gc_cid_versions() {
+        // An empty dag-pb node pinned by its CIDv0 and listed by its CIDv1
+        let pinned = Cid::new_v0(Code::Sha2_256.digest(b"")).unwrap();
+        let listed = v1(&pinned);
+        assert_ne!(pinned, listed);
+        let mock = Unimock::new((
+            IpfsDepMock::blocks
+                .each_call(matching!(()))
+                .answers(move |_| Ok(vec![listed])),
+            IpfsDepMock::block_get
+                .each_call(matching!((c) if *c == pinned))
+                .answers(|_| Ok(Default::default())),
+        ));
+
+        let pins = PinStore::memory();
+        pin::add(mock.clone(), &pins, pinned, true).await.unwrap();
+
+        let removed: Vec<Removed> = gc(mock, &pins).await.unwrap().try_collect().await.unwrap();
+        assert!(removed.is_empty(), "{:?}", removed);
+    }
+}
@@ -3,17 +3,17 @@ use std::collections::BTreeMap;
 
 use iroh_api::{Multiaddr, PeerId};
 
-use crate::{error::Error, IpfsDep};
+use crate::{error::Error, IpfsDep, PeerConnection};
 
-/// Report all connected peers of the current node.
+/// Report all connected peers of the current node, optionally with the protocols of each peer.
 #[tracing::instrument(skip(client))]
-pub async fn peers<T>(client: T) -> Result<BTreeMap<PeerId, Vec<Multiaddr>>, Error>
+pub async fn peers<T>(client: T, protocols: bool) -> Result<BTreeMap<PeerId, PeerConnection>, Error>
 where
     T: IpfsDep,
 {
     // Use a BTreeMap for consistent ordering of peers
     Ok(client
-        .peers()
+        .peers(protocols)
         .await?
         .into_iter()
         .collect::<BTreeMap<_, _>>())
@@ -29,3 +29,54 @@ where
 
     Ok(())
 }
+
+/// Disconnect from a specific peer node.
+#[tracing::instrument(skip(client))]
+pub async fn disconnect<T>(client: T, peer_id: PeerId) -> Result<(), Error>
+where
+    T: IpfsDep,
+{
+    client.disconnect(peer_id).await?;
+
+    Ok(())
+}
+
+/// Report the known addresses of peers.
+///
+/// Only the addresses of connected peers are known.
+#[tracing::instrument(skip(client))]
+pub async fn addrs<T>(client: T) -> Result<BTreeMap<PeerId, Vec<Multiaddr>>, Error>
+where
+    T: IpfsDep,
+{
+    Ok(peers(client)
+        .await?
+        .into_iter()
+        .map(|(peer_id, connection)| (peer_id, connection.addrs))
+        .collect())
+}
+
+/// Report the addresses of the current node, both listening and as observed by other peers.
+#[tracing::instrument(skip(client))]
+pub async fn local_addrs<T>(client: T) -> Result<Vec<Multiaddr>, Error>
+where
+    T: IpfsDep,
+{
+    let info = client.id().await?;
+    let mut addrs = info.listen_addrs;
+    for addr in info.observed_addrs {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    Ok(addrs)
+}
+
+/// Report the addresses on which the current node is listening.
+#[tracing::instrument(skip(client))]
+pub async fn listen_addrs<T>(client: T) -> Result<Vec<Multiaddr>, Error>
+where
+    T: IpfsDep,
+{
+    client.listeners().await
+}
@@ -0,0 +1,127 @@
+//! Helpers and fixtures shared by the tests of this crate and its dependents.
+use std::collections::HashMap;
+
+use iroh_api::{Bytes, Cid};
+use libipld::{
+    cbor::DagCborCodec,
+    multihash::{Code, MultihashDigest},
+    prelude::Encode,
+    Ipld,
+};
+use unimock::{matching, MockFn, Unimock};
+
+use crate::{dag, error::Error, IpfsDepMock};
+
+/// Encode the data as a dag-cbor block, reporting its CIDv1 and bytes.
+pub fn cbor_block(data: &Ipld) -> (Cid, Bytes) {
+    let mut blob: Vec<u8> = Vec::new();
+    data.encode(DagCborCodec, &mut blob).unwrap();
+    let cid = Cid::new_v1(DagCborCodec.into(), Code::Sha2_256.digest(&blob));
+    (cid, blob.into())
+}
+
+/// Construct a mock that serves the blocks of the fixtures via get, decoding them as the Iroh API does.
+pub fn mock_blocks(fixtures: &[(&str, &str)]) -> Unimock {
+    let blocks: HashMap<Cid, Vec<u8>> = fixtures
+        .iter()
+        .map(|(cid, data)| (cid.parse().unwrap(), hex::decode(data).unwrap()))
+        .collect();
+    Unimock::new(
+        IpfsDepMock::get
+            .each_call(matching!(_))
+            .answers(move |path| {
+                let cid = *path.cid().unwrap();
+                let bytes = blocks.get(&cid).ok_or(Error::NotFound)?;
+                Ok((cid, dag::decode(&cid, bytes)?))
+            }),
+    )
+}
+
+// Blocks of a model instance document stream with a signed genesis commit,
+// a signed data commit and an anchor commit, along with an unsigned genesis commit.
+// Each fixture is the Cid of the block and the hex encoded block data,
+// as printed by `ipfs block get <cid> | xxd -p -c0`.
+// These are not mainnet commits: they follow the structure of mainnet commits and are
+// signed by a did:key generated for these tests. Commits captured from mainnet can replace
+// them, test_fixtures checks that every block matches its Cid and every signature is valid.
+
+/// Payload of the signed genesis commit of a model instance document.
+pub const MID_GENESIS_PAYLOAD: (&str, &str) = (
+    "bafyreie7jryvd6g7alrk4y4e7g7h5rtolpbl2dgdva6ebbgeopbpdjqaoa",
+    "a26464617461a263616765181e646e616d6565616c69636566686561646572a463736570656d6f64656c656d6f64656c5828ce0102018501122054b36d564eadf2dc3fa62b5e34f05066ae8affd81eb965249639f0a755d1b3bc66756e697175654ca1b2c3d4e5f60718293a4b5c6b636f6e74726f6c6c6572738178386469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b",
+);
+/// Signed genesis commit of a model instance document.
+pub const MID_GENESIS: (&str, &str) = (
+    "bagcqcerai4bfz5k4izt5cgkz4ccalsga5bgm6s4cv2pfekvbpobyb7h2pstq",
+    "a2677061796c6f61645824017112209f4c7151f8df02e2ae6384f9be7ec66e5bc2bd0cc3a83c4084c473c2f1a600706a7369676e61747572657381a26970726f74656374656458817b22616c67223a224564445341222c226b6964223a226469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b237a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b227d697369676e6174757265584073fb4524082bbc797142218d173f65337b422ceb5c873e14ac8ff897e964416811032e3b9dd9368a1e30b6961c441cb03cbd418150fbd45eba126ff55888aa04",
+);
+/// Payload of the signed data commit of the model instance document.
+pub const MID_DATA_PAYLOAD: (&str, &str) = (
+    "bafyreif42fnlcozrhvyyg3vbdeqllhywwxcz6afayxuklpx2tap6736f3q",
+    "a4626964d82a582600018501122047025cf55c4667d11959e08405c8c0e84ccf4b82ae9e522aa17b8380fcfa7ca7646461746181a3626f70677265706c6163656470617468642f6167656576616c7565181f6470726576d82a582600018501122047025cf55c4667d11959e08405c8c0e84ccf4b82ae9e522aa17b8380fcfa7ca766686561646572a0",
+);
+/// Signed data commit of the model instance document.
+pub const MID_DATA: (&str, &str) = (
+    "bagcqcerakt5kfrgwnxgtooh7v6lkjg7hryvtf7hndkt6bb5yi3rorpautfkq",
+    "a2677061796c6f6164582401711220bcd15ab13b313d71836ea11920b59f16b5c59f00a0c5e8a5befa981fefefc5dc6a7369676e61747572657381a26970726f74656374656458817b22616c67223a224564445341222c226b6964223a226469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b237a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b227d697369676e61747572655840846ce9a798d7bf754a4357ba4264447234246475c5a9a9f0cf97eebd7df1a113251e790a6dc5e4c90c6ff05aa53b2f5233b047b98802d998f7b89732c6cd1b0c",
+);
+/// Root of the Merkle tree anchoring the data commit of the model instance document.
+pub const MID_ROOT: (&str, &str) = (
+    "bafyreiczohg6vdw2qimm5cvqzwllu3zutqxphacpnyq5rrhnio2te3rdne",
+    "82d82a582600018501122054faa2c4d66dcd3738ffaf96a49be78e2b32fced1aa7e087b846e2e8bc149955f6",
+);
+/// Anchor proof of the model instance document.
+pub const MID_PROOF: (&str, &str) = (
+    "bafyreia3frwxbov6pzpygpxl5nfnsryizw6zzogkz3tctsxui6cmwfzfue",
+    "a464726f6f74d82a582500017112205971cdea8eda8218ce8ab0cd96ba6f349c2ef3804f6e21d8c4ed43b5326e236966747848617368d82a5826000193011b201b5b9ccb3e8d006a5230de9bda23ff91edc794d4f56410560830b418528e446c667478547970656a6628627974657333322967636861696e4964686569703135353a31",
+);
+/// Anchor commit of the model instance document.
+pub const MID_ANCHOR: (&str, &str) = (
+    "bafyreicpfxvzpwfv5jlreb5pu6t3tz7idl52ljur37iv2jo47wdbgwqom4",
+    "a4626964d82a582600018501122047025cf55c4667d11959e08405c8c0e84ccf4b82ae9e522aa17b8380fcfa7ca7647061746861306470726576d82a582600018501122054faa2c4d66dcd3738ffaf96a49be78e2b32fced1aa7e087b846e2e8bc1499556570726f6f66d82a582500017112201b2c6d70babe7e5f833eebeb4ad94708cdbd9cb8cacee629caf44784cb1725a1",
+);
+/// Unsigned genesis commit of a tile document.
+pub const UNSIGNED_GENESIS: (&str, &str) = (
+    "bafyreicl2ixonon2g7foyfxtutjlyx4aaype6v3tm6kilm7ar62amqqaye",
+    "a166686561646572a26666616d696c79634944586b636f6e74726f6c6c6572738178386469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b",
+);
+
+/// All fixtures above.
+pub const FIXTURES: [(&str, &str); 8] = [
+    MID_GENESIS_PAYLOAD,
+    MID_GENESIS,
+    MID_DATA_PAYLOAD,
+    MID_DATA,
+    MID_ROOT,
+    MID_PROOF,
+    MID_ANCHOR,
+    UNSIGNED_GENESIS,
+];
+
+/// The did:key that signs the fixtures.
+pub const DID: &str = "did:key:z6Mkn9LLDGb5CYhAktT5Roc3KR79g4ATHxAJhwLAnFGF7NSK";
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use dag_jose::DagJoseCodec;
+
+    use super::*;
+
+    #[test]
+    fn test_fixtures() {
+        for fixture in FIXTURES {
+            let bytes = hex::decode(fixture.1).unwrap();
+            dag::verify(&fixture.0.parse().unwrap(), &bytes).unwrap();
+        }
+        for fixture in [MID_GENESIS, MID_DATA] {
+            let verifications = dag::verify_signatures(
+                DagJoseCodec,
+                &mut Cursor::new(hex::decode(fixture.1).unwrap()),
+            )
+            .unwrap();
+            assert_eq!(1, verifications.len());
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Implements the version endpoint.
+
+/// Version information about the node software.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// Version of the node software.
+    pub version: String,
+    /// Build string describing the commit of the node software.
+    pub commit: String,
+    /// Architecture and operating system of the node.
+    pub system: String,
+}
+
+impl Version {
+    /// Construct version information for the current system.
+    pub fn new(version: impl Into<String>, commit: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+            commit: commit.into(),
+            system: format!("{}/{}", std::env::consts::ARCH, std::env::consts::OS),
+        }
+    }
+}
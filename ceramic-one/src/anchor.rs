@@ -0,0 +1,643 @@
+//! Implements verification of anchor commits against the blockchain transactions of their proofs.
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ceramic_core::event::{Anchor, AnchorProof};
+use ceramic_kubo_rpc::IpfsDep;
+use iroh_api::IpfsPath;
+use libipld::{cid::Cid, multihash::Multihash, Ipld};
+use serde_json::{json, Value};
+
+// Transaction type of proofs whose transaction calls anchorDagCbor(bytes32) with the digest of the root.
+const V1_PROOF_TYPE: &str = "f(bytes32)";
+// Function selector of anchorDagCbor(bytes32), the first four bytes of the keccak-256 of the signature.
+const ANCHOR_DAG_CBOR_SELECTOR: &str = "97ad09eb";
+// Multicodec of Ethereum transactions and multihash code of keccak-256, the hashes of transactions.
+const ETH_TX_CODEC: u64 = 0x93;
+const KECCAK_256: u64 = 0x1b;
+// Ceramic anchor contract of each chain, same as js-ceramic.
+const ANCHOR_CONTRACTS: &[(&str, &str)] =
+    &[("eip155:1", "0x231055A0852D67C7107Ad0d0DFeab60278fE6AdC")];
+// Unix timestamp of each chain after which anchors are made through the anchor contract, same as js-ceramic.
+// Legacy proofs carry no contract to check, so they are only trusted in blocks before it.
+const LEGACY_PROOF_THRESHOLDS: &[(&str, i64)] = &[("eip155:1", 1_615_800_000)];
+
+/// Validates that the transaction of an anchor proof was included in a blockchain and anchors the proof root.
+#[async_trait]
+pub trait AnchorValidator: Send + Sync {
+    /// Validate the transaction of the proof, returning the unix timestamp of the block containing it.
+    async fn validate(&self, proof: &AnchorProof) -> Result<i64>;
+}
+
+/// Verify an anchor commit and return the timestamp of its anchor.
+///
+/// The Merkle path of the anchor is walked from the root of the proof and must lead to the anchored commit,
+/// then the transaction of the proof is validated.
+#[tracing::instrument(skip(client, validator))]
+pub async fn verify<T>(
+    client: T,
+    validator: &dyn AnchorValidator,
+    anchor: &Anchor,
+    proof: &AnchorProof,
+) -> Result<i64>
+where
+    T: IpfsDep,
+{
+    let cid = walk_path(client, proof.root, &anchor.path).await?;
+    if cid != anchor.prev {
+        bail!(
+            "path {} of anchor proof leads to {} not to the anchored commit {}",
+            anchor.path,
+            cid,
+            anchor.prev
+        );
+    }
+    validator.validate(proof).await
+}
+
+// Follow the path through the Merkle tree, each node is a list of links to its children.
+async fn walk_path<T>(client: T, root: Cid, path: &str) -> Result<Cid>
+where
+    T: IpfsDep,
+{
+    let mut cid = root;
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let index: usize = segment
+            .parse()
+            .map_err(|_| anyhow!("invalid segment {} of anchor path {}", segment, path))?;
+        let (_, node) = client.get(&IpfsPath::from_cid(cid)).await?;
+        cid = match node {
+            Ipld::List(list) => match list.get(index) {
+                Some(Ipld::Link(child)) => *child,
+                _ => bail!("merkle tree node {} has no link at index {}", cid, index),
+            },
+            _ => bail!("merkle tree node {} is not a list", cid),
+        };
+    }
+    Ok(cid)
+}
+
+/// Validates anchor transactions using the JSON-RPC API of an Ethereum node.
+///
+/// Transactions must call the Ceramic anchor contract of the chain, except legacy proofs included
+/// in blocks from before the contract was used.
+pub struct EthereumRpc {
+    client: reqwest::Client,
+    url: String,
+    chain_id: String,
+    contract: Option<String>,
+}
+
+impl EthereumRpc {
+    /// Connect to the JSON-RPC API at the url, determining the CAIP-2 id of the chain it serves.
+    ///
+    /// The anchor contract defaults to the Ceramic anchor contract of the chain, when known.
+    pub async fn new(url: String, contract: Option<String>) -> Result<Self> {
+        let mut rpc = Self {
+            client: reqwest::Client::new(),
+            url,
+            chain_id: String::new(),
+            contract,
+        };
+        let chain_id = rpc.call("eth_chainId", json!([])).await?;
+        rpc.chain_id = format!("eip155:{}", quantity(&chain_id)?);
+        if rpc.contract.is_none() {
+            rpc.contract = lookup(ANCHOR_CONTRACTS, &rpc.chain_id).map(str::to_string);
+        }
+        Ok(rpc)
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("{} failed: {}", method, error);
+        }
+        Ok(response["result"].clone())
+    }
+}
+
+#[async_trait]
+impl AnchorValidator for EthereumRpc {
+    async fn validate(&self, proof: &AnchorProof) -> Result<i64> {
+        if proof.chain_id != self.chain_id {
+            bail!(
+                "anchor proof is on chain {} but the node serves chain {}",
+                proof.chain_id,
+                self.chain_id
+            );
+        }
+        let tx_hash = format!("0x{}", hex::encode(proof.tx_hash.hash().digest()));
+        let tx = self
+            .call("eth_getTransactionByHash", json!([tx_hash]))
+            .await?;
+        if tx.is_null() {
+            bail!("transaction {} not found", tx_hash);
+        }
+        let input = tx["input"]
+            .as_str()
+            .ok_or_else(|| anyhow!("transaction {} has no input", tx_hash))?;
+        if !input.eq_ignore_ascii_case(&transaction_input(proof)?) {
+            bail!(
+                "transaction {} does not anchor the root {}",
+                tx_hash,
+                proof.root
+            );
+        }
+        let legacy = proof.tx_type.as_deref() != Some(V1_PROOF_TYPE);
+        if !legacy {
+            let contract = self.contract.as_deref().ok_or_else(|| {
+                anyhow!(
+                    "the anchor contract of chain {} is not known, pass --anchor-contract",
+                    self.chain_id
+                )
+            })?;
+            let to = tx["to"].as_str().unwrap_or("none");
+            if !to.eq_ignore_ascii_case(contract) {
+                bail!(
+                    "transaction {} was sent to {} not to the anchor contract {}",
+                    tx_hash,
+                    to,
+                    contract
+                );
+            }
+        }
+        let block_hash = match &tx["blockHash"] {
+            Value::String(block_hash) => block_hash.clone(),
+            _ => bail!("transaction {} is not included in a block", tx_hash),
+        };
+        let block = self
+            .call("eth_getBlockByHash", json!([block_hash, false]))
+            .await?;
+        let timestamp = i64::try_from(quantity(&block["timestamp"])?)
+            .map_err(|_| anyhow!("block {} has an invalid timestamp", block_hash))?;
+        if legacy {
+            match lookup(LEGACY_PROOF_THRESHOLDS, &self.chain_id) {
+                Some(threshold) if timestamp < threshold => {}
+                _ => bail!(
+                    "transaction {} of a legacy anchor proof was included after anchors \
+                    moved to the anchor contract",
+                    tx_hash
+                ),
+            }
+        }
+        Ok(timestamp)
+    }
+}
+
+// Look up the value of the chain in a table of chains.
+fn lookup<T: Copy>(table: &[(&str, T)], chain_id: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(chain, _)| *chain == chain_id)
+        .map(|(_, value)| *value)
+}
+
+// Construct the hex encoded input of a transaction anchoring the root of the proof.
+fn transaction_input(proof: &AnchorProof) -> Result<String> {
+    match proof.tx_type.as_deref() {
+        Some(V1_PROOF_TYPE) => {
+            // The contract recreates the root Cid as dag-cbor with a sha2-256 multihash.
+            if proof.root.codec() != 0x71 || proof.root.hash().code() != 0x12 {
+                bail!(
+                    "root {} of {} anchor proof must be dag-cbor and sha2-256",
+                    proof.root,
+                    V1_PROOF_TYPE
+                );
+            }
+            Ok(format!(
+                "0x{}{}",
+                ANCHOR_DAG_CBOR_SELECTOR,
+                hex::encode(proof.root.hash().digest())
+            ))
+        }
+        // Legacy proofs store the bytes of the root Cid as the input.
+        None | Some("raw") => Ok(format!("0x{}", hex::encode(proof.root.to_bytes()))),
+        Some(tx_type) => bail!("unsupported anchor transaction type {}", tx_type),
+    }
+}
+
+// Parse a hex encoded JSON-RPC quantity.
+fn quantity(value: &Value) -> Result<u64> {
+    value
+        .as_str()
+        .and_then(|s| s.strip_prefix("0x"))
+        .and_then(|s| u64::from_str_radix(s, 16).ok())
+        .ok_or_else(|| anyhow!("invalid quantity {}", value))
+}
+
+/// Parse the 0x prefixed hex encoded hash of an Ethereum transaction into the Cid used by anchor proofs.
+pub fn parse_tx_hash(tx_hash: &str) -> Result<Cid> {
+    let digest = tx_hash
+        .strip_prefix("0x")
+        .and_then(|digest| hex::decode(digest).ok())
+        .filter(|digest| digest.len() == 32)
+        .ok_or_else(|| anyhow!("invalid transaction hash {}", tx_hash))?;
+    Ok(Cid::new_v1(
+        ETH_TX_CODEC,
+        Multihash::wrap(KECCAK_256, &digest)?,
+    ))
+}
+
+/// Validates anchor transactions against trusted timestamps recorded in memory.
+///
+/// The input of the transactions is not known, so they are trusted to anchor the root of any proof.
+#[derive(Default)]
+pub struct MemoryAnchorValidator {
+    timestamps: HashMap<Cid, i64>,
+}
+
+impl MemoryAnchorValidator {
+    /// Record that the transaction was included in a block with the timestamp.
+    pub fn insert(&mut self, tx_hash: Cid, timestamp: i64) {
+        self.timestamps.insert(tx_hash, timestamp);
+    }
+}
+
+#[async_trait]
+impl AnchorValidator for MemoryAnchorValidator {
+    async fn validate(&self, proof: &AnchorProof) -> Result<i64> {
+        match self.timestamps.get(&proof.tx_hash) {
+            Some(timestamp) => Ok(*timestamp),
+            None => bail!("transaction {} not found", proof.tx_hash),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ceramic_kubo_rpc::{error::Error, IpfsDepMock};
+    use libipld::ipld;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use unimock::{matching, MockFn, Unimock};
+
+    use super::*;
+
+    // Construct a Cid whose digest is the byte n repeated.
+    fn cid(n: u8) -> Cid {
+        Cid::new_v1(0x71, Multihash::wrap(0x12, &[n; 32]).unwrap())
+    }
+
+    // A Merkle tree with the commit cid(1) at path 1/0 and the commit cid(2) at path 0.
+    fn mock_tree() -> Unimock {
+        let nodes = HashMap::from([
+            (cid(10), ipld!([cid(2), cid(11)])),
+            (cid(11), ipld!([cid(1), cid(3)])),
+        ]);
+        Unimock::new(
+            IpfsDepMock::get
+                .each_call(matching!(_))
+                .answers(move |path| {
+                    let cid = *path.cid().unwrap();
+                    Ok((cid, nodes.get(&cid).ok_or(Error::NotFound)?.clone()))
+                }),
+        )
+    }
+
+    fn proof() -> AnchorProof {
+        AnchorProof {
+            chain_id: "eip155:1".to_string(),
+            root: cid(10),
+            tx_hash: Cid::from_str("bagjqcgzadnnzzsz6ruaguurq32n5ui77shw4pfgu6vsbavqigc2bquuoirwa")
+                .unwrap(),
+            tx_type: Some(V1_PROOF_TYPE.to_string()),
+        }
+    }
+
+    fn anchor(prev: Cid, path: &str) -> Anchor {
+        Anchor {
+            id: cid(0),
+            prev,
+            proof: cid(20),
+            path: path.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let mut validator = MemoryAnchorValidator::default();
+        validator.insert(proof().tx_hash, 1_680_000_000);
+        let timestamp = verify(mock_tree(), &validator, &anchor(cid(1), "1/0"), &proof())
+            .await
+            .unwrap();
+        assert_eq!(1_680_000_000, timestamp);
+        let timestamp = verify(mock_tree(), &validator, &anchor(cid(2), "0"), &proof())
+            .await
+            .unwrap();
+        assert_eq!(1_680_000_000, timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_verify_wrong_path() {
+        let mut validator = MemoryAnchorValidator::default();
+        validator.insert(proof().tx_hash, 1_680_000_000);
+        let err = verify(mock_tree(), &validator, &anchor(cid(1), "1/1"), &proof())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!(
+                "path 1/1 of anchor proof leads to {} not to the anchored commit {}",
+                cid(3),
+                cid(1)
+            ),
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_path_past_leaf() {
+        let validator = MemoryAnchorValidator::default();
+        let err = verify(mock_tree(), &validator, &anchor(cid(1), "0/0"), &proof())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::NotFound)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_unknown_transaction() {
+        let validator = MemoryAnchorValidator::default();
+        let err = verify(mock_tree(), &validator, &anchor(cid(1), "1/0"), &proof())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "transaction bagjqcgzadnnzzsz6ruaguurq32n5ui77shw4pfgu6vsbavqigc2bquuoirwa not found",
+            err.to_string()
+        );
+    }
+
+    // Hash of the transaction of the proof as reported by Ethereum nodes.
+    const TX_HASH: &str = "0x1b5b9ccb3e8d006a5230de9bda23ff91edc794d4f56410560830b418528e446c";
+
+    // Read an HTTP request with a JSON body from the stream.
+    async fn read_request(stream: &mut TcpStream) -> Value {
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            request.extend_from_slice(&chunk[..n]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                let len: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|len| len.trim().parse().unwrap())
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + len {
+                    return serde_json::from_slice(&request[end + 4..end + 4 + len]).unwrap();
+                }
+            }
+            assert!(n > 0, "connection closed before the request was read");
+        }
+    }
+
+    // Serve JSON-RPC calls on a local port, answering each with the result of the handler.
+    async fn stub_rpc<F>(handler: F) -> String
+    where
+        F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let request = read_request(&mut stream).await;
+                    let result = handler(request["method"].as_str().unwrap(), &request["params"]);
+                    let body = json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+                        .to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        url
+    }
+
+    // Anchor contract of the chain of the proof.
+    const CONTRACT: &str = "0x231055a0852d67c7107ad0d0dfeab60278fe6adc";
+
+    // Connect to a stub Ethereum node serving the chain and answering with the transaction,
+    // the block of the transaction has the timestamp 1680000000.
+    async fn ethereum(chain_id: &'static str, tx: Value) -> EthereumRpc {
+        ethereum_at(chain_id, tx, "0x6422c400").await
+    }
+
+    // Connect to a stub Ethereum node, the block of the transaction has the timestamp.
+    async fn ethereum_at(
+        chain_id: &'static str,
+        tx: Value,
+        timestamp: &'static str,
+    ) -> EthereumRpc {
+        let url = stub_rpc(move |method, params| match method {
+            "eth_chainId" => json!(chain_id),
+            "eth_getTransactionByHash" => {
+                assert_eq!(&json!([TX_HASH]), params);
+                tx.clone()
+            }
+            "eth_getBlockByHash" => {
+                assert_eq!(&json!(["0xb1", false]), params);
+                json!({ "timestamp": timestamp })
+            }
+            method => panic!("unexpected call to {}", method),
+        })
+        .await;
+        EthereumRpc::new(url, None).await.unwrap()
+    }
+
+    // A transaction calling the anchor contract with the root of the proof.
+    fn contract_tx(to: &str) -> Value {
+        json!({
+            "to": to,
+            "input": format!("0x97ad09eb{}", "0a".repeat(32)),
+            "blockHash": "0xb1",
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc() {
+        let rpc = ethereum("0x1", contract_tx(CONTRACT)).await;
+        assert_eq!(1_680_000_000, rpc.validate(&proof()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_other_contract() {
+        let other = "0x2fd866b7082220c0bb7c104147f732e83062bf97";
+        let rpc = ethereum("0x1", contract_tx(other)).await;
+        assert_eq!(
+            format!(
+                "transaction {} was sent to {} not to the anchor contract \
+                0x231055A0852D67C7107Ad0d0DFeab60278fE6AdC",
+                TX_HASH, other
+            ),
+            rpc.validate(&proof()).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_unknown_contract() {
+        let url = stub_rpc(|method, _| match method {
+            "eth_chainId" => json!("0x64"),
+            _ => contract_tx(CONTRACT),
+        })
+        .await;
+        let rpc = EthereumRpc::new(url, None).await.unwrap();
+        let proof = AnchorProof {
+            chain_id: "eip155:100".to_string(),
+            ..proof()
+        };
+        assert_eq!(
+            "the anchor contract of chain eip155:100 is not known, pass --anchor-contract",
+            rpc.validate(&proof).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_legacy() {
+        let proof = AnchorProof {
+            tx_type: None,
+            ..proof()
+        };
+        // Legacy transactions are sent to any address
+        let tx = json!({
+            "to": "0x2fd866b7082220c0bb7c104147f732e83062bf97",
+            "input": format!("0x01711220{}", "0a".repeat(32)),
+            "blockHash": "0xb1",
+        });
+        // 2021-03-01
+        let rpc = ethereum_at("0x1", tx.clone(), "0x603c2e80").await;
+        assert_eq!(1_614_556_800, rpc.validate(&proof).await.unwrap());
+        let rpc = ethereum("0x1", tx).await;
+        assert_eq!(
+            format!(
+                "transaction {} of a legacy anchor proof was included after anchors \
+                moved to the anchor contract",
+                TX_HASH
+            ),
+            rpc.validate(&proof).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_other_chain() {
+        let rpc = ethereum("0x5", Value::Null).await;
+        assert_eq!(
+            "anchor proof is on chain eip155:1 but the node serves chain eip155:5",
+            rpc.validate(&proof()).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_unknown_transaction() {
+        let rpc = ethereum("0x1", Value::Null).await;
+        assert_eq!(
+            format!("transaction {} not found", TX_HASH),
+            rpc.validate(&proof()).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_other_input() {
+        let rpc = ethereum(
+            "0x1",
+            json!({
+                "to": CONTRACT,
+                "input": format!("0x97ad09eb{}", "0b".repeat(32)),
+                "blockHash": "0xb1",
+            }),
+        )
+        .await;
+        assert_eq!(
+            format!(
+                "transaction {} does not anchor the root {}",
+                TX_HASH,
+                cid(10)
+            ),
+            rpc.validate(&proof()).await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ethereum_rpc_pending_transaction() {
+        let rpc = ethereum(
+            "0x1",
+            json!({
+                "to": CONTRACT,
+                "input": format!("0x97ad09eb{}", "0a".repeat(32)),
+                "blockHash": null,
+            }),
+        )
+        .await;
+        assert_eq!(
+            format!("transaction {} is not included in a block", TX_HASH),
+            rpc.validate(&proof()).await.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_tx_hash() {
+        assert_eq!(proof().tx_hash, parse_tx_hash(TX_HASH).unwrap());
+        assert!(parse_tx_hash(&TX_HASH[2..]).is_err());
+        assert!(parse_tx_hash("0x1b5b").is_err());
+    }
+
+    #[test]
+    fn test_transaction_input() {
+        assert_eq!(
+            format!("0x97ad09eb{}", "0a".repeat(32)),
+            transaction_input(&proof()).unwrap()
+        );
+        assert_eq!(
+            format!("0x01711220{}", "0a".repeat(32)),
+            transaction_input(&AnchorProof {
+                tx_type: None,
+                ..proof()
+            })
+            .unwrap()
+        );
+        assert_eq!(
+            "unsupported anchor transaction type f(bytes)",
+            transaction_input(&AnchorProof {
+                tx_type: Some("f(bytes)".to_string()),
+                ..proof()
+            })
+            .unwrap_err()
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(1, quantity(&json!("0x1")).unwrap());
+        assert_eq!(1_680_000_000, quantity(&json!("0x6422c400")).unwrap());
+        assert!(quantity(&json!(1)).is_err());
+        assert!(quantity(&json!("1")).is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! Layered configuration of a node.
+//!
+//! Values are read from a TOML file and overridden by `CERAMIC_ONE_*` environment variables,
+//! which are in turn overridden by command line flags. Values missing from all layers use defaults.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{network::Network, store::Backend};
+
+/// Name of the config file read from the data directory when no config file is given.
+const CONFIG_FILE: &str = "config.toml";
+
+/// Effective configuration of a node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name of the Ceramic network the node participates in
+    pub network: String,
+    /// Pub/sub topic of a local network
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_topic: Option<String>,
+    /// Local storage of blocks and pins
+    pub store: StoreConfig,
+    /// Peer to peer networking
+    pub p2p: P2pConfig,
+    /// Kubo RPC HTTP API
+    pub http: HttpConfig,
+    /// Metrics and tracing collection
+    pub metrics: MetricsConfig,
+}
+
+/// Configuration of local storage.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// Directory containing the block store and pins, defaults to the data directory for mainnet
+    /// and to a directory of the data directory named after the network otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Storage backend of blocks
+    pub backend: Backend,
+}
+
+/// Configuration of peer to peer networking.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct P2pConfig {
+    /// Multiaddrs to listen on for peer connections
+    pub listen_addrs: Vec<String>,
+    /// Multiaddrs of peers to connect to on startup, defaults to the peers of the network
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bootstrap_addrs: Option<Vec<String>>,
+}
+
+/// Configuration of the HTTP API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address the HTTP API is served on
+    pub bind_address: String,
+}
+
+/// Configuration of metrics and tracing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Push metrics to the Prometheus push gateway
+    pub collect: bool,
+    /// Export traces to the OpenTelemetry collector
+    pub tracing: bool,
+    /// Endpoint of the Prometheus push gateway
+    pub prometheus_endpoint: String,
+    /// Endpoint of the OpenTelemetry collector
+    pub tracing_endpoint: String,
+    /// Address the Prometheus metrics of the HTTP API are served on, not served when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            network: "mainnet".to_string(),
+            network_topic: None,
+            store: StoreConfig::default(),
+            p2p: P2pConfig::default(),
+            http: HttpConfig::default(),
+            metrics: MetricsConfig::default(),
+        }
+    }
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        Self {
+            listen_addrs: vec![
+                "/ip4/0.0.0.0/tcp/0".to_string(),
+                "/ip4/0.0.0.0/udp/0/quic-v1".to_string(),
+            ],
+            bootstrap_addrs: None,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:5001".to_string(),
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            collect: false,
+            tracing: false,
+            prometheus_endpoint: "http://localhost:9091".to_string(),
+            tracing_endpoint: "http://localhost:4317".to_string(),
+            bind_address: None,
+        }
+    }
+}
+
+/// Flags overriding the config file, each may also be set with a `CERAMIC_ONE_*` environment variable.
+#[derive(Args, Debug, Default)]
+pub struct ConfigOpts {
+    /// Path to a TOML config file, defaults to config.toml in the data directory if it exists
+    #[arg(long, global = true, env = "CERAMIC_ONE_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Name of the Ceramic network: mainnet, testnet-clay, dev-unstable, local or inmemory
+    #[arg(long, global = true, env = "CERAMIC_ONE_NETWORK")]
+    pub network: Option<String>,
+    /// Pub/sub topic of a local network
+    #[arg(long, global = true, env = "CERAMIC_ONE_NETWORK_TOPIC")]
+    pub network_topic: Option<String>,
+    /// Directory containing the block store and pins
+    #[arg(long, global = true, env = "CERAMIC_ONE_STORE_PATH")]
+    pub store_path: Option<PathBuf>,
+    /// Storage backend of blocks, the daemon only runs backends other than rocksdb with
+    /// `--network inmemory` as peers are only served blocks from the rocksdb backend
+    #[arg(long, global = true, env = "CERAMIC_ONE_STORE_BACKEND", value_enum)]
+    pub store_backend: Option<Backend>,
+    /// Comma separated multiaddrs to listen on for peer connections
+    #[arg(
+        long,
+        global = true,
+        env = "CERAMIC_ONE_LISTEN_ADDRS",
+        value_delimiter = ','
+    )]
+    pub listen_addrs: Option<Vec<String>>,
+    /// Comma separated multiaddrs of peers to connect to on startup
+    #[arg(
+        long,
+        global = true,
+        env = "CERAMIC_ONE_BOOTSTRAP_ADDRS",
+        value_delimiter = ','
+    )]
+    pub bootstrap_addrs: Option<Vec<String>>,
+    /// Address the HTTP API is served on
+    #[arg(short, long, global = true, env = "CERAMIC_ONE_BIND_ADDRESS")]
+    pub bind_address: Option<String>,
+    /// Push metrics to the Prometheus push gateway
+    #[arg(short, long, global = true, env = "CERAMIC_ONE_METRICS", num_args = 0..=1, default_missing_value = "true")]
+    pub metrics: Option<bool>,
+    /// Export traces to the OpenTelemetry collector
+    #[arg(short, long, global = true, env = "CERAMIC_ONE_TRACING", num_args = 0..=1, default_missing_value = "true")]
+    pub tracing: Option<bool>,
+    /// Endpoint of the Prometheus push gateway
+    #[arg(long, global = true, env = "CERAMIC_ONE_PROMETHEUS_ENDPOINT")]
+    pub prometheus_endpoint: Option<String>,
+    /// Endpoint of the OpenTelemetry collector
+    #[arg(long, global = true, env = "CERAMIC_ONE_TRACING_ENDPOINT")]
+    pub tracing_endpoint: Option<String>,
+    /// Address the Prometheus metrics of the HTTP API are served on
+    #[arg(long, global = true, env = "CERAMIC_ONE_METRICS_BIND_ADDRESS")]
+    pub metrics_bind_address: Option<String>,
+}
+
+impl Config {
+    /// Load the config file and apply the overrides of the flags and environment.
+    /// The config file is read from the data directory when not given,
+    /// and the store defaults to the data directory or one of its directories.
+    pub fn load(opts: &ConfigOpts, data_dir: &Path) -> Result<Self> {
+        let config = match &opts.config {
+            Some(path) => Self::from_file(path)?,
+            None => {
+                let path = data_dir.join(CONFIG_FILE);
+                if path.exists() {
+                    Self::from_file(&path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+        config.with_overrides(opts).resolve(data_dir)
+    }
+
+    /// Read a TOML config file, missing values use defaults.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read config {}: {}", path.display(), err))?;
+        toml::from_str(&contents)
+            .map_err(|err| anyhow!("invalid config {}: {}", path.display(), err))
+    }
+
+    /// Replace values of the config with those set by the flags or environment.
+    pub fn with_overrides(mut self, opts: &ConfigOpts) -> Self {
+        if let Some(network) = &opts.network {
+            self.network = network.clone();
+        }
+        if let Some(topic) = &opts.network_topic {
+            self.network_topic = Some(topic.clone());
+        }
+        if let Some(path) = &opts.store_path {
+            self.store.path = Some(path.clone());
+        }
+        if let Some(backend) = opts.store_backend {
+            self.store.backend = backend;
+        }
+        if let Some(listen_addrs) = &opts.listen_addrs {
+            self.p2p.listen_addrs = listen_addrs.clone();
+        }
+        if let Some(bootstrap_addrs) = &opts.bootstrap_addrs {
+            self.p2p.bootstrap_addrs = Some(bootstrap_addrs.clone());
+        }
+        if let Some(bind_address) = &opts.bind_address {
+            self.http.bind_address = bind_address.clone();
+        }
+        if let Some(collect) = opts.metrics {
+            self.metrics.collect = collect;
+        }
+        if let Some(tracing) = opts.tracing {
+            self.metrics.tracing = tracing;
+        }
+        if let Some(endpoint) = &opts.prometheus_endpoint {
+            self.metrics.prometheus_endpoint = endpoint.clone();
+        }
+        if let Some(endpoint) = &opts.tracing_endpoint {
+            self.metrics.tracing_endpoint = endpoint.clone();
+        }
+        if let Some(bind_address) = &opts.metrics_bind_address {
+            self.metrics.bind_address = Some(bind_address.clone());
+        }
+        self
+    }
+
+    /// Validate the network and fill in the values that default to those of the network.
+    pub fn resolve(mut self, data_dir: &Path) -> Result<Self> {
+        let network = self.network()?;
+        self.store.path = Some(
+            self.store
+                .path
+                .unwrap_or_else(|| default_store_path(data_dir, &network)),
+        );
+        self.p2p.bootstrap_addrs = Some(
+            self.p2p
+                .bootstrap_addrs
+                .unwrap_or_else(|| network.bootstrap_addrs()),
+        );
+        Ok(self)
+    }
+
+    /// The network the node participates in.
+    pub fn network(&self) -> Result<Network> {
+        Network::new(&self.network, self.network_topic.as_deref())
+    }
+
+    /// Directory containing the block store and pins.
+    pub fn store_path(&self) -> PathBuf {
+        self.store
+            .path
+            .clone()
+            .unwrap_or_else(|| match self.network() {
+                Ok(network) => default_store_path(&data_dir(), &network),
+                Err(_) => data_dir().join(&self.network),
+            })
+    }
+
+    /// Multiaddrs of peers to connect to on startup.
+    pub fn bootstrap_addrs(&self) -> Result<Vec<String>> {
+        match &self.p2p.bootstrap_addrs {
+            Some(addrs) => Ok(addrs.clone()),
+            None => Ok(self.network()?.bootstrap_addrs()),
+        }
+    }
+
+    /// Represent the config as TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
+
+// Mainnet nodes keep their store in the data directory itself as they did before networks were
+// selectable, the stores of other networks are kept apart in directories named after the network.
+fn default_store_path(data_dir: &Path, network: &Network) -> PathBuf {
+    match network {
+        Network::Mainnet => data_dir.to_path_buf(),
+        network => data_dir.join(network.name()),
+    }
+}
+
+/// Directory of the config file and the stores of the node, in the home directory of the user.
+pub fn data_dir() -> PathBuf {
+    match home::home_dir() {
+        Some(home_dir) => home_dir.join(".ceramic-one"),
+        None => PathBuf::from(".ceramic-one"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use clap::{CommandFactory, FromArgMatches, Parser};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        opts: ConfigOpts,
+    }
+
+    // Parse the flags, ignoring the CERAMIC_ONE_* environment variables of the test process.
+    fn opts(args: &[&str]) -> ConfigOpts {
+        let matches = Cli::command()
+            .mut_args(|arg| arg.env(None::<&'static str>))
+            .try_get_matches_from(std::iter::once("ceramic-one").chain(args.iter().copied()))
+            .unwrap();
+        Cli::from_arg_matches(&matches).unwrap().opts
+    }
+
+    // Load the config with an empty data directory instead of the one in the home directory.
+    fn load(args: &[&str]) -> Result<Config> {
+        let data_dir = TempDir::new().unwrap();
+        Config::load(&opts(args), data_dir.path())
+    }
+
+    const FILE: &str = r#"
+network = "testnet-clay"
+
+[store]
+path = "/var/lib/ceramic-one"
+
+[http]
+bind_address = "0.0.0.0:5001"
+
+[metrics]
+collect = true
+"#;
+
+    #[test]
+    fn test_partial_file() {
+        let config: Config = toml::from_str(FILE).unwrap();
+        assert_eq!(
+            Config {
+                network: "testnet-clay".to_string(),
+                network_topic: None,
+                store: StoreConfig {
+                    path: Some(PathBuf::from("/var/lib/ceramic-one")),
+                    ..Default::default()
+                },
+                http: HttpConfig {
+                    bind_address: "0.0.0.0:5001".to_string(),
+                },
+                metrics: MetricsConfig {
+                    collect: true,
+                    ..Default::default()
+                },
+                p2p: P2pConfig::default(),
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let err = toml::from_str::<Config>("[http]\nbind = \"0.0.0.0:5001\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown field `bind`"), "{}", err);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = Config::default()
+            .resolve(Path::new("/var/lib/ceramic-one"))
+            .unwrap();
+        assert_eq!(config, toml::from_str(&config.to_toml().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_flags_override_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(FILE.as_bytes()).unwrap();
+        let path = file.path().to_str().unwrap();
+        let config = load(&[
+            "--config",
+            path,
+            "--bind-address",
+            "127.0.0.1:5101",
+            "--metrics=false",
+            "--tracing",
+            "--metrics-bind-address",
+            "127.0.0.1:9464",
+            "--store-backend",
+            "sqlite",
+            "--listen-addrs",
+            "/ip4/0.0.0.0/tcp/4101,/ip4/0.0.0.0/udp/4101/quic-v1",
+        ])
+        .unwrap();
+        assert_eq!(
+            Config {
+                network: "testnet-clay".to_string(),
+                network_topic: None,
+                store: StoreConfig {
+                    path: Some(PathBuf::from("/var/lib/ceramic-one")),
+                    backend: Backend::Sqlite,
+                },
+                http: HttpConfig {
+                    bind_address: "127.0.0.1:5101".to_string(),
+                },
+                metrics: MetricsConfig {
+                    collect: false,
+                    tracing: true,
+                    bind_address: Some("127.0.0.1:9464".to_string()),
+                    ..Default::default()
+                },
+                p2p: P2pConfig {
+                    listen_addrs: vec![
+                        "/ip4/0.0.0.0/tcp/4101".to_string(),
+                        "/ip4/0.0.0.0/udp/4101/quic-v1".to_string(),
+                    ],
+                    bootstrap_addrs: Some(Network::TestnetClay.bootstrap_addrs()),
+                },
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let err = load(&["--config", "/does/not/exist.toml"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("failed to read config /does/not/exist.toml"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_data_dir_file() {
+        let data_dir = TempDir::new().unwrap();
+        std::fs::write(data_dir.path().join(CONFIG_FILE), FILE).unwrap();
+        let config = Config::load(&opts(&[]), data_dir.path()).unwrap();
+        assert_eq!("testnet-clay", config.network);
+        assert_eq!(PathBuf::from("/var/lib/ceramic-one"), config.store_path());
+    }
+
+    #[test]
+    fn test_network_defaults() {
+        let data_dir = TempDir::new().unwrap();
+        let config = Config::load(
+            &opts(&["--config", "/dev/null", "--network", "dev-unstable"]),
+            data_dir.path(),
+        )
+        .unwrap();
+        assert_eq!(Network::DevUnstable, config.network().unwrap());
+        assert_eq!(data_dir.path().join("dev-unstable"), config.store_path());
+        assert_eq!(
+            Network::DevUnstable.bootstrap_addrs(),
+            config.bootstrap_addrs().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_mainnet_store_path() {
+        let data_dir = TempDir::new().unwrap();
+        let config = Config::load(&opts(&["--config", "/dev/null"]), data_dir.path()).unwrap();
+        assert_eq!(Network::Mainnet, config.network().unwrap());
+        assert_eq!(data_dir.path(), config.store_path());
+    }
+
+    #[test]
+    fn test_local_network() {
+        let config = load(&[
+            "--config",
+            "/dev/null",
+            "--network",
+            "local",
+            "--network-topic",
+            "/ceramic/local-1234",
+            "--bootstrap-addrs",
+            "/ip4/127.0.0.1/tcp/4101",
+        ])
+        .unwrap();
+        assert_eq!(
+            Network::Local("/ceramic/local-1234".to_string()),
+            config.network().unwrap()
+        );
+        assert_eq!(
+            vec!["/ip4/127.0.0.1/tcp/4101".to_string()],
+            config.bootstrap_addrs().unwrap()
+        );
+        assert!(config
+            .to_toml()
+            .unwrap()
+            .contains("network_topic = \"/ceramic/local-1234\""));
+    }
+
+    #[test]
+    fn test_invalid_network() {
+        let err = load(&["--config", "/dev/null", "--network", "local"]).unwrap_err();
+        assert_eq!(
+            "the local network requires a network topic",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_no_overrides() {
+        let config = Config::default();
+        assert_eq!(
+            config.clone(),
+            config.with_overrides(&ConfigOpts::default())
+        );
+    }
+}
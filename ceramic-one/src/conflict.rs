@@ -0,0 +1,319 @@
+//! Selects the canonical state of a stream between competing tips.
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+use ceramic_kubo_rpc::IpfsDep;
+use libipld::cid::Cid;
+
+use crate::{
+    anchor::AnchorValidator,
+    state::{self, AnchorStatus, LogEntry, StreamState},
+};
+
+/// Load the log ending at a candidate tip and select the canonical state
+/// between it and the current state of the stream.
+#[tracing::instrument(skip(client, validator, current), fields(stream_id = %current.stream_id))]
+pub async fn resolve<T>(
+    client: T,
+    validator: Option<&dyn AnchorValidator>,
+    current: StreamState,
+    tip: Cid,
+) -> Result<StreamState>
+where
+    T: IpfsDep,
+{
+    // The candidate is already part of the current log, there is nothing to load.
+    if current.log.iter().any(|entry| entry.cid == tip) {
+        return Ok(current);
+    }
+    let candidate = state::load(client, validator, &current.stream_id, tip).await?;
+    pick(current, candidate)
+}
+
+/// Select the canonical state between two states of the same stream.
+///
+/// A log that extends the other log is always selected.
+/// Otherwise logs have diverged and the rules of Ceramic are applied in order:
+///  * an anchored log wins over a log that is not anchored,
+///  * when both are anchored the log with the earliest anchor wins,
+///    the timestamps of both anchors must be known, i.e. they must have been verified,
+///  * the longest log wins,
+///  * the log whose tip has the lowest Cid wins.
+pub fn pick(current: StreamState, candidate: StreamState) -> Result<StreamState> {
+    if current.stream_id != candidate.stream_id {
+        bail!(
+            "cannot select between states of different streams {} and {}",
+            current.stream_id,
+            candidate.stream_id
+        );
+    }
+    if is_prefix(&current.log, &candidate.log) {
+        return Ok(candidate);
+    }
+    if is_prefix(&candidate.log, &current.log) {
+        return Ok(current);
+    }
+    match compare(&current, &candidate)? {
+        Ordering::Greater => Ok(candidate),
+        Ordering::Less | Ordering::Equal => Ok(current),
+    }
+}
+
+// Order two diverged states so that the state that should be selected is the lesser.
+fn compare(a: &StreamState, b: &StreamState) -> Result<Ordering> {
+    match (a.anchor_status, b.anchor_status) {
+        (AnchorStatus::Anchored, AnchorStatus::NotRequested) => return Ok(Ordering::Less),
+        (AnchorStatus::NotRequested, AnchorStatus::Anchored) => return Ok(Ordering::Greater),
+        (AnchorStatus::Anchored, AnchorStatus::Anchored) => {
+            if let (Some(a_proof), Some(b_proof)) = (&a.anchor_proof, &b.anchor_proof) {
+                if a_proof.chain_id != b_proof.chain_id {
+                    bail!(
+                        "cannot compare anchors on different chains {} and {}",
+                        a_proof.chain_id,
+                        b_proof.chain_id
+                    );
+                }
+            }
+            match (a.anchor_timestamp(), b.anchor_timestamp()) {
+                (Some(a_time), Some(b_time)) => {
+                    // Anchors in the same block do not decide.
+                    if a_time != b_time {
+                        return Ok(a_time.cmp(&b_time));
+                    }
+                }
+                _ => bail!(
+                    "cannot compare anchors whose timestamps are not known, anchors must be verified"
+                ),
+            }
+        }
+        (AnchorStatus::NotRequested, AnchorStatus::NotRequested) => {}
+    }
+    match b.log.len().cmp(&a.log.len()) {
+        Ordering::Equal => Ok(a.tip().to_bytes().cmp(&b.tip().to_bytes())),
+        ordering => Ok(ordering),
+    }
+}
+
+// Report whether the log `a` is the start of the log `b`.
+fn is_prefix(a: &[LogEntry], b: &[LogEntry]) -> bool {
+    a.len() <= b.len() && a.iter().zip(b).all(|(a, b)| a.cid == b.cid)
+}
+
+#[cfg(test)]
+mod tests {
+    use ceramic_core::{event::AnchorProof, StreamId, StreamType};
+    use libipld::multihash::Multihash;
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::{CommitType, Metadata};
+
+    // Construct a Cid whose digest is the byte n repeated, so that Cids are ordered by n.
+    fn cid(n: u8) -> Cid {
+        Cid::new_v1(0x71, Multihash::wrap(0x12, &[n; 32]).unwrap())
+    }
+
+    fn stream_id() -> StreamId {
+        StreamId::new(StreamType::Tile, cid(0))
+    }
+
+    // A commit following the genesis commit: the byte of its Cid, its type and anchor timestamp.
+    type Commit = (u8, CommitType, Option<i64>);
+
+    const D: CommitType = CommitType::Signed;
+    const A: CommitType = CommitType::Anchor;
+
+    // Construct the state of a stream with the given commits following the genesis commit.
+    fn state(commits: &[Commit], chain_id: &str) -> StreamState {
+        let mut log = vec![LogEntry {
+            cid: cid(0),
+            commit_type: CommitType::Genesis,
+            timestamp: None,
+        }];
+        log.extend(commits.iter().map(|(n, commit_type, timestamp)| LogEntry {
+            cid: cid(*n),
+            commit_type: *commit_type,
+            timestamp: *timestamp,
+        }));
+        let anchored = log.last().unwrap().commit_type == CommitType::Anchor;
+        let has_anchor = log
+            .iter()
+            .any(|entry| entry.commit_type == CommitType::Anchor);
+        StreamState {
+            stream_id: stream_id(),
+            content: json!({}),
+            metadata: Metadata::default(),
+            log,
+            anchor_status: if anchored {
+                AnchorStatus::Anchored
+            } else {
+                AnchorStatus::NotRequested
+            },
+            anchor_proof: has_anchor.then(|| AnchorProof {
+                chain_id: chain_id.to_string(),
+                root: cid(255),
+                tx_hash: cid(254),
+                tx_type: None,
+            }),
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Winner {
+        Current,
+        Candidate,
+    }
+
+    #[test]
+    fn test_pick() {
+        use Winner::*;
+        let cases: &[(&str, &[Commit], &[Commit], Winner)] = &[
+            (
+                "candidate extends current",
+                &[(1, D, None), (2, A, Some(100))],
+                &[(1, D, None), (2, A, Some(100)), (3, D, None)],
+                Candidate,
+            ),
+            (
+                "current extends candidate",
+                &[(1, D, None), (2, D, None)],
+                &[(1, D, None)],
+                Current,
+            ),
+            (
+                "candidate is the current log",
+                &[(1, D, None)],
+                &[(1, D, None)],
+                Current,
+            ),
+            (
+                "only current is anchored",
+                &[(1, D, None), (2, A, Some(100))],
+                &[(3, D, None), (4, D, None), (5, D, None)],
+                Current,
+            ),
+            (
+                "only candidate is anchored",
+                &[(3, D, None), (4, D, None), (5, D, None)],
+                &[(1, D, None), (2, A, Some(100))],
+                Candidate,
+            ),
+            (
+                "candidate anchored earlier",
+                &[(1, D, None), (2, A, Some(200))],
+                &[(3, D, None), (4, A, Some(100))],
+                Candidate,
+            ),
+            (
+                "current anchored earlier than a longer candidate",
+                &[(1, D, None), (2, A, Some(100))],
+                &[(3, D, None), (4, D, None), (5, A, Some(200))],
+                Current,
+            ),
+            (
+                "anchored in the same block, candidate is longer",
+                &[(1, D, None), (2, A, Some(100))],
+                &[(3, D, None), (4, D, None), (5, A, Some(100))],
+                Candidate,
+            ),
+            (
+                "neither anchored, current is longer",
+                &[(1, D, None), (2, D, None)],
+                &[(3, D, None)],
+                Current,
+            ),
+            (
+                "neither anchored, candidate is longer",
+                &[(1, D, None)],
+                &[(2, D, None), (3, D, None)],
+                Candidate,
+            ),
+            (
+                "neither anchored, same length, candidate has the lowest tip",
+                &[(5, D, None)],
+                &[(4, D, None)],
+                Candidate,
+            ),
+            (
+                "neither anchored, same length, current has the lowest tip",
+                &[(4, D, None)],
+                &[(5, D, None)],
+                Current,
+            ),
+            (
+                "anchored in the same block, same length, candidate has the lowest tip",
+                &[(1, D, None), (4, A, Some(100))],
+                &[(2, D, None), (3, A, Some(100))],
+                Candidate,
+            ),
+            (
+                "anchored in the same block, same length, current has the lowest tip",
+                &[(2, D, None), (3, A, Some(100))],
+                &[(1, D, None), (4, A, Some(100))],
+                Current,
+            ),
+            (
+                "diverged after a shared anchor, candidate anchored earlier",
+                &[(1, A, Some(50)), (2, D, None), (3, A, Some(200))],
+                &[(1, A, Some(50)), (4, A, Some(100))],
+                Candidate,
+            ),
+        ];
+        for (name, current, candidate, winner) in cases {
+            let current = state(current, "eip155:1");
+            let candidate = state(candidate, "eip155:1");
+            let expected = match winner {
+                Current => current.clone(),
+                Candidate => candidate.clone(),
+            };
+            assert_eq!(
+                expected,
+                pick(current, candidate).unwrap(),
+                "expected {:?} to win when {}",
+                winner,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_pick_different_chains() {
+        let err = pick(
+            state(&[(1, D, None), (2, A, Some(100))], "eip155:1"),
+            state(&[(3, D, None), (4, A, Some(100))], "eip155:5"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "cannot compare anchors on different chains eip155:1 and eip155:5",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_pick_unknown_timestamps() {
+        let err = pick(
+            state(&[(1, D, None), (2, D, None), (3, A, None)], "eip155:1"),
+            state(&[(4, D, None), (5, A, Some(100))], "eip155:1"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "cannot compare anchors whose timestamps are not known, anchors must be verified",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_pick_different_streams() {
+        let mut candidate = state(&[(1, D, None)], "eip155:1");
+        candidate.stream_id = StreamId::new(StreamType::Tile, cid(9));
+        let err = pick(state(&[(2, D, None)], "eip155:1"), candidate).unwrap_err();
+        assert_eq!(
+            format!(
+                "cannot select between states of different streams {} and {}",
+                stream_id(),
+                StreamId::new(StreamType::Tile, cid(9))
+            ),
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,592 @@
+//! Imports the blocks and pins of a go-ipfs/Kubo repository.
+//!
+//! Kubo stores each block in its own file in the `blocks` directory of a flatfs datastore,
+//! named after the base32 encoding of the multihash of the block. As the file name does not
+//! record the codec of the block, the Cids of blocks are found by walking the DAGs of the pins,
+//! whose links carry the codec of each block. Only the codec of blocks that no pin reaches is
+//! derived from the data of the block.
+//!
+//! The pins are read from the leveldb datastore of the repository, so Kubo must not be running.
+//! Repositories using the badger datastore are not supported.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use ceramic_kubo_rpc::{
+    block, dag,
+    pin::{self, PinStore},
+    IpfsDep,
+};
+use libipld::{
+    cbor::DagCborCodec,
+    cid::Cid,
+    multihash::Multihash,
+    pb::DagPbCodec,
+    prelude::{Codec, Decode},
+    Ipld,
+};
+use rusty_leveldb::LdbIterator;
+
+const DAG_PB: u64 = 0x70;
+const DAG_CBOR: u64 = 0x71;
+const DAG_JOSE: u64 = 0x85;
+const RAW: u64 = 0x55;
+const SHA2_256: u64 = 0x12;
+
+// Prefix of the keys of the pins in the datastore, since Kubo 0.8.
+const PIN_PREFIX: &[u8] = b"/pins/pin/";
+// Key of the root of the pins in the datastore, before Kubo 0.8.
+const LEGACY_PINS_KEY: &[u8] = b"/local/pins";
+// Mode of recursive pins in the datastore.
+const RECURSIVE: i128 = 0;
+
+/// Summary of an import.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of imported blocks
+    pub blocks: u64,
+    /// Total size of the imported blocks in bytes
+    pub bytes: u64,
+    /// Number of imported blocks that no pin reaches, whose codec was derived from their data
+    pub guessed: u64,
+    /// Number of imported recursive pins
+    pub pins: u64,
+}
+
+// A block file of the flatfs datastore.
+struct BlockFile {
+    path: PathBuf,
+    hash: Multihash,
+    // The Cid of the block, when the key of the file is a whole Cid.
+    cid: Option<Cid>,
+}
+
+/// Import every block of the repository and pin its recursive pins.
+///
+/// Blocks are verified against their Cid and stored with the same `put` as `dag put`.
+/// Progress is reported after each block file with the number of imported files and the total.
+pub async fn import<T>(
+    client: T,
+    repo: &Path,
+    pins: &PinStore,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<Stats>
+where
+    T: IpfsDep,
+{
+    let recursive_pins = read_pins(repo)?;
+    let files = block_files(repo)?
+        .into_iter()
+        .map(|path| {
+            let (hash, cid) =
+                file_key(&path).map_err(|err| anyhow!("block {}: {}", path.display(), err))?;
+            Ok(BlockFile { path, hash, cid })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let total = files.len() as u64;
+    let by_hash: HashMap<Multihash, &BlockFile> =
+        files.iter().map(|file| (file.hash, file)).collect();
+
+    let mut stats = Stats::default();
+    let mut imported: HashSet<Multihash> = HashSet::new();
+    // Walk the pinned DAGs, the links of each block give the exact Cids of its children.
+    // Blocks are visited once per codec, a block linked by its CIDv0 and its CIDv1 is the same.
+    let mut visited: HashSet<Cid> = HashSet::new();
+    let mut queue = recursive_pins.clone();
+    while let Some(cid) = queue.pop() {
+        if !visited.insert(Cid::new_v1(cid.codec(), *cid.hash())) {
+            continue;
+        }
+        // Missing blocks are reported when pinning.
+        let file = match by_hash.get(cid.hash()) {
+            Some(file) => file,
+            None => continue,
+        };
+        let blob = read_block(&file.path)?;
+        let size = blob.len() as u64;
+        queue.extend(import_block(&client, file, cid, blob).await?);
+        // A block linked under several codecs is stored once per codec but counted once.
+        if imported.insert(file.hash) {
+            stats.blocks += 1;
+            stats.bytes += size;
+            progress(imported.len() as u64, total);
+        }
+    }
+
+    // Nothing links to the remaining blocks, so their codec is unknown unless their key is a Cid.
+    for file in &files {
+        if imported.contains(&file.hash) {
+            continue;
+        }
+        let blob = read_block(&file.path)?;
+        let cid = match file.cid {
+            Some(cid) => cid,
+            None => {
+                stats.guessed += 1;
+                guess_cid(file.hash, &blob)?
+            }
+        };
+        stats.blocks += 1;
+        stats.bytes += blob.len() as u64;
+        import_block(&client, file, cid, blob).await?;
+        imported.insert(file.hash);
+        progress(imported.len() as u64, total);
+    }
+
+    // Pinning walks each DAG, so every block of the pinned DAGs must have been imported.
+    for cid in &recursive_pins {
+        pin::add(client.clone(), pins, *cid, true)
+            .await
+            .map_err(|err| anyhow!("pin {}: {}", cid, err))?;
+        stats.pins += 1;
+    }
+    Ok(stats)
+}
+
+// Verify and store a block, returning its links.
+async fn import_block<T>(client: &T, file: &BlockFile, cid: Cid, blob: Vec<u8>) -> Result<Vec<Cid>>
+where
+    T: IpfsDep,
+{
+    dag::verify(&cid, &blob).map_err(|err| anyhow!("block {}: {}", file.path.display(), err))?;
+    let links = block::links(&cid, &blob).map_err(|err| anyhow!("block {}: {}", cid, err))?;
+    client.put(cid, blob.into(), links.clone()).await?;
+    Ok(links)
+}
+
+fn read_block(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|err| anyhow!("failed to read block {}: {}", path.display(), err))
+}
+
+/// Read the recursive pins from the leveldb datastore of the repository.
+///
+/// Kubo stores each pin in the datastore under `/pins/pin/<id>` as a dag-cbor map of its Cid
+/// and mode. Pins of other modes are skipped.
+pub fn read_pins(repo: &Path) -> Result<Vec<Cid>> {
+    if repo.join("badgerds").exists() {
+        bail!(
+            "{} uses the badger datastore which is not supported, \
+            only the default flatfs and leveldb datastores are",
+            repo.display()
+        );
+    }
+    let path = repo.join("datastore");
+    if !path.join("CURRENT").exists() {
+        bail!(
+            "{} is not a Kubo repository with a leveldb datastore",
+            repo.display()
+        );
+    }
+    let options = rusty_leveldb::Options {
+        create_if_missing: false,
+        ..Default::default()
+    };
+    let mut db = rusty_leveldb::DB::open(&path, options).map_err(|err| {
+        anyhow!(
+            "failed to open {}, is Kubo running? {}",
+            path.display(),
+            err
+        )
+    })?;
+    let mut entries = db.new_iter()?;
+    let mut pins = Vec::new();
+    let mut legacy = false;
+    while let Some((key, value)) = entries.next() {
+        if key == LEGACY_PINS_KEY {
+            legacy = true;
+        }
+        if !key.starts_with(PIN_PREFIX) {
+            continue;
+        }
+        let id = String::from_utf8_lossy(&key[PIN_PREFIX.len()..]).into_owned();
+        if let Some(cid) = decode_pin(&value).map_err(|err| anyhow!("pin {}: {}", id, err))? {
+            pins.push(cid);
+        }
+    }
+    // Kubo converts the pins to the current format the first time it opens the repository.
+    if legacy && pins.is_empty() {
+        bail!(
+            "the pins of {} are stored in the format of Kubo before 0.8, \
+            run a newer Kubo once to convert them",
+            repo.display()
+        );
+    }
+    pins.sort();
+    Ok(pins)
+}
+
+// Decode a pin of the datastore, returning its Cid when it is recursive.
+fn decode_pin(value: &[u8]) -> Result<Option<Cid>> {
+    let map = match decode_all(DagCborCodec, value) {
+        Some(Ipld::Map(map)) => map,
+        _ => bail!("malformed pin"),
+    };
+    match (map.get("cid"), map.get("mode")) {
+        (Some(Ipld::Link(cid)), Some(Ipld::Integer(mode))) => {
+            Ok((*mode == RECURSIVE).then_some(*cid))
+        }
+        _ => bail!("malformed pin"),
+    }
+}
+
+// List the block files of the flatfs datastore, in every shard directory.
+fn block_files(repo: &Path) -> Result<Vec<PathBuf>> {
+    let blocks = repo.join("blocks");
+    if !blocks.join("SHARDING").exists() {
+        bail!(
+            "{} is not a Kubo repository with a flatfs datastore",
+            repo.display()
+        );
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![blocks];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension() == Some("data".as_ref()) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Decode the key of a block file, the multihash of the block and its Cid when the key is one.
+//
+// Repositories written before Kubo 0.12 use the bytes of the Cid as the key of CIDv1 blocks,
+// these keys start with the Cid version which is not a valid hash function code.
+fn file_key(path: &Path) -> Result<(Multihash, Option<Cid>)> {
+    let name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid block file name"))?;
+    let key = multibase::Base::Base32Upper
+        .decode(name)
+        .map_err(|err| anyhow!("invalid block file name {}: {}", name, err))?;
+    if key.first() == Some(&1) {
+        let cid = Cid::try_from(key)?;
+        return Ok((*cid.hash(), Some(cid)));
+    }
+    Ok((Multihash::from_bytes(&key)?, None))
+}
+
+// Guess the Cid of a block from its multihash and data.
+fn guess_cid(hash: Multihash, blob: &[u8]) -> Result<Cid> {
+    Ok(match detect_codec(blob) {
+        // Kubo refers to dag-pb blocks with CIDv0.
+        DAG_PB if hash.code() == SHA2_256 => Cid::new_v0(hash)?,
+        codec => Cid::new_v1(codec, hash),
+    })
+}
+
+// Detect the codec of a block from its data.
+//
+// Ceramic commits are CBOR maps, with signed and encrypted commits using the dag-jose layout.
+// Blocks that are neither CBOR maps nor dag-pb nodes are raw.
+fn detect_codec(blob: &[u8]) -> u64 {
+    if let Some(Ipld::Map(map)) = decode_all(DagCborCodec, blob) {
+        let jws = map.contains_key("payload") && map.contains_key("signatures");
+        let jwe = map.contains_key("ciphertext") && map.contains_key("protected");
+        if jws || jwe {
+            DAG_JOSE
+        } else {
+            DAG_CBOR
+        }
+    } else if decode_all(DagPbCodec, blob).is_some() {
+        DAG_PB
+    } else {
+        RAW
+    }
+}
+
+// Decode the block as a single value, trailing data means the block uses another codec.
+fn decode_all<C>(codec: C, blob: &[u8]) -> Option<Ipld>
+where
+    C: Codec,
+    Ipld: Decode<C>,
+{
+    let mut reader = Cursor::new(blob);
+    let data = Ipld::decode(codec, &mut reader).ok()?;
+    (reader.position() == blob.len() as u64).then_some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use ceramic_kubo_rpc::{memory::MemoryIpfs, pin::PinType, PeerInfo};
+    use iroh_api::PeerId;
+    use libipld::{
+        ipld,
+        multihash::{Code, MultihashDigest},
+        prelude::Encode,
+    };
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn cbor(data: Ipld) -> Vec<u8> {
+        let mut blob: Vec<u8> = Vec::new();
+        data.encode(DagCborCodec, &mut blob).unwrap();
+        blob
+    }
+
+    // Create a repository with the recursive pins in its datastore.
+    fn repo(pins: &[Cid]) -> TempDir {
+        let repo = tempfile::tempdir().unwrap();
+        fs::create_dir(repo.path().join("blocks")).unwrap();
+        fs::write(
+            repo.path().join("blocks/SHARDING"),
+            "/repo/flatfs/shard/v1/next-to-last/2\n",
+        )
+        .unwrap();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = pins
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, cid)| (pin_key(i), cbor(ipld!({"cid": cid, "mode": 0}))))
+            .collect();
+        write_datastore(repo.path(), &entries);
+        repo
+    }
+
+    fn pin_key(i: usize) -> Vec<u8> {
+        format!("/pins/pin/CIQPIN{}", i).into_bytes()
+    }
+
+    fn write_datastore(repo: &Path, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let mut db =
+            rusty_leveldb::DB::open(repo.join("datastore"), rusty_leveldb::Options::default())
+                .unwrap();
+        for (key, value) in entries {
+            db.put(key, value).unwrap();
+        }
+        db.flush().unwrap();
+    }
+
+    // Write a block file the way flatfs does, sharded by the next to last two characters.
+    fn write_block(repo: &Path, key: &[u8], blob: &[u8]) {
+        let name = multibase::Base::Base32Upper.encode(key);
+        let dir = repo
+            .join("blocks")
+            .join(&name[name.len() - 3..name.len() - 1]);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.data", name)), blob).unwrap();
+    }
+
+    fn ipfs() -> MemoryIpfs {
+        MemoryIpfs::new(PeerInfo {
+            peer_id: "12D3KooWRyGSRzzEBpHbHyRkGTgCpXuoRMQgYrqk7tFQzM3AFEWp"
+                .parse::<PeerId>()
+                .unwrap(),
+            listen_addrs: vec![],
+            observed_addrs: vec![],
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "test".to_string(),
+            protocols: vec![],
+        })
+    }
+
+    #[test]
+    fn test_guess_cid() {
+        let cid_of = |blob: &[u8]| guess_cid(Code::Sha2_256.digest(blob), blob).unwrap();
+
+        let commit = cbor(ipld!({"header": {"controllers": ["did:key:z6Mk"]}}));
+        assert_eq!(DAG_CBOR, cid_of(&commit).codec());
+        let signed = cbor(ipld!({
+            "payload": Ipld::Bytes(vec![1, 2, 3]),
+            "signatures": [{"protected": Ipld::Bytes(vec![4]), "signature": Ipld::Bytes(vec![5])}],
+        }));
+        assert_eq!(DAG_JOSE, cid_of(&signed).codec());
+        assert_eq!(RAW, cid_of(b"hello world").codec());
+        // The empty unixfs directory created by ipfs init
+        assert_eq!(
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn",
+            cid_of(&[0x0a, 0x02, 0x08, 0x01]).to_string()
+        );
+    }
+
+    #[test]
+    fn test_file_key() {
+        let repo = repo(&[]);
+        let hash = Code::Sha2_256.digest(b"hello world");
+        write_block(repo.path(), &hash.to_bytes(), b"hello world");
+        // Keys of older repositories are the whole Cid
+        let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(b"{}"));
+        write_block(repo.path(), &cid.to_bytes(), b"{}");
+
+        let keys: HashMap<Multihash, Option<Cid>> = block_files(repo.path())
+            .unwrap()
+            .iter()
+            .map(|path| file_key(path).unwrap())
+            .collect();
+        assert_eq!(
+            HashMap::from([(hash, None), (*cid.hash(), Some(cid))]),
+            keys
+        );
+    }
+
+    #[test]
+    fn test_read_pins() {
+        let recursive = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(b"recursive"));
+        let direct = Cid::new_v0(Code::Sha2_256.digest(b"direct")).unwrap();
+        let repo = repo(&[]);
+        write_datastore(
+            repo.path(),
+            &[
+                (
+                    pin_key(0),
+                    cbor(ipld!({"cid": recursive, "mode": 0, "name": "x"})),
+                ),
+                (pin_key(1), cbor(ipld!({"cid": direct, "mode": 1}))),
+                (b"/pins/version".to_vec(), b"1".to_vec()),
+                (b"/peers/addrs".to_vec(), vec![]),
+            ],
+        );
+        assert_eq!(vec![recursive], read_pins(repo.path()).unwrap());
+
+        write_datastore(repo.path(), &[(pin_key(2), cbor(ipld!({"mode": 0})))]);
+        let err = read_pins(repo.path()).unwrap_err();
+        assert_eq!("pin CIQPIN2: malformed pin", err.to_string());
+    }
+
+    #[test]
+    fn test_read_legacy_pins() {
+        let repo = repo(&[]);
+        let root = Cid::new_v0(Code::Sha2_256.digest(b"pins")).unwrap();
+        write_datastore(repo.path(), &[(b"/local/pins".to_vec(), root.to_bytes())]);
+        let err = read_pins(repo.path()).unwrap_err();
+        assert!(err.to_string().contains("before 0.8"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        // A CBOR leaf linked as raw, which its data alone would not tell
+        let leaf = cbor(ipld!({"x": [1, 2, 3]}));
+        let leaf_cid = Cid::new_v1(RAW, Code::Sha2_256.digest(&leaf));
+        let root = cbor(ipld!({"link": leaf_cid}));
+        let root_cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&root));
+        // Blocks that no pin reaches
+        let other = cbor(ipld!({"y": 1}));
+        let other_cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&other));
+        let raw = b"hello world".to_vec();
+        let raw_cid = Cid::new_v1(RAW, Code::Sha2_256.digest(&raw));
+        let repo = repo(&[root_cid]);
+        write_block(repo.path(), &leaf_cid.hash().to_bytes(), &leaf);
+        write_block(repo.path(), &root_cid.hash().to_bytes(), &root);
+        write_block(repo.path(), &other_cid.hash().to_bytes(), &other);
+        write_block(repo.path(), &raw_cid.to_bytes(), &raw);
+        fs::write(repo.path().join("blocks/diskUsage.cache"), "{}").unwrap();
+
+        let ipfs = ipfs();
+        let pins = PinStore::memory();
+        let mut reported = Vec::new();
+        let stats = import(ipfs.clone(), repo.path(), &pins, |n, total| {
+            reported.push((n, total))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            Stats {
+                blocks: 4,
+                bytes: (leaf.len() + root.len() + other.len() + raw.len()) as u64,
+                guessed: 1,
+                pins: 1,
+            },
+            stats
+        );
+        assert_eq!(vec![(1, 4), (2, 4), (3, 4), (4, 4)], reported);
+        for (cid, blob) in [
+            (leaf_cid, leaf),
+            (root_cid, root),
+            (other_cid, other),
+            (raw_cid, raw),
+        ] {
+            assert_eq!(blob, ipfs.block_get(cid).await.unwrap().to_vec());
+        }
+        assert_eq!(Some(PinType::Recursive), pins.get(&root_cid));
+    }
+
+    #[tokio::test]
+    async fn test_import_linked_under_two_codecs() {
+        let leaf = cbor(ipld!({"x": [1, 2, 3]}));
+        let hash = Code::Sha2_256.digest(&leaf);
+        let (raw_cid, cbor_cid) = (Cid::new_v1(RAW, hash), Cid::new_v1(DAG_CBOR, hash));
+        let root = cbor(ipld!({"raw": raw_cid, "cbor": cbor_cid}));
+        let root_cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&root));
+        let repo = repo(&[root_cid]);
+        write_block(repo.path(), &hash.to_bytes(), &leaf);
+        write_block(repo.path(), &root_cid.hash().to_bytes(), &root);
+
+        let ipfs = ipfs();
+        let stats = import(ipfs.clone(), repo.path(), &PinStore::memory(), |_, _| {})
+            .await
+            .unwrap();
+        // The leaf is stored under both codecs and counted once
+        assert_eq!(
+            Stats {
+                blocks: 2,
+                bytes: (leaf.len() + root.len()) as u64,
+                guessed: 0,
+                pins: 1,
+            },
+            stats
+        );
+        for cid in [raw_cid, cbor_cid] {
+            assert_eq!(leaf, ipfs.block_get(cid).await.unwrap().to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_corrupt_block() {
+        let repo = repo(&[]);
+        let blob = cbor(ipld!({"x": 1}));
+        let hash = Code::Sha2_256.digest(b"something else");
+        write_block(repo.path(), &hash.to_bytes(), &blob);
+
+        let err = import(ipfs(), repo.path(), &PinStore::memory(), |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("does not match the hash"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_missing_pinned_block() {
+        let leaf = cbor(ipld!({"x": 1}));
+        let leaf_cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&leaf));
+        let root = cbor(ipld!({"link": leaf_cid}));
+        let root_cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&root));
+        let repo = repo(&[root_cid]);
+        write_block(repo.path(), &root_cid.hash().to_bytes(), &root);
+
+        let err = import(ipfs(), repo.path(), &PinStore::memory(), |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("pin "), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_import_not_kubo() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = import(ipfs(), dir.path(), &PinStore::memory(), |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not a Kubo repository"), "{}", err);
+
+        fs::create_dir(dir.path().join("badgerds")).unwrap();
+        let err = import(ipfs(), dir.path(), &PinStore::memory(), |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("badger datastore"), "{}", err);
+    }
+}
@@ -2,100 +2,635 @@
 #![deny(warnings)]
 #![deny(missing_docs)]
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
-use anyhow::Result;
-use clap::{Args, Parser, Subcommand};
-use iroh_embed::{IrohBuilder, Libp2pConfig, P2pService, RocksStoreService};
+use anyhow::{anyhow, bail, Result};
+use ceramic_core::{CommitId, StreamId};
+use ceramic_kubo_rpc::{pin::PinStore, version::Version};
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Config, ConfigOpts};
+use futures_util::StreamExt;
+use identity::Identity;
+use iroh_api::Multiaddr;
+use iroh_embed::{Iroh, IrohBuilder, Libp2pConfig, P2pService, RocksStoreService};
 use iroh_metrics::config::Config as MetricsConfig;
-use tracing::{debug, info};
+use libipld::cid::Cid;
+use store::{Backend, BlockStore, StoreIpfs};
+use tracing::{debug, info, warn};
+
+mod anchor;
+mod config;
+mod conflict;
+mod identity;
+mod kubo;
+mod network;
+mod state;
+mod store;
+
+// Compile time version information
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const BUILD: &str = git_version::git_version!(
+    prefix = "git:",
+    cargo_prefix = "cargo:",
+    fallback = "unknown"
+);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    #[command(flatten)]
+    config: ConfigOpts,
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
+    /// Run the node.
     Daemon(DaemonOpts),
+    /// Print the peer ID and public key of the node.
+    Id {
+        /// Path of the identity of the node, defaults to the identity in the store directory
+        /// and then to the key the p2p service created in the store directory
+        #[arg(long, env = "CERAMIC_ONE_IDENTITY")]
+        identity: Option<PathBuf>,
+    },
+    /// Remove all blocks that are not reachable from a pin, the daemon must not be running.
+    Gc,
+    /// Work with Ceramic stream identifiers.
+    #[command(subcommand)]
+    Streamid(StreamIdCommand),
+    /// Work with Ceramic streams, the daemon must not be running.
+    #[command(subcommand)]
+    Stream(StreamCommand),
+    /// Work with the configuration of the node.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Manage the libp2p identity of the node.
+    #[command(subcommand)]
+    Key(KeyCommand),
+    /// Work with the block store of the node, the daemon must not be running.
+    #[command(subcommand)]
+    Store(StoreCommand),
+    /// Import the data of other IPFS nodes, the daemon must not be running.
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
 }
 
-#[derive(Args, Debug)]
+#[derive(clap::Args, Debug)]
 struct DaemonOpts {
-    #[arg(short, long, default_value = "127.0.0.1:5001")]
-    bind_address: String,
-    #[arg(short, long, default_value_t = false)]
-    metrics: bool,
-    #[arg(short, long, default_value_t = false)]
-    tracing: bool,
+    /// Path of the identity of the node, defaults to the identity in the store directory.
+    /// When neither exists the p2p service uses the key of its keychain in the store directory,
+    /// creating it on first start, see `ceramic-one id`.
+    #[arg(long, env = "CERAMIC_ONE_IDENTITY")]
+    identity: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum StreamIdCommand {
+    /// Print the decoded parts of a StreamID or CommitID.
+    Inspect {
+        /// The StreamID or CommitID to inspect
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StreamCommand {
+    /// Print the state of a stream by replaying its commits from the local store.
+    State {
+        /// The StreamID of the stream, requires at least one tip, or the CommitID to load at its commit
+        id: String,
+        /// Tips of the stream, the canonical state among all tips is printed
+        #[arg(long = "tip")]
+        tips: Vec<String>,
+        /// Ethereum JSON-RPC endpoint used to verify anchor commits, required to select between anchored tips
+        #[arg(long)]
+        ethereum_rpc_url: Option<String>,
+        /// Address of the Ceramic anchor contract on the chain of the Ethereum JSON-RPC endpoint,
+        /// defaults to the contract of Ethereum mainnet
+        #[arg(long, requires = "ethereum_rpc_url")]
+        anchor_contract: Option<String>,
+        /// Trusted timestamp of the block including an anchor transaction as <TX_HASH>=<UNIX_TIMESTAMP>,
+        /// used instead of an Ethereum JSON-RPC endpoint
+        #[arg(long = "anchor-timestamp", conflicts_with = "ethereum_rpc_url")]
+        anchor_timestamps: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the effective config after applying the config file, environment and flags.
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum KeyCommand {
+    /// Generate a new identity and print its peer ID.
+    Generate {
+        /// Path of the new identity, defaults to the identity in the store directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format of the new identity
+        #[arg(long, value_enum, default_value_t = identity::Format::Pem)]
+        format: identity::Format,
+    },
+    /// Import an existing identity into the store directory and print its peer ID.
+    Import {
+        /// Path of the identity to import
+        file: PathBuf,
+        /// Format of the identity, detected when absent
+        #[arg(long, value_enum)]
+        format: Option<identity::Format>,
+    },
+    /// Export the identity of the store directory.
+    Export {
+        /// Path of the exported identity, written to stdout when absent
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format of the exported identity
+        #[arg(long, value_enum, default_value_t = identity::Format::Pem)]
+        format: identity::Format,
+    },
+    /// Print the peer ID and public key of an identity.
+    Show {
+        /// Path of the identity, defaults to the identity in the store directory
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StoreCommand {
+    /// Copy every block from one storage backend to another, verifying each block against its Cid.
+    Migrate {
+        /// Backend to copy blocks from
+        #[arg(long, value_enum)]
+        from: Backend,
+        /// Backend to copy blocks to
+        #[arg(long, value_enum)]
+        to: Backend,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MigrateCommand {
+    /// Import the blocks and recursive pins of a go-ipfs/Kubo repository with the default flatfs
+    /// and leveldb datastores, the badger datastore is not supported. Kubo must not be running.
+    FromKubo {
+        /// Path of the Kubo repository
+        #[arg(env = "IPFS_PATH")]
+        ipfs_path: PathBuf,
+    },
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    let args = Cli::parse();
-    match args.command {
-        Command::Daemon(opts) => daemon(opts).await,
+    let Cli {
+        command,
+        config: config_opts,
+    } = Cli::parse();
+    // Only commands working with the node load its config, so that an invalid config does not
+    // prevent using the other commands.
+    let load = || Config::load(&config_opts, &config::data_dir());
+    match command {
+        Command::Daemon(opts) => daemon(load()?, opts).await,
+        Command::Id { identity } => id(load()?, identity),
+        Command::Gc => gc(load()?).await,
+        Command::Streamid(StreamIdCommand::Inspect { id }) => inspect_stream_id(&id),
+        Command::Stream(StreamCommand::State {
+            id,
+            tips,
+            ethereum_rpc_url,
+            anchor_contract,
+            anchor_timestamps,
+        }) => {
+            stream_state(
+                load()?,
+                &id,
+                &tips,
+                ethereum_rpc_url,
+                anchor_contract,
+                &anchor_timestamps,
+            )
+            .await
+        }
+        Command::Config(ConfigCommand::Show) => {
+            print!("{}", load()?.to_toml()?);
+            Ok(())
+        }
+        Command::Key(command) => key(load()?, command),
+        Command::Store(StoreCommand::Migrate { from, to }) => {
+            store_migrate(load()?, from, to).await
+        }
+        Command::Migrate(MigrateCommand::FromKubo { ipfs_path }) => {
+            migrate_from_kubo(load()?, &ipfs_path).await
+        }
     }
 }
 
-async fn daemon(opts: DaemonOpts) -> Result<()> {
+async fn daemon(config: Config, opts: DaemonOpts) -> Result<()> {
+    let network = config.network()?;
+    let dir = config.store_path();
+    let identity = read_identity(&dir, opts.identity)?;
+    let bootstrap_addrs = parse_multiaddrs(&config.bootstrap_addrs()?)?;
     let mut metrics_config = MetricsConfig::default();
     metrics_config = metrics_config_with_compile_time_info(metrics_config);
-    metrics_config.collect = opts.metrics;
-    metrics_config.tracing = opts.tracing;
+    metrics_config.collect = config.metrics.collect;
+    metrics_config.tracing = config.metrics.tracing;
+    metrics_config.prom_gateway_endpoint = config.metrics.prometheus_endpoint;
+    metrics_config.collector_endpoint = config.metrics.tracing_endpoint;
     let service_name = metrics_config.service_name.clone();
     let instance_id = metrics_config.instance_id.clone();
     let metrics_handle = iroh_metrics::MetricsHandle::new(metrics_config)
         .await
         .expect("failed to initialize metrics");
-    info!(service_name, instance_id);
+    info!(service_name, instance_id, %network);
 
-    let dir = match home::home_dir() {
-        Some(home_dir) => home_dir.join(".ceramic-one"),
-        None => PathBuf::from(".ceramic-one"),
-    };
     debug!("Using directory: {}", dir.display());
 
-    let store = RocksStoreService::new(dir.join("store")).await?;
+    let backend = config.store.backend;
+    info!(?backend, "using block store");
+    // The p2p service of Iroh exchanges blocks with peers through its own RocksDB store and
+    // cannot be backed by another store, with other backends peers would never be provided the
+    // blocks of the node.
+    if network.has_p2p() && backend != Backend::Rocksdb {
+        bail!(
+            "the {} backend is not supported with networking, peers are only served blocks \
+            from the rocksdb backend, use `--store-backend rocksdb` or run without networking \
+            with `--network inmemory`",
+            backend
+                .to_possible_value()
+                .map(|v| v.get_name().to_string())
+                .unwrap_or_default()
+        );
+    }
+    let pins = match backend {
+        Backend::Memory => PinStore::memory(),
+        _ => PinStore::open(dir.join("pins"))?,
+    };
+
+    let iroh = if network.has_p2p() {
+        let store = RocksStoreService::new(dir.join("store")).await?;
+        let mut p2p_config = Libp2pConfig::default();
+        p2p_config.bootstrap_peers = bootstrap_addrs;
+        p2p_config.listening_multiaddrs = parse_multiaddrs(&config.p2p.listen_addrs)?;
+        // Without an identity the p2p service loads or creates a key in the store directory.
+        let keychain = match &identity {
+            Some(identity) => {
+                info!(peer_id = %identity.peer_id(), "using identity");
+                identity.write_keychain(&dir.join("keychain"))?
+            }
+            None => dir.clone(),
+        };
+        let p2p = P2pService::new(p2p_config, keychain, store.addr()).await?;
 
-    let mut p2p_config = Libp2pConfig::default();
-    p2p_config.bootstrap_peers = vec![
-        "/dns4/go-ipfs-ceramic-private-mainnet-external.3boxlabs.com/tcp/4011/ws/p2p/QmXALVsXZwPWTUbsT8G6VVzzgTJaAWRUD7FWL5f7d5ubAL".parse().unwrap(),
-        "/dns4/go-ipfs-ceramic-private-cas-mainnet-external.3boxlabs.com/tcp/4011/ws/p2p/QmUvEKXuorR7YksrVgA7yKGbfjWHuCRisw2cH9iqRVM9P8".parse().unwrap(),
-        "/dns4/go-ipfs-ceramic-elp-1-1-external.3boxlabs.com/tcp/4011/ws/p2p/QmUiF8Au7wjhAF9BYYMNQRW5KhY7o8fq4RUozzkWvHXQrZ".parse().unwrap(),
-        "/dns4/go-ipfs-ceramic-elp-1-2-external.3boxlabs.com/tcp/4011/ws/p2p/QmRNw9ZimjSwujzS3euqSYxDW9EHDU5LB3NbLQ5vJ13hwJ".parse().unwrap(),
-    ];
-    p2p_config.listening_multiaddrs = vec![
-        "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
-        "/ip4/0.0.0.0/udp/0/quic-v1".parse().unwrap(),
-    ];
-    let p2p = P2pService::new(p2p_config, dir, store.addr()).await?;
+        // Note by default this is configured with an indexer, but not with http resolvers.
+        Some(IrohBuilder::new().store(store).p2p(p2p).build().await?)
+    } else {
+        info!("networking is disabled");
+        iroh_store(&dir, backend).await?
+    };
+    let api = iroh.as_ref().map(|iroh| iroh.api().clone());
+    let store = store::open(backend, &dir, api.clone())?;
+    let ipfs = StoreIpfs::new(store, api.filter(|_| network.has_p2p()));
 
-    // Note by default this is configured with an indexer, but not with http resolvers.
-    let iroh = IrohBuilder::new().store(store).p2p(p2p).build().await?;
+    // Join the pub/sub topic of the network so this node relays the messages of the network.
+    if let Some(topic) = network.topic() {
+        let messages = ceramic_kubo_rpc::pubsub::subscribe(ipfs.clone(), topic.clone()).await?;
+        tokio::spawn(async move {
+            futures_util::pin_mut!(messages);
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(message) => debug!(topic, from = ?message.from, "received pub/sub message"),
+                    Err(err) => warn!(topic, %err, "failed to receive pub/sub message"),
+                }
+            }
+        });
+    }
 
-    // Run the HTTP server
-    ceramic_kubo_rpc::http::serve(iroh.api().clone(), opts.bind_address).await?;
+    // Run the HTTP server, along with the metrics server when it has an address
+    let metrics_addr = config.metrics.bind_address;
+    let metrics_server = async move {
+        match metrics_addr {
+            Some(addr) => ceramic_kubo_rpc::http::serve_metrics(addr).await,
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(
+        ceramic_kubo_rpc::http::serve(
+            ipfs,
+            pins,
+            Version::new(VERSION, BUILD),
+            config.http.bind_address,
+        ),
+        metrics_server,
+    )?;
 
     // Stop the system gracefully.
-    iroh.stop().await?;
+    if let Some(iroh) = iroh {
+        iroh.stop().await?;
+    }
 
     metrics_handle.shutdown();
     Ok(())
 }
 
+async fn gc(config: Config) -> Result<()> {
+    let dir = config.store_path();
+    debug!("Using directory: {}", dir.display());
+
+    let pins = PinStore::open(dir.join("pins"))?;
+    // Without an Iroh node the rocksdb backend is opened directly, which allows removing blocks.
+    let store = store::open(config.store.backend, &dir, None)?;
+
+    let removed = ceramic_kubo_rpc::repo::gc(StoreIpfs::new(store, None), &pins).await?;
+    futures_util::pin_mut!(removed);
+    let (mut count, mut bytes) = (0, 0);
+    while let Some(removed) = removed.next().await {
+        let removed = removed?;
+        println!("removed {}", removed.cid);
+        count += 1;
+        bytes += removed.size;
+    }
+    println!("removed {} blocks, reclaimed {} bytes", count, bytes);
+    Ok(())
+}
+
+async fn store_migrate(config: Config, from: Backend, to: Backend) -> Result<()> {
+    if from == to {
+        return Err(anyhow!("cannot migrate the {:?} backend to itself", from));
+    }
+    if from == Backend::Memory || to == Backend::Memory {
+        return Err(anyhow!("the memory backend does not persist blocks"));
+    }
+    let dir = config.store_path();
+    debug!("Using directory: {}", dir.display());
+
+    // At most one of the backends is RocksDB, which requires an Iroh store.
+    let iroh = iroh_store(&dir, if to == Backend::Rocksdb { to } else { from }).await?;
+    let api = iroh.as_ref().map(|iroh| iroh.api().clone());
+    let result = async {
+        let source = store::open(from, &dir, api.clone())?;
+        let target = store::open(to, &dir, api.clone())?;
+        store::migrate(source.as_ref(), target.as_ref()).await
+    }
+    .await;
+    if let Some(iroh) = iroh {
+        iroh.stop().await?;
+    }
+
+    let (count, bytes) = result?;
+    println!("migrated {} blocks, {} bytes", count, bytes);
+    Ok(())
+}
+
+async fn migrate_from_kubo(config: Config, ipfs_path: &Path) -> Result<()> {
+    let backend = config.store.backend;
+    if backend == Backend::Memory {
+        return Err(anyhow!("the memory backend does not persist blocks"));
+    }
+    let dir = config.store_path();
+    debug!("Using directory: {}", dir.display());
+    // Importing is usually the first use of a new store directory.
+    std::fs::create_dir_all(&dir)?;
+
+    let pins = PinStore::open(dir.join("pins"))?;
+    let (store, iroh) = open_store(&dir, backend).await?;
+    let stats = kubo::import(
+        StoreIpfs::new(store, None),
+        ipfs_path,
+        &pins,
+        |count, total| {
+            if count % 1000 == 0 || count == total {
+                eprintln!("imported {} of {} blocks", count, total);
+            }
+        },
+    )
+    .await;
+    if let Some(iroh) = iroh {
+        iroh.stop().await?;
+    }
+
+    let stats = stats?;
+    println!(
+        "imported {} blocks, {} bytes, {} pins",
+        stats.blocks, stats.bytes, stats.pins
+    );
+    if stats.guessed > 0 {
+        println!(
+            "{} blocks are not reachable from any pin, their codec was derived from their data",
+            stats.guessed
+        );
+    }
+    Ok(())
+}
+
+// Start an Iroh node with only a RocksDB store, if the backend requires it.
+async fn iroh_store(dir: &Path, backend: Backend) -> Result<Option<Iroh>> {
+    match backend {
+        Backend::Rocksdb => {
+            let store = RocksStoreService::new(dir.join("store")).await?;
+            Ok(Some(IrohBuilder::new().store(store).build().await?))
+        }
+        Backend::Sqlite | Backend::Memory => Ok(None),
+    }
+}
+
+// Open the block store of the backend,
+// the returned Iroh node must be stopped once the store is no longer used.
+async fn open_store(dir: &Path, backend: Backend) -> Result<(Arc<dyn BlockStore>, Option<Iroh>)> {
+    let iroh = iroh_store(dir, backend).await?;
+    let store = store::open(backend, dir, iroh.as_ref().map(|iroh| iroh.api().clone()))?;
+    Ok((store, iroh))
+}
+
+// Read the given identity or else the identity in the store directory, if it exists.
+fn read_identity(dir: &Path, path: Option<PathBuf>) -> Result<Option<Identity>> {
+    path.or_else(|| Some(dir.join(identity::IDENTITY_FILE)).filter(|path| path.exists()))
+        .map(|path| {
+            identity::check_permissions(&path)?;
+            Identity::read(&path)
+        })
+        .transpose()
+}
+
+fn id(config: Config, identity: Option<PathBuf>) -> Result<()> {
+    let dir = config.store_path();
+    let identity = match read_identity(&dir, identity)? {
+        Some(identity) => identity,
+        // Without an identity the p2p service uses the key of its keychain in the store directory.
+        None => Identity::read_keychain(&dir)?.ok_or_else(|| {
+            anyhow!(
+                "the node has no identity yet, create one with `ceramic-one key generate` \
+                 or start the daemon once"
+            )
+        })?,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({
+            "ID": identity.peer_id(),
+            "PublicKey": identity.public_key(),
+        }))?
+    );
+    Ok(())
+}
+
+fn key(config: Config, command: KeyCommand) -> Result<()> {
+    let default_path = config.store_path().join(identity::IDENTITY_FILE);
+    match command {
+        KeyCommand::Generate { output, format } => {
+            let identity = Identity::generate();
+            let path = output.unwrap_or(default_path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            identity.write(&path, format)?;
+            println!("{}", identity.peer_id());
+        }
+        KeyCommand::Import { file, format } => {
+            let bytes = std::fs::read(&file)
+                .map_err(|err| anyhow!("failed to read identity {}: {}", file.display(), err))?;
+            let identity = Identity::decode(&bytes, format)?;
+            std::fs::create_dir_all(config.store_path())?;
+            identity.write(&default_path, identity::Format::Pem)?;
+            println!("{}", identity.peer_id());
+        }
+        KeyCommand::Export { output, format } => {
+            let identity = Identity::read(&default_path)?;
+            match output {
+                Some(path) => identity.write(&path, format)?,
+                None => {
+                    std::io::Write::write_all(&mut std::io::stdout(), &identity.encode(format))?
+                }
+            }
+        }
+        KeyCommand::Show { path } => {
+            let identity = Identity::read(&path.unwrap_or(default_path))?;
+            println!("Peer ID:    {}", identity.peer_id());
+            println!("Public key: {}", identity.public_key());
+        }
+    }
+    Ok(())
+}
+
+fn inspect_stream_id(id: &str) -> Result<()> {
+    if let Ok(stream_id) = StreamId::from_str(id) {
+        println!("StreamID: {}", stream_id);
+        println!(
+            "Type:     {} ({})",
+            stream_id.stream_type(),
+            stream_id.stream_type().code()
+        );
+        println!("Genesis:  {}", stream_id.cid());
+        return Ok(());
+    }
+    let commit_id = CommitId::from_str(id)
+        .map_err(|err| anyhow!("{} is not a StreamID or CommitID: {}", id, err))?;
+    println!("CommitID: {}", commit_id);
+    println!("StreamID: {}", commit_id.stream_id());
+    println!(
+        "Type:     {} ({})",
+        commit_id.stream_type(),
+        commit_id.stream_type().code()
+    );
+    println!("Genesis:  {}", commit_id.genesis());
+    println!("Commit:   {}", commit_id.commit());
+    Ok(())
+}
+
+async fn stream_state(
+    config: Config,
+    id: &str,
+    tips: &[String],
+    ethereum_rpc_url: Option<String>,
+    anchor_contract: Option<String>,
+    anchor_timestamps: &[String],
+) -> Result<()> {
+    let mut tips = tips
+        .iter()
+        .map(|tip| Cid::from_str(tip).map_err(|err| anyhow!("invalid tip {}: {}", tip, err)))
+        .collect::<Result<Vec<_>>>()?;
+    // The local store has no index of the tips of streams, so a StreamID alone cannot be loaded.
+    let (stream_id, tip) = match StreamId::from_str(id) {
+        Ok(stream_id) => {
+            if tips.is_empty() {
+                bail!(
+                    "the tip of stream {} is not known, pass a CommitID or at least one --tip",
+                    stream_id
+                );
+            }
+            (stream_id, tips.remove(0))
+        }
+        Err(_) => {
+            let commit_id = CommitId::from_str(id)
+                .map_err(|err| anyhow!("{} is not a StreamID or CommitID: {}", id, err))?;
+            (commit_id.stream_id(), commit_id.commit())
+        }
+    };
+    let validator: Option<Box<dyn anchor::AnchorValidator>> = match ethereum_rpc_url {
+        Some(url) => Some(Box::new(
+            anchor::EthereumRpc::new(url, anchor_contract).await?,
+        )),
+        None if !anchor_timestamps.is_empty() => {
+            let mut validator = anchor::MemoryAnchorValidator::default();
+            for arg in anchor_timestamps {
+                let (tx_hash, timestamp) = arg.split_once('=').ok_or_else(|| {
+                    anyhow!(
+                        "invalid anchor timestamp {}, expected <TX_HASH>=<UNIX_TIMESTAMP>",
+                        arg
+                    )
+                })?;
+                let timestamp = timestamp
+                    .parse()
+                    .map_err(|err| anyhow!("invalid anchor timestamp {}: {}", arg, err))?;
+                validator.insert(anchor::parse_tx_hash(tx_hash)?, timestamp);
+            }
+            Some(Box::new(validator))
+        }
+        None => None,
+    };
+    let validator = validator.as_deref();
+
+    let dir = config.store_path();
+    debug!("Using directory: {}", dir.display());
+
+    let (store, iroh) = open_store(&dir, config.store.backend).await?;
+
+    let api = StoreIpfs::new(store, None);
+    let state = async {
+        let mut state = state::load(api.clone(), validator, &stream_id, tip).await?;
+        for tip in tips {
+            state = conflict::resolve(api.clone(), validator, state, tip).await?;
+        }
+        anyhow::Ok(state)
+    }
+    .await;
+    if let Some(iroh) = iroh {
+        iroh.stop().await?;
+    }
+
+    println!("{}", serde_json::to_string_pretty(&state?.to_json())?);
+    Ok(())
+}
+
+fn parse_multiaddrs(addrs: &[String]) -> Result<Vec<Multiaddr>> {
+    addrs
+        .iter()
+        .map(|addr| {
+            addr.parse()
+                .map_err(|err| anyhow!("invalid multiaddr {}: {}", addr, err))
+        })
+        .collect()
+}
+
 fn metrics_config_with_compile_time_info(cfg: MetricsConfig) -> MetricsConfig {
     // compile time configuration
     cfg.with_service_name(env!("CARGO_PKG_NAME").to_string())
-        .with_build(
-            git_version::git_version!(
-                prefix = "git:",
-                cargo_prefix = "cargo:",
-                fallback = "unknown"
-            )
-            .to_string(),
-        )
-        .with_version(env!("CARGO_PKG_VERSION").to_string())
+        .with_build(BUILD.to_string())
+        .with_version(VERSION.to_string())
 }
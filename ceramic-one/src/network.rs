@@ -0,0 +1,176 @@
+//! Describes the Ceramic networks a node can participate in.
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+
+/// A Ceramic network, each network has its own peers and pub/sub topic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Network {
+    /// Production network
+    Mainnet,
+    /// Public test network
+    TestnetClay,
+    /// Network used for development of Ceramic itself
+    DevUnstable,
+    /// Private network of local nodes sharing the given pub/sub topic
+    Local(String),
+    /// Single node without any networking
+    InMemory,
+}
+
+impl Network {
+    /// Construct a network from its name, the local network requires a topic and other networks do not accept one.
+    pub fn new(name: &str, topic: Option<&str>) -> Result<Self> {
+        let network = match name {
+            "mainnet" => Network::Mainnet,
+            "testnet-clay" => Network::TestnetClay,
+            "dev-unstable" => Network::DevUnstable,
+            "local" => {
+                return match topic {
+                    Some(topic) if !topic.is_empty() => Ok(Network::Local(topic.to_string())),
+                    _ => Err(anyhow!("the local network requires a network topic")),
+                }
+            }
+            "inmemory" => Network::InMemory,
+            _ => bail!(
+                "unknown network {}, expected one of mainnet, testnet-clay, dev-unstable, local or inmemory",
+                name
+            ),
+        };
+        if topic.is_some() {
+            bail!("a network topic can only be set for the local network");
+        }
+        Ok(network)
+    }
+
+    /// Name of the network.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::TestnetClay => "testnet-clay",
+            Network::DevUnstable => "dev-unstable",
+            Network::Local(_) => "local",
+            Network::InMemory => "inmemory",
+        }
+    }
+
+    /// Pub/sub topic on which nodes of the network announce updates to streams,
+    /// there is no topic when networking is disabled.
+    pub fn topic(&self) -> Option<String> {
+        match self {
+            Network::Local(topic) => Some(topic.clone()),
+            Network::InMemory => None,
+            network => Some(format!("/ceramic/{}", network.name())),
+        }
+    }
+
+    /// Report whether nodes of the network connect to peers.
+    pub fn has_p2p(&self) -> bool {
+        !matches!(self, Network::InMemory)
+    }
+
+    /// Multiaddrs of the peers a node of the network connects to on startup.
+    pub fn bootstrap_addrs(&self) -> Vec<String> {
+        let addrs: &[&str] = match self {
+            Network::Mainnet => &[
+                "/dns4/go-ipfs-ceramic-private-mainnet-external.3boxlabs.com/tcp/4011/ws/p2p/QmXALVsXZwPWTUbsT8G6VVzzgTJaAWRUD7FWL5f7d5ubAL",
+                "/dns4/go-ipfs-ceramic-private-cas-mainnet-external.3boxlabs.com/tcp/4011/ws/p2p/QmUvEKXuorR7YksrVgA7yKGbfjWHuCRisw2cH9iqRVM9P8",
+                "/dns4/go-ipfs-ceramic-elp-1-1-external.3boxlabs.com/tcp/4011/ws/p2p/QmUiF8Au7wjhAF9BYYMNQRW5KhY7o8fq4RUozzkWvHXQrZ",
+                "/dns4/go-ipfs-ceramic-elp-1-2-external.3boxlabs.com/tcp/4011/ws/p2p/QmRNw9ZimjSwujzS3euqSYxDW9EHDU5LB3NbLQ5vJ13hwJ",
+            ],
+            Network::TestnetClay => &[
+                "/dns4/go-ipfs-ceramic-public-clay-external.3boxlabs.com/tcp/4011/ws/p2p/QmWiY3CbNawZjWnHXx3p3DXsg21pZYTj4CRY1iwMkhP8r3",
+                "/dns4/go-ipfs-ceramic-private-clay-external.3boxlabs.com/tcp/4011/ws/p2p/QmQotCKxiMWt935TyCBFTN23jaivxwrZ3uD58wNxeg5npi",
+                "/dns4/go-ipfs-ceramic-private-cas-clay-external.3boxlabs.com/tcp/4011/ws/p2p/QmbeBTzSccH8xYottaYeyVX8QsKyox1ExfRx7T1iBqRyCd",
+            ],
+            Network::DevUnstable => &[
+                "/dns4/go-ipfs-ceramic-public-qa-external.3boxlabs.com/tcp/4011/ws/p2p/QmPP3RdaSWDkhcxZReGo591FWanLw9ucvgmUZhtSLt9t6D",
+                "/dns4/go-ipfs-ceramic-private-qa-external.3boxlabs.com/tcp/4011/ws/p2p/QmXcmXfLkkaGbQdj98cgGvHr5gkwJp4r79j9xbJajsoYHr",
+                "/dns4/go-ipfs-ceramic-private-cas-qa-external.3boxlabs.com/tcp/4011/ws/p2p/QmRvJ4HX4N6H26NgtqjoJEUyaDyDRUhGESP1aoyCJE1X1b",
+            ],
+            // Peers of local networks are configured explicitly.
+            Network::Local(_) | Network::InMemory => &[],
+        };
+        addrs.iter().map(|addr| addr.to_string()).collect()
+    }
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_api::Multiaddr;
+
+    use super::*;
+
+    #[test]
+    fn test_networks() {
+        for (name, topic, expected_topic) in [
+            ("mainnet", None, Some("/ceramic/mainnet")),
+            ("testnet-clay", None, Some("/ceramic/testnet-clay")),
+            ("dev-unstable", None, Some("/ceramic/dev-unstable")),
+            (
+                "local",
+                Some("/ceramic/local-1234"),
+                Some("/ceramic/local-1234"),
+            ),
+            ("inmemory", None, None),
+        ] {
+            let network = Network::new(name, topic).unwrap();
+            assert_eq!(name, network.to_string());
+            assert_eq!(
+                expected_topic.map(String::from),
+                network.topic(),
+                "{}",
+                name
+            );
+            assert_eq!(name != "inmemory", network.has_p2p(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_bootstrap_addrs() {
+        for network in [Network::Mainnet, Network::TestnetClay, Network::DevUnstable] {
+            let addrs = network.bootstrap_addrs();
+            assert!(!addrs.is_empty(), "{}", network);
+            for addr in addrs {
+                addr.parse::<Multiaddr>()
+                    .unwrap_or_else(|err| panic!("{} {}: {}", network, addr, err));
+            }
+        }
+        assert!(Network::Local("/ceramic/local-1234".to_string())
+            .bootstrap_addrs()
+            .is_empty());
+        assert!(Network::InMemory.bootstrap_addrs().is_empty());
+    }
+
+    #[test]
+    fn test_topics() {
+        assert_eq!(
+            "the local network requires a network topic",
+            Network::new("local", None).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "the local network requires a network topic",
+            Network::new("local", Some("")).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "a network topic can only be set for the local network",
+            Network::new("mainnet", Some("/ceramic/other"))
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_unknown_network() {
+        assert_eq!(
+            "unknown network testnet, expected one of mainnet, testnet-clay, dev-unstable, local or inmemory",
+            Network::new("testnet", None).unwrap_err().to_string()
+        );
+    }
+}
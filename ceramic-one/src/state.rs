@@ -0,0 +1,854 @@
+//! Aggregates the commit log of a stream into its current state.
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, bail, Result};
+use ceramic_core::{
+    event::{AnchorProof, Data, Event, Genesis, Payload},
+    jws, StreamId, StreamType,
+};
+use ceramic_kubo_rpc::{cacao, event, IpfsDep};
+use chrono::{DateTime, TimeZone, Utc};
+use libipld::{cid::Cid, Ipld};
+use multibase::Base;
+use serde_json::{json, Value};
+
+use crate::anchor::{self, AnchorValidator};
+
+/// Whether the tip of a stream has been anchored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnchorStatus {
+    /// The tip of the stream is a data commit that has not been anchored.
+    NotRequested,
+    /// The tip of the stream is an anchor commit.
+    Anchored,
+}
+
+impl AnchorStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            AnchorStatus::NotRequested => "NOT_REQUESTED",
+            AnchorStatus::Anchored => "ANCHORED",
+        }
+    }
+}
+
+/// Type of a commit in the log of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitType {
+    /// The first commit of the stream.
+    Genesis,
+    /// A signed data commit.
+    Signed,
+    /// A commit anchoring the previous commits in a blockchain.
+    Anchor,
+}
+
+impl CommitType {
+    fn name(&self) -> &'static str {
+        match self {
+            CommitType::Genesis => "GENESIS",
+            CommitType::Signed => "SIGNED",
+            CommitType::Anchor => "ANCHOR",
+        }
+    }
+}
+
+/// An entry in the log of a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Cid of the commit
+    pub cid: Cid,
+    /// Type of the commit
+    pub commit_type: CommitType,
+    /// Unix timestamp of the block containing the anchor transaction, known once the proof is validated
+    pub timestamp: Option<i64>,
+}
+
+/// Metadata of a stream, set by the genesis commit and updated by the headers of data commits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// DIDs allowed to write to the stream
+    pub controllers: Vec<String>,
+    /// Family of the stream
+    pub family: Option<String>,
+    /// Schema of the stream content
+    pub schema: Option<String>,
+    /// Tags of the stream
+    pub tags: Vec<String>,
+    /// Model of a model instance document
+    pub model: Option<StreamId>,
+}
+
+impl Metadata {
+    // Apply the fields of a data commit header, other fields do not change the metadata.
+    fn update(&mut self, header: &BTreeMap<String, Ipld>) -> Result<()> {
+        for (key, value) in header {
+            match key.as_str() {
+                "controllers" => self.controllers = strings(key, value)?,
+                "tags" => self.tags = strings(key, value)?,
+                "family" => self.family = Some(string(key, value)?),
+                "schema" => self.schema = Some(string(key, value)?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state of a stream as of the tip of its log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamState {
+    /// Id of the stream
+    pub stream_id: StreamId,
+    /// Content of the stream after applying all data commits
+    pub content: Value,
+    /// Metadata of the stream
+    pub metadata: Metadata,
+    /// Commits of the stream from the genesis commit to the tip
+    pub log: Vec<LogEntry>,
+    /// Whether the tip of the stream is anchored
+    pub anchor_status: AnchorStatus,
+    /// Proof of the most recent anchor commit
+    pub anchor_proof: Option<AnchorProof>,
+}
+
+impl StreamState {
+    /// Cid of the last commit in the log.
+    pub fn tip(&self) -> Cid {
+        self.log
+            .last()
+            .expect("log always contains the genesis commit")
+            .cid
+    }
+
+    /// Timestamp of the most recent anchor commit, if it is known.
+    pub fn anchor_timestamp(&self) -> Option<i64> {
+        self.log
+            .iter()
+            .rev()
+            .find(|entry| entry.commit_type == CommitType::Anchor)
+            .and_then(|entry| entry.timestamp)
+    }
+
+    /// Represent the state as JSON, using the field names of js-ceramic.
+    pub fn to_json(&self) -> Value {
+        let mut metadata = json!({
+            "controllers": self.metadata.controllers,
+        });
+        if let Some(family) = &self.metadata.family {
+            metadata["family"] = json!(family);
+        }
+        if let Some(schema) = &self.metadata.schema {
+            metadata["schema"] = json!(schema);
+        }
+        if !self.metadata.tags.is_empty() {
+            metadata["tags"] = json!(self.metadata.tags);
+        }
+        if let Some(model) = &self.metadata.model {
+            metadata["model"] = json!(model.to_string());
+        }
+        let log = self
+            .log
+            .iter()
+            .map(|entry| {
+                let mut value = json!({
+                    "cid": entry.cid.to_string(),
+                    "type": entry.commit_type.name(),
+                });
+                if let Some(timestamp) = entry.timestamp {
+                    value["timestamp"] = json!(timestamp);
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        let mut state = json!({
+            "streamId": self.stream_id.to_string(),
+            "type": self.stream_id.stream_type().code(),
+            "content": self.content,
+            "metadata": metadata,
+            "log": log,
+            "anchorStatus": self.anchor_status.name(),
+        });
+        if let Some(proof) = &self.anchor_proof {
+            state["anchorProof"] = json!({
+                "chainId": proof.chain_id,
+                "root": proof.root.to_string(),
+                "txHash": proof.tx_hash.to_string(),
+            });
+        }
+        state
+    }
+}
+
+/// Load the state of a stream by walking its log from the tip back to the genesis commit
+/// and replaying the commits in order.
+///
+/// When a validator is provided each anchor commit is verified and its timestamp recorded in the log,
+/// otherwise the timestamps of anchors are not known.
+/// The signatures of signed commits and the CACAOs delegating to their signers are verified.
+#[tracing::instrument(skip(client, validator))]
+pub async fn load<T>(
+    client: T,
+    validator: Option<&dyn AnchorValidator>,
+    stream_id: &StreamId,
+    tip: Cid,
+) -> Result<StreamState>
+where
+    T: IpfsDep,
+{
+    let log = walk(client.clone(), stream_id, tip).await?;
+    let mut timestamps = HashMap::new();
+    if let Some(validator) = validator {
+        for (cid, event) in &log {
+            if let Event::Anchor(anchor, proof) = event {
+                let timestamp = anchor::verify(client.clone(), validator, anchor, proof)
+                    .await
+                    .map_err(|err| anyhow!("anchor commit {} is not valid: {}", cid, err))?;
+                timestamps.insert(*cid, timestamp);
+            }
+        }
+    }
+    let authors = verify_signed(client, stream_id, &log, &timestamps).await?;
+    let mut state = apply(stream_id, log, &authors)?;
+    for entry in &mut state.log {
+        entry.timestamp = timestamps.get(&entry.cid).copied();
+    }
+    Ok(state)
+}
+
+// Verify the signatures of the signed commits of a log and the capabilities delegating to their
+// signers, returning the DIDs each commit was made on behalf of.
+// A capability must be valid when the commit was anchored, or now when it has not been anchored.
+// The expiry of capabilities is not checked for commits anchored at an unknown time.
+async fn verify_signed<T>(
+    client: T,
+    stream_id: &StreamId,
+    log: &[(Cid, Event)],
+    timestamps: &HashMap<Cid, i64>,
+) -> Result<HashMap<Cid, Vec<String>>>
+where
+    T: IpfsDep,
+{
+    let model = log.first().and_then(|(_, event)| match event {
+        Event::Genesis(genesis) => genesis.header.model.as_ref(),
+        Event::Signed(signed) => match &signed.payload {
+            Payload::Genesis(genesis) => genesis.header.model.as_ref(),
+            Payload::Data(_) => None,
+        },
+        Event::Anchor(..) => None,
+    });
+    let mut authors = HashMap::new();
+    let mut at: Option<DateTime<Utc>> = Some(Utc::now());
+    for (cid, event) in log.iter().rev() {
+        match event {
+            Event::Anchor(..) => {
+                at = match timestamps.get(cid) {
+                    Some(timestamp) => Some(
+                        Utc.timestamp_opt(*timestamp, 0)
+                            .single()
+                            .ok_or_else(|| anyhow!("invalid anchor timestamp {}", timestamp))?,
+                    ),
+                    None => None,
+                }
+            }
+            Event::Signed(signed) => {
+                for verification in jws::verify(&signed.jws)
+                    .map_err(|err| anyhow!("commit {} is not valid: {}", cid, err))?
+                {
+                    if !verification.is_valid() {
+                        bail!(
+                            "commit {} has an invalid signature by {}",
+                            cid,
+                            verification.did()
+                        );
+                    }
+                }
+                let signers = cacao::verify(client.clone(), &signed.jws, stream_id, model, at)
+                    .await
+                    .map_err(|err| anyhow!("commit {} is not valid: {}", cid, err))?;
+                authors.insert(*cid, signers);
+            }
+            Event::Genesis(_) => {}
+        }
+    }
+    Ok(authors)
+}
+
+/// Fetch the commits of a stream following `prev` links from the tip to the genesis commit.
+/// Returns the commits in log order, i.e. starting with the genesis commit.
+pub async fn walk<T>(client: T, stream_id: &StreamId, tip: Cid) -> Result<Vec<(Cid, Event)>>
+where
+    T: IpfsDep,
+{
+    let mut log = Vec::new();
+    let mut cid = tip;
+    loop {
+        let event = event::get(client.clone(), cid).await?;
+        match (event.id(), event.prev()) {
+            (Some(id), Some(prev)) => {
+                if id != stream_id.cid() {
+                    bail!("commit {} belongs to stream with genesis {}", cid, id);
+                }
+                log.push((cid, event));
+                cid = prev;
+            }
+            _ => {
+                if cid != stream_id.cid() {
+                    bail!("genesis commit {} does not match stream {}", cid, stream_id);
+                }
+                log.push((cid, event));
+                break;
+            }
+        }
+    }
+    log.reverse();
+    Ok(log)
+}
+
+/// Apply the commits of a log, starting with the genesis commit, to produce the state of a stream.
+///
+/// Each signed commit must be made on behalf of controllers of the stream as of its previous commit,
+/// `authors` maps the Cid of each signed commit to the DIDs it was made on behalf of.
+pub fn apply(
+    stream_id: &StreamId,
+    log: Vec<(Cid, Event)>,
+    authors: &HashMap<Cid, Vec<String>>,
+) -> Result<StreamState> {
+    let mut commits = log.into_iter();
+    let (cid, genesis) = commits
+        .next()
+        .ok_or_else(|| anyhow!("log of stream {} is empty", stream_id))?;
+    let genesis = match genesis {
+        Event::Genesis(genesis) => genesis,
+        Event::Signed(signed) => match signed.payload {
+            Payload::Genesis(genesis) => {
+                check_authors(&genesis.header.controllers, cid, authors)?;
+                genesis
+            }
+            Payload::Data(_) => bail!("first commit {} is not a genesis commit", cid),
+        },
+        Event::Anchor(..) => bail!("first commit {} is not a genesis commit", cid),
+    };
+    let mut state = genesis_state(stream_id, cid, genesis)?;
+    for (cid, event) in commits {
+        match event {
+            Event::Signed(signed) => match signed.payload {
+                Payload::Data(data) => {
+                    check_authors(&state.metadata.controllers, cid, authors)?;
+                    apply_data(&mut state, cid, data)?
+                }
+                Payload::Genesis(_) => bail!("unexpected genesis commit {}", cid),
+            },
+            Event::Anchor(_, proof) => {
+                state.log.push(LogEntry {
+                    cid,
+                    commit_type: CommitType::Anchor,
+                    timestamp: None,
+                });
+                state.anchor_status = AnchorStatus::Anchored;
+                state.anchor_proof = Some(proof);
+            }
+            Event::Genesis(_) => bail!("unexpected genesis commit {}", cid),
+        }
+    }
+    Ok(state)
+}
+
+// Check that a signed commit is made on behalf of controllers only.
+fn check_authors(
+    controllers: &[String],
+    cid: Cid,
+    authors: &HashMap<Cid, Vec<String>>,
+) -> Result<()> {
+    let authors = match authors.get(&cid) {
+        Some(authors) if !authors.is_empty() => authors,
+        _ => bail!("commit {} is not signed", cid),
+    };
+    for author in authors {
+        if !controllers.contains(author) {
+            bail!(
+                "commit {} is signed by {} which is not a controller",
+                cid,
+                author
+            );
+        }
+    }
+    Ok(())
+}
+
+fn genesis_state(stream_id: &StreamId, cid: Cid, genesis: Genesis) -> Result<StreamState> {
+    let header = genesis.header;
+    match (stream_id.stream_type(), &header.model) {
+        (StreamType::Tile, None) | (StreamType::ModelInstanceDocument, Some(_)) => {}
+        (StreamType::Tile, Some(_)) => bail!("tile document genesis must not have a model"),
+        (StreamType::ModelInstanceDocument, None) => {
+            bail!("model instance document genesis must have a model")
+        }
+        (stream_type, _) => bail!("unsupported stream type {}", stream_type),
+    }
+    let content = match &genesis.data {
+        Some(data) => to_json(data)?,
+        None => json!({}),
+    };
+    Ok(StreamState {
+        stream_id: *stream_id,
+        content,
+        metadata: Metadata {
+            controllers: header.controllers,
+            family: header.family,
+            schema: header.schema,
+            tags: header.tags,
+            model: header.model,
+        },
+        log: vec![LogEntry {
+            cid,
+            commit_type: CommitType::Genesis,
+            timestamp: None,
+        }],
+        anchor_status: AnchorStatus::NotRequested,
+        anchor_proof: None,
+    })
+}
+
+fn apply_data(state: &mut StreamState, cid: Cid, data: Data) -> Result<()> {
+    if data.prev != state.tip() {
+        bail!(
+            "commit {} has prev {} but the tip is {}",
+            cid,
+            data.prev,
+            state.tip()
+        );
+    }
+    let patch: json_patch::Patch = serde_json::from_value(to_json(&data.data)?)
+        .map_err(|err| anyhow!("commit {} has an invalid patch: {}", cid, err))?;
+    json_patch::patch(&mut state.content, &patch)
+        .map_err(|err| anyhow!("failed to apply patch of commit {}: {}", cid, err))?;
+    state.metadata.update(&data.header)?;
+    state.log.push(LogEntry {
+        cid,
+        commit_type: CommitType::Signed,
+        timestamp: None,
+    });
+    state.anchor_status = AnchorStatus::NotRequested;
+    Ok(())
+}
+
+// Convert IPLD to JSON, representing bytes and links as in dag-json.
+fn to_json(ipld: &Ipld) -> Result<Value> {
+    Ok(match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(b) => Value::Bool(*b),
+        Ipld::Integer(i) => {
+            if let Ok(i) = i64::try_from(*i) {
+                Value::from(i)
+            } else if let Ok(u) = u64::try_from(*i) {
+                Value::from(u)
+            } else {
+                bail!("integer {} is out of range", i)
+            }
+        }
+        Ipld::Float(f) => serde_json::Number::from_f64(*f)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("float {} cannot be represented as JSON", f))?,
+        Ipld::String(s) => Value::String(s.clone()),
+        Ipld::Bytes(bytes) => json!({"/": {"bytes": Base::Base64.encode(bytes)}}),
+        Ipld::List(list) => Value::Array(list.iter().map(to_json).collect::<Result<_>>()?),
+        Ipld::Map(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), to_json(v)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Ipld::Link(cid) => json!({"/": cid.to_string()}),
+    })
+}
+
+fn string(key: &str, value: &Ipld) -> Result<String> {
+    match value {
+        Ipld::String(s) => Ok(s.clone()),
+        _ => Err(anyhow!("header field {} must be a string", key)),
+    }
+}
+
+fn strings(key: &str, value: &Ipld) -> Result<Vec<String>> {
+    match value {
+        Ipld::List(list) => list.iter().map(|value| string(key, value)).collect(),
+        _ => Err(anyhow!("header field {} must be a list of strings", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ceramic_kubo_rpc::{
+        error::Error,
+        testing::{
+            mock_blocks, DID, MID_ANCHOR, MID_DATA, MID_DATA_PAYLOAD, MID_GENESIS,
+            MID_GENESIS_PAYLOAD, MID_PROOF,
+        },
+    };
+
+    use super::*;
+    use crate::anchor::MemoryAnchorValidator;
+
+    // Blocks of a tile document stream with a signed genesis commit, two signed data commits
+    // and an anchor commit, the model instance document stream comes from the shared fixtures.
+    // Each fixture is the Cid of the block and the hex encoded block data.
+    // The commits follow the structure of commits found on mainnet,
+    // and are signed by a did:key generated for these tests.
+    const TILE_GENESIS_PAYLOAD: (&str, &str) = (
+        "bafyreic7fpk3zsroeishw2qeto4wcn74pzbridyzi63rtouijbvkcq7xpq",
+        "a26464617461a2656974656d73820102657469746c656568656c6c6f66686561646572a364746167738161616666616d696c79676578616d706c656b636f6e74726f6c6c6572738178386469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b",
+    );
+    const TILE_GENESIS: (&str, &str) = (
+        "bagcqcera6uz4pozq3lc35s2rtje2eigeidqkqmdmz7n5bth4ehmz4lahnsea",
+        "a2677061796c6f61645824017112205f2bd5bcca2e22247b6a049bb96137fc7e43140f1947b719ba88486aa143f77c6a7369676e61747572657381a26970726f74656374656458817b22616c67223a224564445341222c226b6964223a226469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b237a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b227d697369676e617475726558408a61efceab960def6f983f952e36a3eaec0f29da7ae8728dc6316e64bec04fe1ef78ba35cd9979c32dc303251da54e0b7f1330a4f3ae146aa31bc52cd27d1600",
+    );
+    const TILE_DATA_1_PAYLOAD: (&str, &str) = (
+        "bafyreigik7nc74mrfmsiobblhcvkfqcokky5rl4x6ijlhvzspzemir3p2a",
+        "a4626964d82a5826000185011220f533c7bb30dac5becb519a49a220c440e0a8306ccfdbd0ccfc21d99e2c076c88646461746181a3626f70636164646470617468682f6974656d732f2d6576616c7565036470726576d82a5826000185011220f533c7bb30dac5becb519a49a220c440e0a8306ccfdbd0ccfc21d99e2c076c8866686561646572a0",
+    );
+    const TILE_DATA_1: (&str, &str) = (
+        "bagcqceraiekwapi5camunjyaqc5uqvo6akv6l4jvmqz7os5tpvwoxot4lxkq",
+        "a2677061796c6f6164582401711220c857da2ff1912b2487042b38aaa2c04e52b1d8af97f212b3d7327e48c4476fd06a7369676e61747572657381a26970726f74656374656458817b22616c67223a224564445341222c226b6964223a226469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b237a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b227d697369676e61747572655840fdba2ce7b5b3454708dbf4b11ab82f804488989a545d04913699287f1ee306c9b98b05cb80ab902ef919f588de8875acbbbbc2f6650a92bcc8d43c632573340b",
+    );
+    const TILE_DATA_2_PAYLOAD: (&str, &str) = (
+        "bafyreigt4ehng5ksfg6gxhqldzuwrhaesacyt7h3hdxep2b2nujytgonjq",
+        "a4626964d82a5826000185011220f533c7bb30dac5becb519a49a220c440e0a8306ccfdbd0ccfc21d99e2c076c88646461746181a3626f70677265706c6163656470617468662f7469746c656576616c756565776f726c646470726576d82a58260001850112204115603d1d101946a70080bb4855de02abe5f1356433f74bb37d6cebba7c5dd566686561646572a164746167738261616162",
+    );
+    const TILE_DATA_2: (&str, &str) = (
+        "bagcqcerawm2y764fj5a3j3i2r7344jio4l23lvwh72p7h6z3kdhstha3gata",
+        "a2677061796c6f6164582401711220d3e10ed3755229bc6b9e0b1e69689c04900589fcfb38ee47e83a6d138999cd4c6a7369676e61747572657381a26970726f74656374656458817b22616c67223a224564445341222c226b6964223a226469643a6b65793a7a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b237a364d6b6e394c4c44476235435968416b745435526f63334b523739673441544878414a68774c416e464746374e534b227d697369676e61747572655840f988700733dbf58defe865bb709ea736eb29cf924df18f6f80ab0e981143fe3260b4da5bb4b8babfc230baea87fbbd07e143aac023923d87c9dfd402ca9d1503",
+    );
+    const TILE_ROOT: (&str, &str) = (
+        "bafyreidmb4krg7262v6f2bbrzkrl5juxd6x4g3w2kublh5fcyrmseuaxjq",
+        "82d82a5826000185011220b3358ffb854f41b4ed1a8ff7ce250ee2f5b5d6c7fe9ff3fb3b50cf299c1b3026f6",
+    );
+    const TILE_PROOF: (&str, &str) = (
+        "bafyreiaskvrrbz2bnp7h3h22wpmrvj2flncmpn6yekr26he7ot6t4ndqvu",
+        "a464726f6f74d82a582500017112206c0f15137f5ed57c5d0431caa2bea6971fafc36eda5502b3f4a2c4592250174c66747848617368d82a5826000193011b2060b9f31fc80c312ee83d06a48e36513c2ee93d22ad1ce02abbf3994827ae81c5667478547970656a6628627974657333322967636861696e4964686569703135353a31",
+    );
+    const TILE_ANCHOR: (&str, &str) = (
+        "bafyreid4fyn6hwnmmgqvd62xnhf7l3d6msp6pfeavddt3x6x65kqgsfo5y",
+        "a4626964d82a5826000185011220f533c7bb30dac5becb519a49a220c440e0a8306ccfdbd0ccfc21d99e2c076c88647061746861306470726576d82a5826000185011220b3358ffb854f41b4ed1a8ff7ce250ee2f5b5d6c7fe9ff3fb3b50cf299c1b30266570726f6f66d82a5825000171122012556310e7416bfe7d9f5ab3d91aa7455b44c7b7d822a3af1c9f74fd3e3470ad",
+    );
+    const MID_STREAM: &str = "kjzl6kcym7w8y6nhw5phbvvkxbyvknbl42hpgowzdnx0oz6bpr3pge66w3db2xz";
+    const TILE_STREAM: &str = "kjzl6cwe1jw14bdw557i0un1z0nj7w0jfg3qdyc1f09gt37v3n2jywx2x6mtr48";
+    const MODEL: &str = "kjzl6hvfrbw6c74hccl7t8237c7txblexlxhns30kyrp687rk7n4nw0vl0wx130";
+
+    const MID_BLOCKS: &[(&str, &str)] = &[
+        MID_GENESIS,
+        MID_GENESIS_PAYLOAD,
+        MID_DATA,
+        MID_DATA_PAYLOAD,
+        MID_ANCHOR,
+        MID_PROOF,
+    ];
+    const TILE_BLOCKS: &[(&str, &str)] = &[
+        TILE_GENESIS,
+        TILE_GENESIS_PAYLOAD,
+        TILE_DATA_1,
+        TILE_DATA_1_PAYLOAD,
+        TILE_DATA_2,
+        TILE_DATA_2_PAYLOAD,
+        TILE_ANCHOR,
+        TILE_PROOF,
+        TILE_ROOT,
+    ];
+
+    fn cid(fixture: (&str, &str)) -> Cid {
+        Cid::from_str(fixture.0).unwrap()
+    }
+
+    fn entry(fixture: (&str, &str), commit_type: CommitType) -> LogEntry {
+        LogEntry {
+            cid: cid(fixture),
+            commit_type,
+            timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_model_instance_document() {
+        let stream_id = StreamId::from_str(MID_STREAM).unwrap();
+        let state = load(mock_blocks(MID_BLOCKS), None, &stream_id, cid(MID_ANCHOR))
+            .await
+            .unwrap();
+        assert_eq!(
+            StreamState {
+                stream_id,
+                content: json!({"name": "alice", "age": 31}),
+                metadata: Metadata {
+                    controllers: vec![DID.to_string()],
+                    model: Some(StreamId::from_str(MODEL).unwrap()),
+                    ..Default::default()
+                },
+                log: vec![
+                    entry(MID_GENESIS, CommitType::Genesis),
+                    entry(MID_DATA, CommitType::Signed),
+                    entry(MID_ANCHOR, CommitType::Anchor),
+                ],
+                anchor_status: AnchorStatus::Anchored,
+                anchor_proof: Some(AnchorProof {
+                    chain_id: "eip155:1".to_string(),
+                    root: Cid::from_str(
+                        "bafyreiczohg6vdw2qimm5cvqzwllu3zutqxphacpnyq5rrhnio2te3rdne"
+                    )
+                    .unwrap(),
+                    tx_hash: Cid::from_str(
+                        "bagjqcgzadnnzzsz6ruaguurq32n5ui77shw4pfgu6vsbavqigc2bquuoirwa"
+                    )
+                    .unwrap(),
+                    tx_type: Some("f(bytes32)".to_string()),
+                }),
+            },
+            state
+        );
+    }
+
+    #[tokio::test]
+    async fn test_model_instance_document_at_commit() {
+        let stream_id = StreamId::from_str(MID_STREAM).unwrap();
+        let state = load(mock_blocks(MID_BLOCKS), None, &stream_id, cid(MID_DATA))
+            .await
+            .unwrap();
+        assert_eq!(json!({"name": "alice", "age": 31}), state.content);
+        assert_eq!(2, state.log.len());
+        assert_eq!(AnchorStatus::NotRequested, state.anchor_status);
+        assert_eq!(None, state.anchor_proof);
+    }
+
+    #[tokio::test]
+    async fn test_model_instance_document_genesis() {
+        let stream_id = StreamId::from_str(MID_STREAM).unwrap();
+        let state = load(mock_blocks(MID_BLOCKS), None, &stream_id, cid(MID_GENESIS))
+            .await
+            .unwrap();
+        assert_eq!(json!({"name": "alice", "age": 30}), state.content);
+        assert_eq!(vec![entry(MID_GENESIS, CommitType::Genesis)], state.log);
+        assert_eq!(AnchorStatus::NotRequested, state.anchor_status);
+    }
+
+    #[tokio::test]
+    async fn test_tile_document() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        let state = load(mock_blocks(TILE_BLOCKS), None, &stream_id, cid(TILE_ANCHOR))
+            .await
+            .unwrap();
+        assert_eq!(
+            StreamState {
+                stream_id,
+                content: json!({"title": "world", "items": [1, 2, 3]}),
+                metadata: Metadata {
+                    controllers: vec![DID.to_string()],
+                    family: Some("example".to_string()),
+                    tags: vec!["a".to_string(), "b".to_string()],
+                    ..Default::default()
+                },
+                log: vec![
+                    entry(TILE_GENESIS, CommitType::Genesis),
+                    entry(TILE_DATA_1, CommitType::Signed),
+                    entry(TILE_DATA_2, CommitType::Signed),
+                    entry(TILE_ANCHOR, CommitType::Anchor),
+                ],
+                anchor_status: AnchorStatus::Anchored,
+                anchor_proof: Some(AnchorProof {
+                    chain_id: "eip155:1".to_string(),
+                    root: Cid::from_str(
+                        "bafyreidmb4krg7262v6f2bbrzkrl5juxd6x4g3w2kublh5fcyrmseuaxjq"
+                    )
+                    .unwrap(),
+                    tx_hash: Cid::from_str(
+                        "bagjqcgzamc47gh6ibqys52b5a2si4nsrhqxospjcvuooakv36omuqj5oqhcq"
+                    )
+                    .unwrap(),
+                    tx_type: Some("f(bytes32)".to_string()),
+                }),
+            },
+            state
+        );
+    }
+
+    fn tile_proof() -> AnchorProof {
+        AnchorProof {
+            chain_id: "eip155:1".to_string(),
+            root: cid(TILE_ROOT),
+            tx_hash: Cid::from_str("bagjqcgzamc47gh6ibqys52b5a2si4nsrhqxospjcvuooakv36omuqj5oqhcq")
+                .unwrap(),
+            tx_type: Some("f(bytes32)".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tile_document_verified() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        let mut validator = MemoryAnchorValidator::default();
+        validator.insert(tile_proof().tx_hash, 1_680_000_000);
+        let state = load(
+            mock_blocks(TILE_BLOCKS),
+            Some(&validator),
+            &stream_id,
+            cid(TILE_ANCHOR),
+        )
+        .await
+        .unwrap();
+        assert_eq!(Some(1_680_000_000), state.anchor_timestamp());
+        assert_eq!(
+            vec![None, None, None, Some(1_680_000_000)],
+            state
+                .log
+                .iter()
+                .map(|entry| entry.timestamp)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            json!({"cid": TILE_ANCHOR.0, "type": "ANCHOR", "timestamp": 1_680_000_000}),
+            state.to_json()["log"][3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tile_document_unknown_anchor() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        let validator = MemoryAnchorValidator::default();
+        let err = load(
+            mock_blocks(TILE_BLOCKS),
+            Some(&validator),
+            &stream_id,
+            cid(TILE_ANCHOR),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            format!(
+                "anchor commit {} is not valid: transaction {} not found",
+                TILE_ANCHOR.0,
+                tile_proof().tx_hash
+            ),
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tile_document_to_json() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        let state = load(mock_blocks(TILE_BLOCKS), None, &stream_id, cid(TILE_DATA_1))
+            .await
+            .unwrap();
+        assert_eq!(
+            json!({
+                "streamId": TILE_STREAM,
+                "type": 0,
+                "content": {"title": "hello", "items": [1, 2, 3]},
+                "metadata": {
+                    "controllers": [DID],
+                    "family": "example",
+                    "tags": ["a"],
+                },
+                "log": [
+                    {"cid": TILE_GENESIS.0, "type": "GENESIS"},
+                    {"cid": TILE_DATA_1.0, "type": "SIGNED"},
+                ],
+                "anchorStatus": "NOT_REQUESTED",
+            }),
+            state.to_json()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_from_another_stream() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        let err = load(mock_blocks(MID_BLOCKS), None, &stream_id, cid(MID_DATA))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!(
+                "commit {} belongs to stream with genesis {}",
+                MID_DATA.0, MID_GENESIS.0
+            ),
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_stream_type() {
+        // The MID genesis commit under a tile StreamID
+        let stream_id = StreamId::new(StreamType::Tile, cid(MID_GENESIS));
+        let err = load(mock_blocks(MID_BLOCKS), None, &stream_id, cid(MID_GENESIS))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "tile document genesis must not have a model",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_commit() {
+        let stream_id = StreamId::from_str(TILE_STREAM).unwrap();
+        // Omit the first data commit from the served blocks
+        let err = load(
+            mock_blocks(&[
+                TILE_GENESIS,
+                TILE_GENESIS_PAYLOAD,
+                TILE_DATA_2,
+                TILE_DATA_2_PAYLOAD,
+            ]),
+            None,
+            &stream_id,
+            cid(TILE_DATA_2),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::NotFound)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_signature() {
+        let stream_id = StreamId::from_str(MID_STREAM).unwrap();
+        // Change the last byte of the signature of the data commit
+        let data = format!("{}00", &MID_DATA.1[..MID_DATA.1.len() - 2]);
+        let err = load(
+            mock_blocks(&[
+                MID_GENESIS,
+                MID_GENESIS_PAYLOAD,
+                (MID_DATA.0, data.as_str()),
+                MID_DATA_PAYLOAD,
+            ]),
+            None,
+            &stream_id,
+            cid(MID_DATA),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            format!("commit {} has an invalid signature by {}", MID_DATA.0, DID),
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_a_controller() {
+        let stream_id = StreamId::from_str(MID_STREAM).unwrap();
+        let log = walk(mock_blocks(MID_BLOCKS), &stream_id, cid(MID_DATA))
+            .await
+            .unwrap();
+        let other = "did:key:z6MkrBdNdwUPnXDVD1DCxedzVVBpaGi8aSmoXFAeKNgtAer8";
+        let authors = HashMap::from([
+            (cid(MID_GENESIS), vec![DID.to_string()]),
+            (cid(MID_DATA), vec![other.to_string()]),
+        ]);
+        let err = apply(&stream_id, log, &authors).unwrap_err();
+        assert_eq!(
+            format!(
+                "commit {} is signed by {} which is not a controller",
+                MID_DATA.0, other
+            ),
+            err.to_string()
+        );
+    }
+}
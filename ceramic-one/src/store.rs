@@ -0,0 +1,697 @@
+//! Stores the blocks of a node in one of several backends.
+//!
+//! The RocksDB backend is the store of the Iroh node, SQLite stores all blocks in a single file
+//! and the memory backend keeps blocks only for the lifetime of the process.
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ceramic_kubo_rpc::{block, dag, error::Error, IpfsDep, PeerConnection, PeerInfo};
+use clap::ValueEnum;
+use futures_util::stream::BoxStream;
+use iroh_api::{Api, Bytes, IpfsPath, Multiaddr, PeerId};
+use libipld::{cid::Cid, multihash::Multihash, Ipld};
+use libp2p::gossipsub::GossipsubEvent;
+use serde::{Deserialize, Serialize};
+
+/// Storage backend of the blocks of a node.
+///
+/// Peers are only served blocks from the RocksDB store of Iroh, as the p2p service of Iroh cannot
+/// be backed by another store. The daemon therefore only runs the sqlite and memory backends
+/// without networking, i.e. with `--network inmemory`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// RocksDB store of the Iroh node
+    #[default]
+    Rocksdb,
+    /// Single file SQLite database, the daemon only uses it without networking
+    Sqlite,
+    /// In-memory store, blocks are lost when the process exits,
+    /// the daemon only uses it without networking
+    Memory,
+}
+
+/// Storage of blocks by their Cid.
+#[async_trait]
+pub trait BlockStore: Send + Sync {
+    /// Get a block, returning None when the store does not have it.
+    async fn get(&self, cid: Cid) -> Result<Option<Bytes>>;
+    /// Store a block along with the Cids it links to.
+    async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<()>;
+    /// Remove a block, returning its size or None when the store does not have it.
+    async fn remove(&self, cid: Cid) -> Result<Option<u64>>;
+    /// List the Cids of all blocks.
+    async fn cids(&self) -> Result<Vec<Cid>>;
+}
+
+/// Open the block store of the backend in the store directory.
+///
+/// The RocksDB backend uses the API of the Iroh node when it runs with the store,
+/// otherwise the database is opened directly.
+pub fn open(backend: Backend, dir: &Path, api: Option<Api>) -> Result<Arc<dyn BlockStore>> {
+    Ok(match backend {
+        Backend::Rocksdb => Arc::new(match api {
+            Some(api) => RocksBlockStore::Iroh {
+                api,
+                path: dir.join("store"),
+            },
+            None => RocksBlockStore::open_direct(&dir.join("store"))?,
+        }),
+        Backend::Sqlite => Arc::new(SqliteBlockStore::open(&dir.join("store.sqlite"))?),
+        Backend::Memory => Arc::new(MemoryBlockStore::default()),
+    })
+}
+
+/// Blocks stored in memory.
+#[derive(Default)]
+pub struct MemoryBlockStore {
+    blocks: Mutex<BTreeMap<Cid, Bytes>>,
+}
+
+impl MemoryBlockStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<Cid, Bytes>> {
+        self.blocks
+            .lock()
+            .expect("memory block store lock poisoned")
+    }
+}
+
+#[async_trait]
+impl BlockStore for MemoryBlockStore {
+    async fn get(&self, cid: Cid) -> Result<Option<Bytes>> {
+        Ok(self.lock().get(&cid).cloned())
+    }
+    async fn put(&self, cid: Cid, blob: Bytes, _links: Vec<Cid>) -> Result<()> {
+        self.lock().insert(cid, blob);
+        Ok(())
+    }
+    async fn remove(&self, cid: Cid) -> Result<Option<u64>> {
+        Ok(self.lock().remove(&cid).map(|blob| blob.len() as u64))
+    }
+    async fn cids(&self) -> Result<Vec<Cid>> {
+        Ok(self.lock().keys().copied().collect())
+    }
+}
+
+/// Blocks stored in a single SQLite database file.
+///
+/// Links are not recorded, they can be recovered by decoding the blocks.
+pub struct SqliteBlockStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteBlockStore {
+    /// Open the database at path, creating it if it does not exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path)
+            .map_err(|err| anyhow!("failed to open {}: {}", path.display(), err))?;
+        Self::init(conn)
+    }
+    fn init(conn: rusqlite::Connection) -> Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blocks (cid BLOB PRIMARY KEY, bytes BLOB NOT NULL)",
+            (),
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.conn.lock().expect("sqlite block store lock poisoned")
+    }
+}
+
+#[async_trait]
+impl BlockStore for SqliteBlockStore {
+    async fn get(&self, cid: Cid) -> Result<Option<Bytes>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare_cached("SELECT bytes FROM blocks WHERE cid = ?1")?;
+        let mut rows = stmt.query([cid.to_bytes()])?;
+        let blob = match rows.next()? {
+            Some(row) => Some(row.get::<_, Vec<u8>>(0)?.into()),
+            None => None,
+        };
+        Ok(blob)
+    }
+    async fn put(&self, cid: Cid, blob: Bytes, _links: Vec<Cid>) -> Result<()> {
+        self.lock()
+            .prepare_cached("INSERT OR IGNORE INTO blocks (cid, bytes) VALUES (?1, ?2)")?
+            .execute((cid.to_bytes(), blob.as_ref()))?;
+        Ok(())
+    }
+    async fn remove(&self, cid: Cid) -> Result<Option<u64>> {
+        let conn = self.lock();
+        let size: Option<u64> = conn
+            .prepare_cached("SELECT length(bytes) FROM blocks WHERE cid = ?1")?
+            .query([cid.to_bytes()])?
+            .next()?
+            .map(|row| row.get(0))
+            .transpose()?;
+        conn.prepare_cached("DELETE FROM blocks WHERE cid = ?1")?
+            .execute([cid.to_bytes()])?;
+        Ok(size)
+    }
+    async fn cids(&self) -> Result<Vec<Cid>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare_cached("SELECT cid FROM blocks ORDER BY cid")?;
+        let cids = stmt
+            .query_map((), |row| row.get::<_, Vec<u8>>(0))?
+            .map(|cid| Ok(Cid::try_from(cid?.as_slice())?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(cids)
+    }
+}
+
+/// Blocks stored in the RocksDB store of an Iroh node.
+///
+/// While the Iroh node runs blocks are read and written through its API, which cannot remove
+/// blocks. Without a running node the database is opened directly, blocks can then be read and
+/// removed but not stored.
+pub enum RocksBlockStore {
+    /// Store of a running Iroh node.
+    Iroh {
+        /// API of the Iroh node.
+        api: Api,
+        /// Path of the RocksDB database.
+        path: PathBuf,
+    },
+    /// Database opened directly, the Iroh node must not be running.
+    Direct(rocksdb::DB),
+}
+
+// Column family of the Iroh store mapping the multihash and codec of each block to its id.
+const IROH_ID_CF: &str = "id-v0";
+// Column family of the Iroh store mapping the id of each block to its bytes.
+const IROH_BLOBS_CF: &str = "blobs-v0";
+// Codec and multihash code of the blocks addressed by CIDv0.
+const DAG_PB: u64 = 0x70;
+const SHA2_256: u64 = 0x12;
+
+impl RocksBlockStore {
+    /// Open the database of an Iroh store that is not in use by a running Iroh node.
+    pub fn open_direct(path: &Path) -> Result<Self> {
+        let cfs = rocksdb::DB::list_cf(&rocksdb::Options::default(), path)?;
+        let db = rocksdb::DB::open_cf(&rocksdb::Options::default(), path, cfs).map_err(|err| {
+            anyhow!(
+                "failed to open {}, is the daemon running? {}",
+                path.display(),
+                err
+            )
+        })?;
+        Ok(Self::Direct(db))
+    }
+}
+
+#[async_trait]
+impl BlockStore for RocksBlockStore {
+    async fn get(&self, cid: Cid) -> Result<Option<Bytes>> {
+        match self {
+            Self::Iroh { api, .. } => api.client().try_store()?.get(cid).await,
+            Self::Direct(db) => Ok(iroh_blob(db, &cid)?.map(|(_, blob)| blob)),
+        }
+    }
+    async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<()> {
+        match self {
+            Self::Iroh { api, .. } => api.client().try_store()?.put(cid, blob, links).await,
+            Self::Direct(_) => {
+                bail!("storing blocks in the rocksdb backend requires the Iroh node")
+            }
+        }
+    }
+    async fn remove(&self, cid: Cid) -> Result<Option<u64>> {
+        match self {
+            Self::Iroh { .. } => bail!(
+                "removing blocks from the rocksdb backend requires stopping the daemon, \
+                use `ceramic-one gc` instead"
+            ),
+            Self::Direct(db) => {
+                // Only the bytes are removed, the id and links of the block remain so the
+                // references of other blocks stay valid,
+                // the same state Iroh records for linked blocks it has not fetched.
+                let (id, blob) = match iroh_blob(db, &cid)? {
+                    Some(block) => block,
+                    None => return Ok(None),
+                };
+                db.delete_cf(iroh_cf(db, IROH_BLOBS_CF)?, id.to_be_bytes())?;
+                Ok(Some(blob.len() as u64))
+            }
+        }
+    }
+    async fn cids(&self) -> Result<Vec<Cid>> {
+        match self {
+            Self::Iroh { path, .. } => {
+                // The Iroh store does not expose a way to iterate its blocks, instead the
+                // database is opened read only, which is possible while the Iroh store holds it
+                // open.
+                let db = rocksdb::DB::open_cf_for_read_only(
+                    &rocksdb::Options::default(),
+                    path,
+                    [IROH_ID_CF, IROH_BLOBS_CF],
+                    false,
+                )?;
+                iroh_cids(&db)
+            }
+            Self::Direct(db) => iroh_cids(db),
+        }
+    }
+}
+
+fn iroh_cf<'a>(db: &'a rocksdb::DB, name: &str) -> Result<&'a rocksdb::ColumnFamily> {
+    db.cf_handle(name)
+        .ok_or_else(|| anyhow!("missing column family {}", name))
+}
+
+// List the Cids of the Iroh store that have their bytes stored,
+// Iroh also assigns ids to blocks that are only known as links.
+fn iroh_cids(db: &rocksdb::DB) -> Result<Vec<Cid>> {
+    let blobs = iroh_cf(db, IROH_BLOBS_CF)?;
+    let mut cids = Vec::new();
+    for entry in db.iterator_cf(iroh_cf(db, IROH_ID_CF)?, rocksdb::IteratorMode::Start) {
+        let (key, id) = entry?;
+        if db.get_pinned_cf(blobs, &id)?.is_some() {
+            cids.push(iroh_id_key_cid(&key)?);
+        }
+    }
+    Ok(cids)
+}
+
+// Read the id and bytes of a block from the Iroh store.
+fn iroh_blob(db: &rocksdb::DB, cid: &Cid) -> Result<Option<(u64, Bytes)>> {
+    let id = match db.get_pinned_cf(iroh_cf(db, IROH_ID_CF)?, iroh_id_key(cid))? {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let id = u64::from_be_bytes(
+        id.as_ref()
+            .try_into()
+            .map_err(|_| anyhow!("malformed Iroh store id {}", hex::encode(&id)))?,
+    );
+    Ok(db
+        .get_cf(iroh_cf(db, IROH_BLOBS_CF)?, id.to_be_bytes())?
+        .map(|blob| (id, blob.into())))
+}
+
+// Encode the key of the id column family of the Iroh store,
+// the multihash of the block followed by the big endian codec of its Cid.
+fn iroh_id_key(cid: &Cid) -> Vec<u8> {
+    let mut key = cid.hash().to_bytes();
+    key.extend(cid.codec().to_be_bytes());
+    key
+}
+
+// Decode the key of the id column family of the Iroh store.
+// The key does not record the version of the Cid, so dag-pb blocks hashed with sha2-256 are
+// reported by their CIDv0 as Kubo does and other blocks by their CIDv1.
+fn iroh_id_key_cid(key: &[u8]) -> Result<Cid> {
+    if key.len() < 8 {
+        bail!("malformed Iroh store key {}", hex::encode(key));
+    }
+    let (hash, codec) = key.split_at(key.len() - 8);
+    let codec = u64::from_be_bytes(codec.try_into()?);
+    let hash = Multihash::from_bytes(hash)?;
+    if codec == DAG_PB && hash.code() == SHA2_256 && hash.size() == 32 {
+        return Ok(Cid::new_v0(hash)?);
+    }
+    Ok(Cid::new_v1(codec, hash))
+}
+
+/// Copy every block from one store to another, verifying each block against its Cid.
+///
+/// Returns the number of blocks copied and their total size in bytes.
+pub async fn migrate(from: &dyn BlockStore, to: &dyn BlockStore) -> Result<(u64, u64)> {
+    let (mut count, mut bytes) = (0, 0);
+    for cid in from.cids().await? {
+        let blob = from
+            .get(cid)
+            .await?
+            .ok_or_else(|| anyhow!("block {} is listed but missing", cid))?;
+        dag::verify(&cid, &blob).map_err(|err| anyhow!("block {}: {}", cid, err))?;
+        let links = block::links(&cid, &blob).map_err(|err| anyhow!("block {}: {}", cid, err))?;
+        bytes += blob.len() as u64;
+        to.put(cid, blob, links).await?;
+        count += 1;
+    }
+    Ok((count, bytes))
+}
+
+/// IPFS node whose blocks are kept in a block store and whose networking is provided by Iroh.
+///
+/// Blocks missing from the store are fetched from the network when networking is enabled.
+#[derive(Clone)]
+pub struct StoreIpfs {
+    store: Arc<dyn BlockStore>,
+    p2p: Option<Api>,
+}
+
+impl StoreIpfs {
+    /// Construct a node using the store, networking is disabled without an Iroh p2p node.
+    pub fn new(store: Arc<dyn BlockStore>, p2p: Option<Api>) -> Self {
+        Self { store, p2p }
+    }
+    fn p2p(&self) -> Result<&Api, Error> {
+        self.p2p
+            .as_ref()
+            .ok_or_else(|| Error::Internal(anyhow!("networking is disabled")))
+    }
+    async fn block(&self, cid: Cid) -> Result<Bytes, Error> {
+        if let Some(blob) = self.store.get(cid).await.map_err(Error::Internal)? {
+            return Ok(blob);
+        }
+        let blob = self
+            .p2p
+            .as_ref()
+            .ok_or(Error::NotFound)?
+            .get_raw(cid)
+            .await
+            .map_err(Error::Internal)?;
+        let links = block::links(&cid, &blob)?;
+        self.store
+            .put(cid, blob.clone(), links)
+            .await
+            .map_err(Error::Internal)?;
+        Ok(blob)
+    }
+}
+
+#[async_trait]
+impl IpfsDep for StoreIpfs {
+    async fn id(&self) -> Result<PeerInfo, Error> {
+        <Api as IpfsDep>::id(self.p2p()?).await
+    }
+    async fn get(&self, ipfs_path: &IpfsPath) -> Result<(Cid, Ipld), Error> {
+        let cid = ipfs_path
+            .cid()
+            .ok_or_else(|| Error::Invalid(anyhow!("IPFS path does not refer to a CID")))?;
+        dag::traverse(*cid, ipfs_path.tail(), |cid| self.block(cid)).await
+    }
+    async fn block_get(&self, cid: Cid) -> Result<Bytes, Error> {
+        self.block(cid).await
+    }
+    async fn block_size(&self, cid: Cid) -> Result<u64, Error> {
+        Ok(self
+            .store
+            .get(cid)
+            .await
+            .map_err(Error::Internal)?
+            .ok_or(Error::NotFound)?
+            .len() as u64)
+    }
+    async fn blocks(&self) -> Result<Vec<Cid>, Error> {
+        self.store.cids().await.map_err(Error::Internal)
+    }
+    async fn block_rm(&self, cid: Cid) -> Result<u64, Error> {
+        self.store
+            .remove(cid)
+            .await
+            .map_err(Error::Internal)?
+            .ok_or(Error::NotFound)
+    }
+    async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<(), Error> {
+        self.store
+            .put(cid, blob, links)
+            .await
+            .map_err(Error::Internal)
+    }
+    async fn resolve(&self, ipfs_path: &IpfsPath) -> Result<Vec<Cid>, Error> {
+        let cid = ipfs_path
+            .cid()
+            .ok_or_else(|| Error::Invalid(anyhow!("IPFS path does not refer to a CID")))?;
+        // Report the Cid of every block traversed along the path.
+        let traversed = Mutex::new(Vec::new());
+        dag::traverse(*cid, ipfs_path.tail(), |cid| {
+            traversed.lock().expect("traversed lock poisoned").push(cid);
+            self.block(cid)
+        })
+        .await?;
+        Ok(traversed.into_inner().expect("traversed lock poisoned"))
+    }
+    async fn peers(&self, protocols: bool) -> Result<HashMap<PeerId, PeerConnection>, Error> {
+        <Api as IpfsDep>::peers(self.p2p()?, protocols).await
+    }
+    async fn connect(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<(), Error> {
+        <Api as IpfsDep>::connect(self.p2p()?, peer_id, addrs).await
+    }
+    async fn disconnect(&self, peer_id: PeerId) -> Result<(), Error> {
+        <Api as IpfsDep>::disconnect(self.p2p()?, peer_id).await
+    }
+    async fn listeners(&self) -> Result<Vec<Multiaddr>, Error> {
+        <Api as IpfsDep>::listeners(self.p2p()?).await
+    }
+    async fn publish(&self, topic: String, data: Bytes) -> Result<(), Error> {
+        <Api as IpfsDep>::publish(self.p2p()?, topic, data).await
+    }
+    async fn subscribe(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, anyhow::Result<GossipsubEvent>>, Error> {
+        <Api as IpfsDep>::subscribe(self.p2p()?, topic).await
+    }
+    async fn topics(&self) -> Result<Vec<String>, Error> {
+        <Api as IpfsDep>::topics(self.p2p()?).await
+    }
+    async fn topic_peers(&self, topic: Option<String>) -> Result<Vec<PeerId>, Error> {
+        <Api as IpfsDep>::topic_peers(self.p2p()?, topic).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ceramic_kubo_rpc::{
+        pin::{self, PinStore},
+        repo::{gc, Removed},
+        testing::cbor_block,
+    };
+    use futures_util::TryStreamExt;
+    use iroh_embed::{Iroh, IrohBuilder, RocksStoreService};
+    use libipld::{
+        ipld,
+        multihash::{Code, MultihashDigest},
+    };
+
+    use super::*;
+
+    // Start an Iroh node with only a RocksDB store in the directory.
+    async fn iroh(dir: &Path) -> Iroh {
+        let store = RocksStoreService::new(dir.join("store")).await.unwrap();
+        IrohBuilder::new().store(store).build().await.unwrap()
+    }
+
+    fn sqlite() -> SqliteBlockStore {
+        SqliteBlockStore::init(rusqlite::Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    async fn assert_store(store: &dyn BlockStore) {
+        let (leaf, leaf_blob) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (root, root_blob) = cbor_block(&ipld!({"link": leaf}));
+        store.put(leaf, leaf_blob.clone(), vec![]).await.unwrap();
+        store.put(root, root_blob, vec![leaf]).await.unwrap();
+        // Storing a block again is not an error
+        store.put(leaf, leaf_blob.clone(), vec![]).await.unwrap();
+
+        assert_eq!(Some(leaf_blob.clone()), store.get(leaf).await.unwrap());
+        let mut cids = vec![leaf, root];
+        cids.sort();
+        assert_eq!(cids, store.cids().await.unwrap());
+
+        assert_eq!(
+            Some(leaf_blob.len() as u64),
+            store.remove(leaf).await.unwrap()
+        );
+        assert_eq!(None, store.get(leaf).await.unwrap());
+        assert_eq!(None, store.remove(leaf).await.unwrap());
+        assert_eq!(vec![root], store.cids().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        assert_store(&MemoryBlockStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite() {
+        assert_store(&sqlite()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cid, blob) = cbor_block(&ipld!({"persisted": true}));
+        let store = open(Backend::Sqlite, dir.path(), None).unwrap();
+        store.put(cid, blob.clone(), vec![]).await.unwrap();
+        drop(store);
+        let store = open(Backend::Sqlite, dir.path(), None).unwrap();
+        assert_eq!(Some(blob), store.get(cid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let from = MemoryBlockStore::default();
+        let (leaf, leaf_blob) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (root, root_blob) = cbor_block(&ipld!({"link": leaf}));
+        from.put(leaf, leaf_blob.clone(), vec![]).await.unwrap();
+        from.put(root, root_blob.clone(), vec![]).await.unwrap();
+
+        let to = sqlite();
+        assert_eq!(
+            (2, (leaf_blob.len() + root_blob.len()) as u64),
+            migrate(&from, &to).await.unwrap()
+        );
+        assert_eq!(from.cids().await.unwrap(), to.cids().await.unwrap());
+        assert_eq!(Some(root_blob), to.get(root).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_corrupt_block() {
+        let from = MemoryBlockStore::default();
+        let (cid, _) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (_, other_blob) = cbor_block(&ipld!({"x": [4, 5, 6]}));
+        from.put(cid, other_blob, vec![]).await.unwrap();
+
+        let to = MemoryBlockStore::default();
+        let err = migrate(&from, &to).await.unwrap_err();
+        assert_eq!(
+            format!(
+                "block {}: invalid: block data does not match the hash of Cid {}",
+                cid, cid
+            ),
+            err.to_string()
+        );
+        assert!(to.cids().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_store_ipfs() {
+        let ipfs = StoreIpfs::new(Arc::new(MemoryBlockStore::default()), None);
+        let (leaf, leaf_blob) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (root, root_blob) = cbor_block(&ipld!({"link": leaf}));
+        ipfs.put(leaf, leaf_blob, vec![]).await.unwrap();
+        ipfs.put(root, root_blob, vec![leaf]).await.unwrap();
+
+        let path = IpfsPath::from_str(&format!("/ipfs/{}/link/x/1", root)).unwrap();
+        assert_eq!((leaf, Ipld::Integer(2)), ipfs.get(&path).await.unwrap());
+        assert_eq!(vec![root, leaf], ipfs.resolve(&path).await.unwrap());
+
+        // Without networking missing blocks are not found
+        ipfs.block_rm(leaf).await.unwrap();
+        assert!(matches!(ipfs.get(&path).await, Err(Error::NotFound)));
+        assert!(matches!(ipfs.peers(false).await, Err(Error::Internal(_))));
+    }
+
+    #[test]
+    fn test_iroh_id_key() {
+        let cid =
+            Cid::from_str("bafyreidufmzzejc3p7gmh6ivp4fjvca5jfazk57nu6vdkvki4c4vpja724").unwrap();
+        assert_eq!(cid, iroh_id_key_cid(&iroh_id_key(&cid)).unwrap());
+        let v0 = Cid::from_str("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+        assert_eq!(v0, iroh_id_key_cid(&iroh_id_key(&v0)).unwrap());
+        // A dag-pb CIDv1 has the same key, it is reported by its CIDv0
+        let v1 = Cid::new_v1(DAG_PB, *v0.hash());
+        assert_eq!(v0, iroh_id_key_cid(&iroh_id_key(&v1)).unwrap());
+        assert!(iroh_id_key_cid(&[0x12]).is_err());
+    }
+
+    // Read the blocks of a store written by Iroh, through its API and then directly.
+    #[tokio::test]
+    async fn test_rocksdb() {
+        let dir = tempfile::tempdir().unwrap();
+        let (leaf, leaf_blob) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (root, root_blob) = cbor_block(&ipld!({"link": leaf}));
+        // An empty dag-pb node, addressed by its CIDv0
+        let pb = Cid::new_v0(Code::Sha2_256.digest(b"")).unwrap();
+        // Iroh assigns an id to the links of stored blocks that it does not have
+        let (missing, _) = cbor_block(&ipld!({"missing": true}));
+        let mut cids = vec![leaf, root, pb];
+        cids.sort();
+
+        let iroh = iroh(dir.path()).await;
+        let rocks = open(Backend::Rocksdb, dir.path(), Some(iroh.api().clone())).unwrap();
+        rocks.put(leaf, leaf_blob.clone(), vec![]).await.unwrap();
+        rocks
+            .put(root, root_blob, vec![leaf, missing])
+            .await
+            .unwrap();
+        rocks.put(pb, Bytes::new(), vec![]).await.unwrap();
+        let mut listed = rocks.cids().await.unwrap();
+        listed.sort();
+        assert_eq!(cids, listed);
+        assert!(rocks.remove(leaf).await.is_err());
+        drop(rocks);
+        iroh.stop().await.unwrap();
+
+        let rocks = open(Backend::Rocksdb, dir.path(), None).unwrap();
+        let mut listed = rocks.cids().await.unwrap();
+        listed.sort();
+        assert_eq!(cids, listed);
+        assert_eq!(Some(Bytes::new()), rocks.get(pb).await.unwrap());
+        assert_eq!(Some(leaf_blob.clone()), rocks.get(leaf).await.unwrap());
+        assert_eq!(
+            Some(leaf_blob.len() as u64),
+            rocks.remove(leaf).await.unwrap()
+        );
+        assert_eq!(None, rocks.get(leaf).await.unwrap());
+        assert_eq!(None, rocks.remove(leaf).await.unwrap());
+        let mut cids = vec![root, pb];
+        cids.sort();
+        let mut listed = rocks.cids().await.unwrap();
+        listed.sort();
+        assert_eq!(cids, listed);
+        assert!(rocks.put(leaf, leaf_blob, vec![]).await.is_err());
+    }
+
+    // Garbage collect a store written by Iroh, then check Iroh still reads and writes it.
+    #[tokio::test]
+    async fn test_rocksdb_gc() {
+        let dir = tempfile::tempdir().unwrap();
+        let (leaf, leaf_blob) = cbor_block(&ipld!({"x": [1, 2, 3]}));
+        let (root, root_blob) = cbor_block(&ipld!({"link": leaf}));
+        let (garbage, garbage_blob) = cbor_block(&ipld!({"garbage": true}));
+
+        let iroh = iroh(dir.path()).await;
+        let rocks = open(Backend::Rocksdb, dir.path(), Some(iroh.api().clone())).unwrap();
+        rocks.put(leaf, leaf_blob.clone(), vec![]).await.unwrap();
+        rocks
+            .put(root, root_blob.clone(), vec![leaf])
+            .await
+            .unwrap();
+        rocks
+            .put(garbage, garbage_blob.clone(), vec![])
+            .await
+            .unwrap();
+        drop(rocks);
+        iroh.stop().await.unwrap();
+
+        let pins = PinStore::memory();
+        let ipfs = StoreIpfs::new(open(Backend::Rocksdb, dir.path(), None).unwrap(), None);
+        pin::add(ipfs.clone(), &pins, root, true).await.unwrap();
+        let removed: Vec<Removed> = gc(ipfs, &pins).await.unwrap().try_collect().await.unwrap();
+        assert_eq!(
+            vec![Removed {
+                cid: garbage,
+                size: garbage_blob.len() as u64
+            }],
+            removed
+        );
+
+        let iroh = iroh(dir.path()).await;
+        let rocks = open(Backend::Rocksdb, dir.path(), Some(iroh.api().clone())).unwrap();
+        assert_eq!(Some(root_blob), rocks.get(root).await.unwrap());
+        assert_eq!(Some(leaf_blob), rocks.get(leaf).await.unwrap());
+        assert_eq!(None, rocks.get(garbage).await.unwrap());
+        // A removed block can be stored again
+        rocks
+            .put(garbage, garbage_blob.clone(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(Some(garbage_blob), rocks.get(garbage).await.unwrap());
+        drop(rocks);
+        iroh.stop().await.unwrap();
+    }
+}